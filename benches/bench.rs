@@ -1,7 +1,10 @@
 use criterion::{criterion_group, criterion_main, Bencher, Criterion};
 
+use hash_based_signatures::merkle_tree::MerkleTree;
 use hash_based_signatures::signature::stateless_merkle::StatelessMerkleSignatureScheme;
 use hash_based_signatures::signature::winternitz::d::D;
+use hash_based_signatures::signature::winternitz::multi_buffer::{hash_chains, Backend};
+use hash_based_signatures::signature::winternitz::WinternitzSignatureScheme;
 use hash_based_signatures::signature::{HashType, SignatureScheme};
 use rand::prelude::*;
 
@@ -18,7 +21,7 @@ fn make_signature_scheme() -> StatelessMerkleSignatureScheme {
 }
 
 fn key_generation(b: &mut Bencher) {
-    b.iter(|| make_signature_scheme())
+    b.iter(make_signature_scheme)
 }
 
 fn signing(b: &mut Bencher) {
@@ -29,6 +32,23 @@ fn signing(b: &mut Bencher) {
     })
 }
 
+fn signing_with_checkpoint_cache(b: &mut Bencher, checkpoint_cache: bool) {
+    // A shallow tree, whose upper layers are a large share of the layers of a signature
+    let mut signature_scheme =
+        StatelessMerkleSignatureScheme::new(get_random_256bits(), 16, 4, D::new(15));
+    if checkpoint_cache {
+        signature_scheme.enable_checkpoint_cache(15, 1).unwrap();
+        // Fill the cache, which holds the 16 schemes of the layer below the root
+        for _ in 0..200 {
+            signature_scheme.sign(get_random_256bits());
+        }
+    }
+    b.iter(|| {
+        let msg = get_random_256bits();
+        signature_scheme.sign(msg);
+    })
+}
+
 fn verification(b: &mut Bencher) {
     let mut signature_scheme = make_signature_scheme();
     let msg = get_random_256bits();
@@ -38,14 +58,96 @@ fn verification(b: &mut Bencher) {
     })
 }
 
+fn winternitz_signing(b: &mut Bencher, d: u64) {
+    b.iter(|| {
+        let mut signature_scheme = WinternitzSignatureScheme::new(get_random_256bits(), D::new(d));
+        signature_scheme.sign(get_random_256bits())
+    })
+}
+
+fn winternitz_signing_only(b: &mut Bencher, checkpoint_interval: Option<u8>) {
+    let seed = get_random_256bits();
+    let d = D::new(255);
+    let mut signature_scheme = match checkpoint_interval {
+        None => WinternitzSignatureScheme::new(seed, d),
+        Some(interval) => WinternitzSignatureScheme::with_checkpoints(seed, d, interval).unwrap(),
+    };
+    b.iter(|| signature_scheme.sign(get_random_256bits()))
+}
+
+fn winternitz_hash_chains(b: &mut Bencher, backend: Backend) {
+    // The chains of a Winternitz key with d = 15
+    let inputs: Vec<HashType> = (0..67).map(|_| get_random_256bits()).collect();
+    let starts = vec![0; inputs.len()];
+    let ends = vec![15; inputs.len()];
+    b.iter(|| hash_chains(backend, &inputs, &starts, &ends))
+}
+
+/// Number of leaves of the Merkle trees in the benchmarks; deliberately not a power of the arity
+const MERKLE_TREE_LEAVES: usize = 10000;
+
+fn merkle_tree_construction(b: &mut Bencher, arity: usize) {
+    let elements: Vec<HashType> = (0..MERKLE_TREE_LEAVES)
+        .map(|_| get_random_256bits())
+        .collect();
+    b.iter(|| MerkleTree::with_arity(&elements, arity))
+}
+
+fn merkle_proof_verification(b: &mut Bencher, arity: usize) {
+    let elements: Vec<HashType> = (0..MERKLE_TREE_LEAVES)
+        .map(|_| get_random_256bits())
+        .collect();
+    let tree = MerkleTree::with_arity(&elements, arity);
+    let proof = tree.get_proof(1234);
+    b.iter(|| proof.verify(*tree.get_root_hash(), &elements[1234]))
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("benches");
     group.sample_size(20);
 
     group.bench_function("key_generation", key_generation);
     group.bench_function("signing", signing);
+    // The upper layers of the tree are regenerated from their checkpoints
+    group.bench_function("signing_depth_4", |b| {
+        signing_with_checkpoint_cache(b, false)
+    });
+    group.bench_function("signing_depth_4_checkpoint_cache", |b| {
+        signing_with_checkpoint_cache(b, true)
+    });
     group.bench_function("verification", verification);
     group.finish();
+
+    // Larger values of d give shorter signatures, but longer hash chains
+    let mut group = c.benchmark_group("winternitz");
+    for d in [3, 7, 15, 31, 255] {
+        group.bench_function(format!("key_generation_and_signing_d{d}"), |b| {
+            winternitz_signing(b, d)
+        });
+    }
+    for backend in Backend::supported() {
+        group.bench_function(format!("hash_chains_{}", backend.name()), |b| {
+            winternitz_hash_chains(b, backend)
+        });
+    }
+    // Checkpoints trade memory for signing time
+    group.bench_function("signing_d255", |b| winternitz_signing_only(b, None));
+    group.bench_function("signing_d255_checkpoints_16", |b| {
+        winternitz_signing_only(b, Some(16))
+    });
+    group.finish();
+
+    // Wider trees have fewer levels, but more hashes per proof
+    let mut group = c.benchmark_group("merkle_tree");
+    for arity in [2, 4, 16] {
+        group.bench_function(format!("construction_{arity}_ary"), |b| {
+            merkle_tree_construction(b, arity)
+        });
+        group.bench_function(format!("proof_verification_{arity}_ary"), |b| {
+            merkle_proof_verification(b, arity)
+        });
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -24,7 +24,7 @@ async function check_signature(js) {
         document.getElementById('invalid-reason').textContent = "The provided signature cannot be parsed. Did you upload the correct signature file?";
         invalidModal.show();
     } else if (result === "invalid_public_key") {
-        document.getElementById('invalid-reason').textContent = "The provided public key can't be parsed. It needs to be exactly 64 characters, encoding a 256-bit hash in hexadecimal.";
+        document.getElementById('invalid-reason').textContent = "The provided public key can't be parsed. It needs to be a 256-bit hash encoded as 64 hexadecimal characters, optionally prefixed by the version of its format (e.g. \"v1:\").";
         invalidModal.show();
     } else if (result === "invalid_signature") {
         document.getElementById('invalid-reason').textContent = "The provided signature can be parsed, but is not valid for the given file and public key.";
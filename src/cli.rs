@@ -1,13 +1,20 @@
-use crate::io::hash_file;
-use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+use crate::io::chunked::{read_chunk, ChunkedFile, ChunkedFileSignature};
+use crate::io::file_set::{FileSet, FileSetIndex, FileSetProof};
+use crate::io::{hash_file, write_private_file};
+use crate::secret::Secret;
+use crate::signature::key_format::{KeyFormat, VersionedPublicKey};
+use crate::signature::stateless_merkle::derivation::MasterKey;
+use crate::signature::stateless_merkle::StatelessMerkleSignature;
+use crate::signature::stateless_merkle::{StatelessMerkleParams, StatelessMerkleSignatureScheme};
 use crate::signature::winternitz::d::D;
-use crate::signature::{HashType, SignatureScheme};
-use crate::utils::{slice_to_hash, string_to_hash};
+use crate::signature::HashType;
+use crate::utils::slice_to_hash;
 use anyhow::{bail, Context, Result};
 use data_encoding::HEXLOWER;
 use rand::RngCore;
 use std::fs;
-use std::path::PathBuf;
+use std::fs::File;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 fn timed<F, T>(f: F) -> (Duration, T)
@@ -20,35 +27,43 @@ where
     (elapsed_time, result)
 }
 
-pub fn keygen(width: usize, depth: usize, d: u64) -> Result<()> {
+pub fn keygen(width: usize, depth: usize, d: u64, compressed: bool, force: bool) -> Result<()> {
     println!();
     println!(" #######################");
     println!("   Generating key");
     println!(" #######################");
     println!();
 
-    let mut seed = [0u8; 32];
+    let mut seed = Secret::new([0u8; 32]);
     let mut rng = rand::thread_rng();
-    rng.fill_bytes(&mut seed);
+    rng.fill_bytes(&mut *seed);
 
-    if width & (width - 1) != 0 {
+    if !width.is_power_of_two() {
         bail!("Width {width} is not a power of 2!")
     }
 
-    let d = D::try_from(d)?;
+    let d = D::try_new(d)?;
+    let format = if compressed {
+        KeyFormat::Compressed
+    } else {
+        KeyFormat::CURRENT
+    };
     let (time, signature_scheme) =
-        timed(move || StatelessMerkleSignatureScheme::new(seed, width, depth, d));
+        timed(|| StatelessMerkleSignatureScheme::try_with_format(*seed, width, depth, d, format));
+    let signature_scheme = signature_scheme?;
     println!("  (Key generation took: {:?})\n", time);
 
     let private_key = signature_scheme.private_key();
-    let public_key = signature_scheme.public_key();
+    let public_key = signature_scheme.versioned_public_key();
 
-    let private_key_json =
-        serde_json::to_string_pretty(&private_key).context("Error serializing private key.")?;
+    let private_key_json = Secret::new(
+        serde_json::to_string_pretty(&private_key).context("Error serializing private key.")?,
+    );
     let output_path = ".private_key.json";
-    fs::write(output_path, private_key_json).context("Could not write private key.")?;
+    write_private_file(Path::new(output_path), &private_key_json, force)
+        .context("Could not write private key. Use --force to overwrite an existing key.")?;
 
-    println!("Public key:       {}", HEXLOWER.encode(&public_key));
+    println!("Public key:       {}", public_key);
     println!("Private key path: {}", output_path);
 
     println!(
@@ -60,40 +75,144 @@ pub fn keygen(width: usize, depth: usize, d: u64) -> Result<()> {
     Ok(())
 }
 
-pub fn sign(path: PathBuf) -> Result<()> {
+pub fn master_keygen(force: bool) -> Result<()> {
     println!();
     println!(" #######################");
-    println!("   Signing File");
+    println!("   Generating master key");
     println!(" #######################");
     println!();
 
+    let mut seed = Secret::new([0u8; 32]);
+    rand::thread_rng().fill_bytes(&mut *seed);
+    let master_key = MasterKey::new(*seed);
+
+    let master_key_json = Secret::new(
+        serde_json::to_string_pretty(&master_key.private_key())
+            .context("Error serializing master key.")?,
+    );
+    let output_path = ".master_key.json";
+    write_private_file(Path::new(output_path), &master_key_json, force)
+        .context("Could not write master key. Use --force to overwrite an existing key.")?;
+
+    println!("Master key path: {}", output_path);
+    println!(
+        "\n\nBack it up and keep it private! Child keys can be derived using the `derive` command."
+    );
+
+    Ok(())
+}
+
+pub fn derive(
+    label: &str,
+    width: usize,
+    depth: usize,
+    d: u64,
+    export: bool,
+    force: bool,
+) -> Result<()> {
+    println!();
+    println!(" #######################");
+    println!("   Deriving child key");
+    println!(" #######################");
+    println!();
+
+    let master_key_json =
+        Secret::new(fs::read_to_string(".master_key.json").context("Error reading master key")?);
+    let master_key = serde_json::from_str(&master_key_json).context("Error parsing master key")?;
+    let master_key =
+        MasterKey::from_private_key(&master_key).context("Error parsing master key")?;
+
+    if !width.is_power_of_two() {
+        bail!("Width {width} is not a power of 2!")
+    }
+
+    let params = StatelessMerkleParams {
+        width,
+        depth,
+        d: D::try_new(d)?,
+    };
+    let (time, signature_scheme) = timed(|| master_key.derive(label, params));
+    let signature_scheme = signature_scheme?;
+    println!("  (Key derivation took: {:?})\n", time);
+
+    println!("Label:            {}", label);
+    println!(
+        "Public key:       {}",
+        signature_scheme.versioned_public_key()
+    );
+
+    if export {
+        let private_key_json = Secret::new(
+            serde_json::to_string_pretty(&signature_scheme.private_key())
+                .context("Error serializing private key.")?,
+        );
+        let output_path = ".private_key.json";
+        write_private_file(Path::new(output_path), &private_key_json, force)
+            .context("Could not write private key. Use --force to overwrite an existing key.")?;
+        println!("Private key path: {}", output_path);
+    }
+
+    Ok(())
+}
+
+/// Loads the signature scheme from `.private_key.json`.
+fn load_signature_scheme() -> Result<StatelessMerkleSignatureScheme> {
     let private_key_json =
-        fs::read_to_string(".private_key.json").context("Error reading private key")?;
+        Secret::new(fs::read_to_string(".private_key.json").context("Error reading private key")?);
     let private_key =
         serde_json::from_str(&private_key_json).context("Error parsing private key")?;
 
-    let file_hash = hash_file(&path)?;
-    let mut signature_scheme = StatelessMerkleSignatureScheme::from_private_key(&private_key)
+    let signature_scheme = StatelessMerkleSignatureScheme::from_private_key(&private_key)
         .context("Error instantiating signature scheme from private key in .private_key.json.")?;
 
-    if string_to_hash(&private_key.public_key) != signature_scheme.public_key() {
+    let public_key: VersionedPublicKey = private_key
+        .public_key
+        .parse()
+        .context("Error parsing public key in .private_key.json")?;
+    if public_key != signature_scheme.versioned_public_key() {
         bail!(
             "The public key referenced in .private_key.json cannot be derived from the private key. \
                 This is probably because of an incompatible implementation change. \
                 Re-run key generation or manually change the public key to {}",
-            HEXLOWER.encode(&signature_scheme.public_key())
+            signature_scheme.versioned_public_key()
         )
     }
 
-    let (time, signature) = timed(|| signature_scheme.sign(slice_to_hash(file_hash.as_ref())));
+    Ok(signature_scheme)
+}
+
+pub fn sign(path: PathBuf, context: &[u8], randomized: bool) -> Result<()> {
+    println!();
+    println!(" #######################");
+    println!("   Signing File");
+    println!(" #######################");
+    println!();
+
+    let file_hash = hash_file(&path)?;
+    let signature_scheme = load_signature_scheme()?;
+
+    let message = slice_to_hash(file_hash.as_ref());
+    let (time, signature) = timed(|| {
+        if randomized {
+            let mut opt_rand = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut opt_rand);
+            signature_scheme.sign_randomized(message, context, opt_rand)
+        } else {
+            signature_scheme.sign_with_context(message, context)
+        }
+    });
+    let signature = signature?;
     println!("  (Signing took: {:?})\n", time);
 
     println!("File Path:      {}", path.display());
     println!("Hash:           {}", HEXLOWER.encode(file_hash.as_ref()));
     println!(
         "Public key:     {}",
-        HEXLOWER.encode(&signature_scheme.public_key())
+        signature_scheme.versioned_public_key()
     );
+    if !context.is_empty() {
+        println!("Context:        {}", String::from_utf8_lossy(context));
+    }
 
     let output_path = format!("{}.signature", path.display());
     println!("Signature path: {}", output_path);
@@ -105,7 +224,13 @@ pub fn sign(path: PathBuf) -> Result<()> {
     Ok(())
 }
 
-pub fn verify(file_path: PathBuf, signature_path: PathBuf, public_key: HashType) -> Result<bool> {
+pub fn verify(
+    file_path: PathBuf,
+    signature_path: PathBuf,
+    public_key: VersionedPublicKey,
+    context: &[u8],
+    randomized: bool,
+) -> Result<bool> {
     println!();
     println!(" #######################");
     println!("   Verifying file");
@@ -123,17 +248,294 @@ pub fn verify(file_path: PathBuf, signature_path: PathBuf, public_key: HashType)
     let signature = rmp_serde::from_slice(&signature_bytes)
         .with_context(|| format!("Signature at {:?} is malformed.", &signature_path))?;
 
+    let message = slice_to_hash(file_hash.as_ref());
     let (time, verifies) = timed(|| {
-        StatelessMerkleSignatureScheme::verify(
-            public_key,
-            slice_to_hash(file_hash.as_ref()),
-            &signature,
-        )
+        if randomized {
+            StatelessMerkleSignatureScheme::verify_randomized_with_format(
+                &public_key.public_key,
+                &message,
+                context,
+                &signature,
+                public_key.format,
+            )
+        } else {
+            StatelessMerkleSignatureScheme::verify_versioned(
+                &public_key,
+                &message,
+                context,
+                &signature,
+            )
+        }
     });
     println!("  (Verification took: {:?})\n", time);
 
     println!("File Path:      {}", file_path.display());
     println!("Signature Path: {}", signature_path.display());
+    if !context.is_empty() {
+        println!("Context:        {}", String::from_utf8_lossy(context));
+    }
+    println!("Valid:          {}", verifies);
+
+    Ok(verifies)
+}
+
+pub fn sign_chunked(path: PathBuf, chunk_size: usize, context: &[u8]) -> Result<()> {
+    println!();
+    println!(" #######################");
+    println!("   Signing File in Chunks");
+    println!(" #######################");
+    println!();
+
+    let signature_scheme = load_signature_scheme()?;
+    let (time, chunked_file) = timed(|| ChunkedFile::from_path(&path, chunk_size));
+    let chunked_file = chunked_file?;
+    println!("  (Hashing took: {:?})\n", time);
+
+    let manifest = chunked_file.manifest();
+    let (time, signed_manifest) = timed(|| manifest.sign(&signature_scheme, context));
+    let signed_manifest = signed_manifest?;
+    println!("  (Signing took: {:?})\n", time);
+
+    println!("File Path:      {}", path.display());
+    println!("Chunk size:     {}", manifest.chunk_size);
+    println!("Chunks:         {}", manifest.chunk_count());
+    println!("Root hash:      {}", HEXLOWER.encode(&manifest.root_hash));
+    println!(
+        "Public key:     {}",
+        signature_scheme.versioned_public_key()
+    );
+    if !context.is_empty() {
+        println!("Context:        {}", String::from_utf8_lossy(context));
+    }
+
+    let output_path = format!("{}.chunked-signature", path.display());
+    println!("Signature path: {}", output_path);
+
+    let signature = ChunkedFileSignature {
+        signed_manifest,
+        proofs: chunked_file.chunk_proofs(),
+    };
+    let signature_bytes = rmp_serde::to_vec(&signature).context("Error serializing signature")?;
+    fs::write(&output_path, &signature_bytes)
+        .with_context(|| format!("Could not write signature to {:?}", output_path))?;
+
+    Ok(())
+}
+
+pub fn verify_chunked(
+    file_path: PathBuf,
+    signature_path: PathBuf,
+    public_key: VersionedPublicKey,
+    context: &[u8],
+) -> Result<bool> {
+    println!();
+    println!(" #######################");
+    println!("   Verifying file in chunks");
+    println!(" #######################");
+    println!();
+
+    let signature_bytes = fs::read(&signature_path).with_context(|| {
+        format!(
+            "Cannot read signature file at {:?}. Does it exist?",
+            &signature_path
+        )
+    })?;
+    let signature: ChunkedFileSignature = rmp_serde::from_slice(&signature_bytes)
+        .with_context(|| format!("Signature at {:?} is malformed.", &signature_path))?;
+    let manifest = signature.signed_manifest.manifest;
+
+    println!("File Path:      {}", file_path.display());
+    println!("Signature Path: {}", signature_path.display());
+    if !context.is_empty() {
+        println!("Context:        {}", String::from_utf8_lossy(context));
+    }
+
+    if !signature.signed_manifest.verify(&public_key, context) {
+        println!("Valid:          false (invalid manifest signature)");
+        return Ok(false);
+    }
+
+    // Verify the chunks one by one, as they are read
+    let mut reader = File::open(&file_path)
+        .with_context(|| format!("Failed to open file at {:?}. Does it exist?", file_path))?;
+    let mut buffer = Vec::with_capacity(manifest.chunk_size);
+    let mut invalid_chunks = Vec::new();
+    let (time, chunk_count) = timed(|| -> Result<usize> {
+        let mut index = 0;
+        // An empty file still has one (empty) chunk
+        while read_chunk(&mut reader, manifest.chunk_size, &mut buffer)? || index == 0 {
+            let verifies = signature
+                .proofs
+                .get(index)
+                .is_some_and(|proof| manifest.verify_chunk(index, &buffer, proof));
+            if !verifies {
+                invalid_chunks.push(index);
+            }
+            index += 1;
+        }
+        Ok(index)
+    });
+    let chunk_count = chunk_count?;
+    println!("  (Verification took: {:?})\n", time);
+
+    let verifies = invalid_chunks.is_empty() && chunk_count == manifest.chunk_count();
+    println!("Chunks:         {}", chunk_count);
+    if !invalid_chunks.is_empty() {
+        println!("Invalid chunks: {:?}", invalid_chunks);
+    }
+    if chunk_count != manifest.chunk_count() {
+        println!("Expected:       {} chunks", manifest.chunk_count());
+    }
+    println!("Valid:          {}", verifies);
+
+    Ok(verifies)
+}
+
+pub fn tree_build(paths: &[PathBuf], index_path: &Path, sign: bool, context: &[u8]) -> Result<()> {
+    println!();
+    println!(" #######################");
+    println!("   Building file tree");
+    println!(" #######################");
+    println!();
+
+    let (time, file_set) = timed(|| FileSet::from_paths(paths));
+    let file_set = file_set?;
+    println!("  (Hashing took: {:?})\n", time);
+
+    let index_json =
+        serde_json::to_string_pretty(&file_set.index()).context("Error serializing index.")?;
+    fs::write(index_path, index_json)
+        .with_context(|| format!("Could not write index to {:?}", index_path))?;
+
+    println!("Files:          {}", file_set.len());
+    println!("Root hash:      {}", HEXLOWER.encode(&file_set.root_hash()));
+    println!("Index path:     {}", index_path.display());
+
+    if sign {
+        let signature_scheme = load_signature_scheme()?;
+        let (time, signature) = timed(|| {
+            FileSet::sign_root(
+                file_set.root_hash(),
+                file_set.len(),
+                &signature_scheme,
+                context,
+            )
+        });
+        let signature = signature?;
+        println!("  (Signing took: {:?})\n", time);
+
+        println!(
+            "Public key:     {}",
+            signature_scheme.versioned_public_key()
+        );
+        if !context.is_empty() {
+            println!("Context:        {}", String::from_utf8_lossy(context));
+        }
+
+        let output_path = format!("{}.signature", index_path.display());
+        println!("Signature path: {}", output_path);
+
+        let signature_bytes =
+            rmp_serde::to_vec(&signature).context("Error serializing signature")?;
+        fs::write(&output_path, &signature_bytes)
+            .with_context(|| format!("Could not write signature to {:?}", output_path))?;
+    }
+
+    Ok(())
+}
+
+pub fn tree_prove(path: PathBuf, index_path: &Path) -> Result<()> {
+    println!();
+    println!(" #######################");
+    println!("   Proving membership");
+    println!(" #######################");
+    println!();
+
+    let index_json = fs::read_to_string(index_path)
+        .with_context(|| format!("Error reading index at {:?}", index_path))?;
+    let index: FileSetIndex = serde_json::from_str(&index_json).context("Error parsing index")?;
+    let file_set = FileSet::from_index(&index)?;
+
+    let file_hash = slice_to_hash(hash_file(&path)?.as_ref());
+    let proof = file_set.proof(&path)?;
+    if !proof.verify(file_set.root_hash(), &file_hash) {
+        bail!(
+            "The file at {:?} has changed since the tree was built. Re-run `tree build`.",
+            path
+        )
+    }
+
+    println!("File Path:      {}", path.display());
+    println!("Hash:           {}", HEXLOWER.encode(&file_hash));
+    println!("Root hash:      {}", HEXLOWER.encode(&file_set.root_hash()));
+    println!("Files:          {}", file_set.len());
+
+    let output_path = format!("{}.proof", path.display());
+    println!("Proof path:     {}", output_path);
+
+    let proof_bytes = rmp_serde::to_vec(&proof).context("Error serializing proof")?;
+    fs::write(&output_path, &proof_bytes)
+        .with_context(|| format!("Could not write proof to {:?}", output_path))?;
+
+    Ok(())
+}
+
+/// Verifies the membership proof of a file and, if a `signature_path` and `public_key`
+/// are given, the signature of the root hash and file count created by `tree_build()`.
+///
+/// The file count is taken from the proof, so it is only authenticated by the signature.
+pub fn tree_verify_proof(
+    root_hash: HashType,
+    file_path: PathBuf,
+    proof_path: PathBuf,
+    signature: Option<(PathBuf, VersionedPublicKey)>,
+    context: &[u8],
+) -> Result<bool> {
+    println!();
+    println!(" #######################");
+    println!("   Verifying proof");
+    println!(" #######################");
+    println!();
+
+    let file_hash = slice_to_hash(hash_file(&file_path)?.as_ref());
+
+    let proof_bytes = fs::read(&proof_path)
+        .with_context(|| format!("Cannot read proof at {:?}. Does it exist?", &proof_path))?;
+    let proof: FileSetProof = rmp_serde::from_slice(&proof_bytes)
+        .with_context(|| format!("Proof at {:?} is malformed.", &proof_path))?;
+
+    println!("File Path:      {}", file_path.display());
+    println!("Proof Path:     {}", proof_path.display());
+    println!("Root hash:      {}", HEXLOWER.encode(&root_hash));
+    println!("Files:          {}", proof.file_count);
+
+    if let Some((signature_path, public_key)) = signature {
+        let signature_bytes = fs::read(&signature_path).with_context(|| {
+            format!(
+                "Cannot read signature file at {:?}. Does it exist?",
+                &signature_path
+            )
+        })?;
+        let signature: StatelessMerkleSignature = rmp_serde::from_slice(&signature_bytes)
+            .with_context(|| format!("Signature at {:?} is malformed.", &signature_path))?;
+
+        println!("Signature Path: {}", signature_path.display());
+        if !context.is_empty() {
+            println!("Context:        {}", String::from_utf8_lossy(context));
+        }
+        if !FileSet::verify_root(
+            root_hash,
+            proof.file_count,
+            &public_key,
+            context,
+            &signature,
+        ) {
+            println!("Valid:          false (invalid root signature)");
+            return Ok(false);
+        }
+    }
+
+    let verifies = proof.verify(root_hash, &file_hash);
     println!("Valid:          {}", verifies);
 
     Ok(verifies)
@@ -0,0 +1,67 @@
+use thiserror::Error;
+
+/// Errors returned by the fallible (`try_*`) functions of this crate.
+///
+/// Their panicking counterparts (e.g. `MerkleTree::new()`) panic with the same message.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    #[error("Number of elements needs to be a power of 2, got {0}")]
+    NotAPowerOfTwo(usize),
+
+    #[error("Merkle tree needs at least one element")]
+    EmptyTree,
+
+    #[error("Arity needs to be at least 2, got {0}")]
+    InvalidArity(usize),
+
+    #[error("Invalid tree size {size}, expected at most {max_size}")]
+    InvalidTreeSize { size: usize, max_size: usize },
+
+    #[error("Index {index} is out of range for {size} elements")]
+    IndexOutOfRange { index: usize, size: usize },
+
+    #[error("Depth needs to be at least 1, got {0}")]
+    InvalidDepth(usize),
+
+    #[error("d is not of the form 2^k - 1 for 1 <= k <= 8! Try e.g. 1, 3, 7, 15, 31, or 255.")]
+    InvalidD(u64),
+
+    #[error("Message length needs to be a multiple of 8 bits between 8 and 512, got {0}")]
+    InvalidMessageBits(usize),
+
+    #[error("Expected a message of {expected} bits, got {length}")]
+    InvalidMessageLength { length: usize, expected: usize },
+
+    #[error("Expected 32 bytes, got {0}")]
+    InvalidHashLength(usize),
+
+    #[error("Unknown public key version {0:?}")]
+    UnknownKeyVersion(String),
+
+    #[error("Compressed public keys require the canonical leaf encoding")]
+    UnsupportedKeyFormat,
+
+    #[error("Invalid hex encoding: {0}")]
+    InvalidHex(#[from] data_encoding::DecodeError),
+
+    #[error("Context string must be at most 255 bytes, got {0}")]
+    ContextTooLong(usize),
+
+    #[error("Invalid derivation label {0:?}: segments separated by '/' must not be empty")]
+    InvalidLabel(String),
+
+    #[error("One-time signature has been used to sign more than one message!")]
+    OneTimeKeyReuse,
+
+    #[error("Checkpoint interval needs to be between 1 and d, got {0}")]
+    InvalidCheckpointInterval(u8),
+
+    #[error("Checkpoints belong to a different key")]
+    CheckpointMismatch,
+
+    #[error("All {0} one-time keys have been used")]
+    KeyExhausted(usize),
+
+    #[error("Invalid traversal state: {0}")]
+    InvalidTraversalState(&'static str),
+}
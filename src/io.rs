@@ -1,8 +1,11 @@
+pub mod chunked;
+pub mod file_set;
+
 use anyhow::Context as _;
 use anyhow::Result;
 use ring::digest::{Context, Digest, SHA256};
-use std::fs::File;
-use std::io::Read;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
 use std::path::Path;
 
 pub fn hash_file(path: &Path) -> Result<Digest> {
@@ -23,3 +26,71 @@ pub fn hash_file(path: &Path) -> Result<Digest> {
 
     Ok(context.finish())
 }
+
+/// Writes secret key material to `path`.
+///
+/// Fails if the file already exists, unless `overwrite` is set, so that keys are not
+/// replaced by accident.
+/// On Unix, the file is only readable and writable by the owner (mode 0600),
+/// even if it existed before.
+pub fn write_private_file(path: &Path, contents: &str, overwrite: bool) -> Result<()> {
+    let mut options = OpenOptions::new();
+    if overwrite {
+        options.write(true).create(true).truncate(true);
+    } else {
+        options.write(true).create_new(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path).with_context(|| {
+        if path.exists() && !overwrite {
+            format!("File at {:?} already exists.", path)
+        } else {
+            format!("Failed to create file at {:?}.", path)
+        }
+    })?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to set permissions of {:?}.", path))?;
+    }
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("Failed to write file at {:?}.", path))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::write_private_file;
+    use std::fs;
+
+    #[test]
+    fn test_write_private_file() {
+        let path = std::env::temp_dir().join(format!("private_file_{}", std::process::id()));
+        fs::write(&path, "old contents, which are much longer").unwrap();
+
+        // Existing files are only replaced if asked to
+        assert!(write_private_file(&path, "secret", false).is_err());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "old contents, which are much longer"
+        );
+        write_private_file(&path, "secret", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "secret");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+}
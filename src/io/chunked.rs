@@ -0,0 +1,342 @@
+use crate::error::Error;
+use crate::merkle_tree::{MerkleProof, MerkleTree, TreeShape};
+use crate::signature::key_format::VersionedPublicKey;
+use crate::signature::stateless_merkle::{
+    protocol_context, StatelessMerkleSignature, StatelessMerkleSignatureScheme,
+};
+use crate::signature::HashType;
+use crate::utils::hash;
+use anyhow::{bail, Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Context string used to sign chunk manifests, see `ChunkManifest::sign()`.
+pub const CHUNK_MANIFEST_CONTEXT: &[u8] = b"hash-based-signatures chunk manifest";
+
+/// Default size of the chunks a file is split into (1 MiB).
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Reads the next chunk of at most `chunk_size` bytes into `buffer`, returning `false` at the end.
+pub fn read_chunk<R: Read>(
+    reader: &mut R,
+    chunk_size: usize,
+    buffer: &mut Vec<u8>,
+) -> Result<bool> {
+    buffer.clear();
+    reader
+        .take(chunk_size as u64)
+        .read_to_end(buffer)
+        .context("Cannot read chunk.")?;
+    Ok(!buffer.is_empty())
+}
+
+/// A file split into chunks of a fixed size, with a `MerkleTree` over the chunk hashes.
+///
+/// Signing the root hash (see `ChunkManifest`) allows verifying each chunk independently,
+/// using its Merkle proof, e.g. while downloading the file or when reading parts of it.
+/// An empty file consists of a single, empty chunk.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::io::chunked::ChunkedFile;
+///
+/// let data = vec![42u8; 10_000];
+/// let chunked_file = ChunkedFile::from_reader(&mut data.as_slice(), 4096).unwrap();
+/// let manifest = chunked_file.manifest();
+/// assert_eq!(manifest.chunk_count(), 3);
+///
+/// let proof = chunked_file.chunk_proof(2).unwrap();
+/// assert!(manifest.verify_chunk(2, &data[8192..], &proof));
+/// ```
+pub struct ChunkedFile {
+    chunk_size: usize,
+    file_size: u64,
+    tree: MerkleTree<HashType>,
+}
+
+/// Describes a chunked file by its size, its chunk size and the root hash of its chunks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkManifest {
+    pub chunk_size: usize,
+    pub file_size: u64,
+    pub root_hash: HashType,
+}
+
+/// A chunk manifest with its signature.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignedChunkManifest {
+    pub manifest: ChunkManifest,
+    pub signature: StatelessMerkleSignature,
+}
+
+/// A signed manifest together with the proofs of all chunks, e.g. to be stored next to the file.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChunkedFileSignature {
+    pub signed_manifest: SignedChunkManifest,
+    pub proofs: Vec<MerkleProof<HashType>>,
+}
+
+impl ChunkedFile {
+    /// Splits the file at `path` into chunks of `chunk_size` bytes.
+    pub fn from_path(path: &Path, chunk_size: usize) -> Result<Self> {
+        let mut reader = File::open(path)
+            .with_context(|| format!("Failed to open file at {:?}. Does it exist?", path))?;
+        Self::from_reader(&mut reader, chunk_size)
+            .with_context(|| format!("Cannot read file at {:?}.", path))
+    }
+
+    /// Splits everything read from `reader` into chunks of `chunk_size` bytes.
+    pub fn from_reader<R: Read>(reader: &mut R, chunk_size: usize) -> Result<Self> {
+        if chunk_size == 0 {
+            bail!("Chunk size must not be 0!")
+        }
+
+        let mut chunk_hashes = Vec::new();
+        let mut file_size = 0;
+        let mut buffer = Vec::with_capacity(chunk_size);
+        while read_chunk(reader, chunk_size, &mut buffer)? {
+            chunk_hashes.push(hash(&buffer));
+            file_size += buffer.len() as u64;
+        }
+        if chunk_hashes.is_empty() {
+            chunk_hashes.push(hash(&[]));
+        }
+
+        Ok(Self {
+            chunk_size,
+            file_size,
+            tree: MerkleTree::with_arity(&chunk_hashes, 2),
+        })
+    }
+
+    /// The manifest of the file, which should be signed.
+    pub fn manifest(&self) -> ChunkManifest {
+        ChunkManifest {
+            chunk_size: self.chunk_size,
+            file_size: self.file_size,
+            root_hash: *self.tree.get_root_hash(),
+        }
+    }
+
+    /// The Merkle proof of the chunk at `index`.
+    pub fn chunk_proof(&self, index: usize) -> Result<MerkleProof<HashType>, Error> {
+        self.tree.try_get_proof(index)
+    }
+
+    /// The Merkle proofs of all chunks.
+    pub fn chunk_proofs(&self) -> Vec<MerkleProof<HashType>> {
+        (0..self.tree.shape().leaf_count)
+            .map(|index| self.tree.get_proof(index))
+            .collect()
+    }
+}
+
+impl ChunkManifest {
+    /// The number of chunks of the file.
+    ///
+    /// A manifest with a chunk size of 0 can't be created by `ChunkedFile`, but could still
+    /// be deserialized. It has no chunks, so none of them verify.
+    pub fn chunk_count(&self) -> usize {
+        if self.chunk_size == 0 {
+            0
+        } else if self.file_size == 0 {
+            1
+        } else {
+            self.file_size.div_ceil(self.chunk_size as u64) as usize
+        }
+    }
+
+    /// The expected length of the chunk at `index`: the last chunk may be shorter.
+    pub fn chunk_length(&self, index: usize) -> Option<usize> {
+        if self.chunk_size == 0 || index >= self.chunk_count() {
+            return None;
+        }
+        let offset = index as u64 * self.chunk_size as u64;
+        Some((self.file_size - offset).min(self.chunk_size as u64) as usize)
+    }
+
+    /// Verifies that `chunk` is the chunk at `index` of the file described by the manifest.
+    ///
+    /// The shape of the tree is taken from the manifest, not from the proof.
+    pub fn verify_chunk(&self, index: usize, chunk: &[u8], proof: &MerkleProof<HashType>) -> bool {
+        if self.chunk_length(index) != Some(chunk.len()) || proof.index != index {
+            return false;
+        }
+        let shape = TreeShape {
+            leaf_count: self.chunk_count(),
+            arity: 2,
+        };
+        proof.verify_with_shape(self.root_hash, shape, &hash(chunk))
+    }
+
+    /// The message that is signed: the SHA256 hash of the root hash, followed by
+    /// the 8-byte big-endian file size and chunk size.
+    fn message(&self) -> HashType {
+        hash(
+            &[
+                self.root_hash.as_slice(),
+                &self.file_size.to_be_bytes(),
+                &(self.chunk_size as u64).to_be_bytes(),
+            ]
+            .concat(),
+        )
+    }
+
+    /// Signs the manifest within the given `context`, which is combined with
+    /// `CHUNK_MANIFEST_CONTEXT` (see `protocol_context()`).
+    ///
+    /// Fails if the combined context is longer than `MAX_CONTEXT_LENGTH` bytes.
+    pub fn sign(
+        &self,
+        signature_scheme: &StatelessMerkleSignatureScheme,
+        context: &[u8],
+    ) -> Result<SignedChunkManifest, Error> {
+        let context = protocol_context(CHUNK_MANIFEST_CONTEXT, context);
+        Ok(SignedChunkManifest {
+            manifest: *self,
+            signature: signature_scheme.sign_with_context(self.message(), &context)?,
+        })
+    }
+}
+
+impl SignedChunkManifest {
+    /// Verifies the signature of the manifest, for a key in the format of `public_key`.
+    pub fn verify(&self, public_key: &VersionedPublicKey, context: &[u8]) -> bool {
+        StatelessMerkleSignatureScheme::verify_versioned(
+            public_key,
+            &self.manifest.message(),
+            &protocol_context(CHUNK_MANIFEST_CONTEXT, context),
+            &self.signature,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::chunked::ChunkedFile;
+    use crate::merkle_tree::encoding::LeafEncoding;
+    use crate::signature::key_format::{KeyFormat, VersionedPublicKey};
+    use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+    use crate::signature::winternitz::d::D;
+    use crate::utils::hash;
+
+    fn data(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_all_chunks_verify() {
+        for size in [0, 1, 99, 100, 101, 400, 1234] {
+            let data = data(size);
+            let chunked_file = ChunkedFile::from_reader(&mut data.as_slice(), 100).unwrap();
+            let manifest = chunked_file.manifest();
+            assert_eq!(manifest.file_size, size as u64);
+
+            let proofs = chunked_file.chunk_proofs();
+            assert_eq!(proofs.len(), manifest.chunk_count());
+            let mut chunks: Vec<&[u8]> = data.chunks(100).collect();
+            if chunks.is_empty() {
+                chunks.push(&[]);
+            }
+            for (index, (chunk, proof)) in chunks.iter().zip(&proofs).enumerate() {
+                assert!(manifest.verify_chunk(index, chunk, proof));
+                assert!(!manifest.verify_chunk(index, &hash(chunk), proof));
+                assert!(!manifest.verify_chunk(index + 1, chunk, proof));
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_chunks() {
+        let data = data(1234);
+        let chunked_file = ChunkedFile::from_reader(&mut data.as_slice(), 100).unwrap();
+        let manifest = chunked_file.manifest();
+        let proof = chunked_file.chunk_proof(3).unwrap();
+        assert!(manifest.verify_chunk(3, &data[300..400], &proof));
+
+        let mut modified = data[300..400].to_vec();
+        modified[50] ^= 1;
+        assert!(!manifest.verify_chunk(3, &modified, &proof));
+        assert!(!manifest.verify_chunk(3, &data[300..399], &proof));
+        assert!(!manifest.verify_chunk(4, &data[400..500], &proof));
+
+        // The tree shape can't be changed by the proof
+        let mut wrong_shape = proof.clone();
+        wrong_shape.shape = None;
+        assert!(manifest.verify_chunk(3, &data[300..400], &wrong_shape));
+        // Sizes that lead to trees of the same depth are only detected by the signature,
+        // see `test_signed_manifest()`
+        let mut wrong_size = manifest;
+        wrong_size.file_size = 3000;
+        assert!(!wrong_size.verify_chunk(3, &data[300..400], &proof));
+
+        assert!(chunked_file.chunk_proof(13).is_err());
+        assert!(ChunkedFile::from_reader(&mut data.as_slice(), 0).is_err());
+
+        let mut zero_chunk_size = manifest;
+        zero_chunk_size.chunk_size = 0;
+        assert_eq!(zero_chunk_size.chunk_count(), 0);
+        assert!(!zero_chunk_size.verify_chunk(0, &[], &proof));
+    }
+
+    #[test]
+    fn test_signed_manifest() {
+        let data = data(1234);
+        let chunked_file = ChunkedFile::from_reader(&mut data.as_slice(), 100).unwrap();
+        let signature_scheme = StatelessMerkleSignatureScheme::new([0u8; 32], 16, 5, D::new(255));
+        let signed_manifest = chunked_file
+            .manifest()
+            .sign(&signature_scheme, b"images")
+            .unwrap();
+
+        let public_key = signature_scheme.versioned_public_key();
+        assert!(signed_manifest.verify(&public_key, b"images"));
+        assert!(!signed_manifest.verify(&public_key, b""));
+
+        let mut wrong_file_size = signed_manifest.clone();
+        wrong_file_size.manifest.file_size = 1600;
+        assert!(!wrong_file_size.verify(&public_key, b"images"));
+
+        let mut wrong_chunk_size = signed_manifest.clone();
+        wrong_chunk_size.manifest.chunk_size = 200;
+        assert!(!wrong_chunk_size.verify(&public_key, b"images"));
+
+        // The signature can't be used as a signature of a file
+        for context in [&b""[..], b"images"] {
+            assert!(!StatelessMerkleSignatureScheme::verify_with_context(
+                &public_key.public_key,
+                &signed_manifest.manifest.message(),
+                context,
+                &signed_manifest.signature
+            ));
+        }
+    }
+
+    #[test]
+    fn test_signed_manifest_of_legacy_key() {
+        let data = data(1234);
+        let chunked_file = ChunkedFile::from_reader(&mut data.as_slice(), 100).unwrap();
+        let signature_scheme = StatelessMerkleSignatureScheme::try_with_encoding(
+            [0u8; 32],
+            16,
+            5,
+            D::new(255),
+            LeafEncoding::MessagePack,
+        )
+        .unwrap();
+        let signed_manifest = chunked_file
+            .manifest()
+            .sign(&signature_scheme, b"")
+            .unwrap();
+
+        let public_key = signature_scheme.versioned_public_key();
+        assert_eq!(public_key.format, KeyFormat::Legacy);
+        assert!(signed_manifest.verify(&public_key, b""));
+
+        let wrong_format = VersionedPublicKey::new(public_key.public_key, KeyFormat::CURRENT);
+        assert!(!signed_manifest.verify(&wrong_format, b""));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::error::Error;
+use crate::io::hash_file;
+use crate::merkle_tree::{MerkleProof, MerkleTree, TreeShape};
+use crate::signature::key_format::VersionedPublicKey;
+use crate::signature::stateless_merkle::{
+    protocol_context, StatelessMerkleSignature, StatelessMerkleSignatureScheme,
+};
+use crate::signature::HashType;
+use crate::utils::{hash, slice_to_hash, try_string_to_hash};
+use anyhow::{bail, Context, Result};
+use data_encoding::HEXLOWER;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Context string used to sign the roots of file sets, see `FileSet::sign_root()`.
+pub const FILE_SET_CONTEXT: &[u8] = b"hash-based-signatures file set root";
+
+/// A set of files, committed to by a `MerkleTree` over the SHA256 hashes of their contents.
+///
+/// The root hash can be published or signed, and the membership of each file can be proven
+/// using its `MerkleProof`, without revealing the other files.
+/// Files are identified by their path when building proofs, but only their contents are
+/// committed to, so proofs can be verified for a copy of the file at any path.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::io::file_set::FileSet;
+/// use hash_based_signatures::utils::hash;
+///
+/// let file_set = FileSet::from_hashes(vec![
+///     ("a.txt".into(), hash(b"a")),
+///     ("b.txt".into(), hash(b"b")),
+///     ("c.txt".into(), hash(b"c")),
+/// ])
+/// .unwrap();
+///
+/// let proof = file_set.proof("b.txt".as_ref()).unwrap();
+/// assert_eq!(proof.file_count, 3);
+/// assert!(proof.verify(file_set.root_hash(), &hash(b"b")));
+/// ```
+pub struct FileSet {
+    files: Vec<(PathBuf, HashType)>,
+    tree: MerkleTree<HashType>,
+}
+
+/// The serialized form of a `FileSet`, e.g. to be stored as JSON next to the files.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileSetIndex {
+    pub root_hash: String,
+    pub files: Vec<FileSetEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileSetEntry {
+    pub path: PathBuf,
+    pub hash: String,
+}
+
+/// The membership proof of a file, along with the number of files in the set,
+/// e.g. to be stored next to the file.
+///
+/// The number of files determines the shape of the tree, so it needs to be authenticated
+/// along with the root hash, e.g. by verifying the signature of both (see `FileSet::verify_root()`).
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileSetProof {
+    pub file_count: usize,
+    pub proof: MerkleProof<HashType>,
+}
+
+impl FileSetProof {
+    /// Verifies that the proof proves a file with the hash `file_hash` to be part of the set
+    /// with the given root hash and `self.file_count` files.
+    pub fn verify(&self, root_hash: HashType, file_hash: &HashType) -> bool {
+        FileSet::verify_proof(root_hash, self.file_count, file_hash, &self.proof)
+    }
+}
+
+impl FileSet {
+    /// Hashes the files at `paths`, in the given order.
+    pub fn from_paths(paths: &[PathBuf]) -> Result<Self> {
+        let files = paths
+            .iter()
+            .map(|path| Ok((path.clone(), slice_to_hash(hash_file(path)?.as_ref()))))
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_hashes(files)
+    }
+
+    /// Builds the tree from the paths and hashes of the files, without reading them.
+    pub fn from_hashes(files: Vec<(PathBuf, HashType)>) -> Result<Self> {
+        if files.is_empty() {
+            bail!("A file set needs at least one file!")
+        }
+        let hashes: Vec<HashType> = files.iter().map(|(_, hash)| *hash).collect();
+        Ok(Self {
+            tree: MerkleTree::with_arity(&hashes, 2),
+            files,
+        })
+    }
+
+    /// Restores a file set from its index, failing if the root hash does not match.
+    pub fn from_index(index: &FileSetIndex) -> Result<Self> {
+        let files = index
+            .files
+            .iter()
+            .map(|entry| {
+                let hash = try_string_to_hash(&entry.hash)
+                    .with_context(|| format!("Invalid hash for {:?}", entry.path))?;
+                Ok((entry.path.clone(), hash))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let file_set = Self::from_hashes(files)?;
+
+        let root_hash = try_string_to_hash(&index.root_hash).context("Invalid root hash")?;
+        if root_hash != file_set.root_hash() {
+            bail!(
+                "The root hash of the index does not match its files, expected {}",
+                HEXLOWER.encode(&file_set.root_hash())
+            )
+        }
+        Ok(file_set)
+    }
+
+    pub fn index(&self) -> FileSetIndex {
+        FileSetIndex {
+            root_hash: HEXLOWER.encode(&self.root_hash()),
+            files: self
+                .files
+                .iter()
+                .map(|(path, hash)| FileSetEntry {
+                    path: path.clone(),
+                    hash: HEXLOWER.encode(hash),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn root_hash(&self) -> HashType {
+        *self.tree.get_root_hash()
+    }
+
+    /// The number of files in the set.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Always `false`, because a file set needs at least one file.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Verifies that `proof` proves a file with the hash `file_hash` to be part of the set
+    /// with the given root hash and number of files.
+    ///
+    /// Like the root hash, the number of files needs to come from a trusted source,
+    /// as it determines the shape of the tree.
+    pub fn verify_proof(
+        root_hash: HashType,
+        file_count: usize,
+        file_hash: &HashType,
+        proof: &MerkleProof<HashType>,
+    ) -> bool {
+        let shape = TreeShape {
+            leaf_count: file_count,
+            arity: 2,
+        };
+        proof.verify_with_shape(root_hash, shape, file_hash)
+    }
+
+    /// The message that is signed to authenticate a file set: the SHA256 hash of the root hash,
+    /// followed by the number of files as an 8-byte big-endian integer.
+    ///
+    /// The number of files is included, because verifiers need it to check proofs
+    /// (see `FileSet::verify_proof()`).
+    fn message(root_hash: HashType, file_count: usize) -> HashType {
+        hash(&[root_hash.as_slice(), &(file_count as u64).to_be_bytes()].concat())
+    }
+
+    /// Signs the root hash and number of files of a file set within the given `context`,
+    /// which is combined with `FILE_SET_CONTEXT` (see `protocol_context()`).
+    ///
+    /// Fails if the combined context is longer than `MAX_CONTEXT_LENGTH` bytes.
+    pub fn sign_root(
+        root_hash: HashType,
+        file_count: usize,
+        signature_scheme: &StatelessMerkleSignatureScheme,
+        context: &[u8],
+    ) -> Result<StatelessMerkleSignature, Error> {
+        signature_scheme.sign_with_context(
+            Self::message(root_hash, file_count),
+            &protocol_context(FILE_SET_CONTEXT, context),
+        )
+    }
+
+    /// Verifies a signature created by `FileSet::sign_root()`,
+    /// for a key in the format of `public_key`.
+    pub fn verify_root(
+        root_hash: HashType,
+        file_count: usize,
+        public_key: &VersionedPublicKey,
+        context: &[u8],
+        signature: &StatelessMerkleSignature,
+    ) -> bool {
+        StatelessMerkleSignatureScheme::verify_versioned(
+            public_key,
+            &Self::message(root_hash, file_count),
+            &protocol_context(FILE_SET_CONTEXT, context),
+            signature,
+        )
+    }
+
+    /// The proof of the file at `path`, which needs to be given exactly as when
+    /// building the set.
+    pub fn proof(&self, path: &Path) -> Result<FileSetProof> {
+        match self.files.iter().position(|(p, _)| p == path) {
+            Some(index) => Ok(FileSetProof {
+                file_count: self.len(),
+                proof: self.tree.try_get_proof(index)?,
+            }),
+            None => bail!("{:?} is not part of the file set", path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::file_set::FileSet;
+    use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+    use crate::signature::winternitz::d::D;
+    use crate::utils::hash;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn file_set(n: u8) -> FileSet {
+        FileSet::from_hashes(
+            (0..n)
+                .map(|i| (PathBuf::from(format!("{i}.txt")), hash(&[i])))
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_all_proofs_verify() {
+        for n in 1..=9 {
+            let file_set = file_set(n);
+            for i in 0..n {
+                let proof = file_set.proof(format!("{i}.txt").as_ref()).unwrap();
+                assert_eq!(proof.file_count, n as usize);
+                let root_hash = file_set.root_hash();
+                assert!(proof.verify(root_hash, &hash(&[i])));
+                assert!(!proof.verify(root_hash, &hash(&[i + 1])));
+
+                let mut wrong_count = proof;
+                wrong_count.file_count = 2 * n as usize;
+                assert!(!wrong_count.verify(root_hash, &hash(&[i])));
+            }
+        }
+    }
+
+    #[test]
+    fn test_message_depends_on_file_count() {
+        let root_hash = file_set(3).root_hash();
+        assert_ne!(
+            FileSet::message(root_hash, 3),
+            FileSet::message(root_hash, 4)
+        );
+        assert_ne!(FileSet::message(root_hash, 3), root_hash);
+    }
+
+    #[test]
+    fn test_signed_root() {
+        let signature_scheme = StatelessMerkleSignatureScheme::new([0u8; 32], 16, 5, D::new(255));
+        let public_key = signature_scheme.versioned_public_key();
+        let root_hash = file_set(3).root_hash();
+        let signature = FileSet::sign_root(root_hash, 3, &signature_scheme, b"").unwrap();
+        assert!(FileSet::verify_root(
+            root_hash,
+            3,
+            &public_key,
+            b"",
+            &signature
+        ));
+        assert!(!FileSet::verify_root(
+            root_hash,
+            4,
+            &public_key,
+            b"",
+            &signature
+        ));
+        assert!(!FileSet::verify_root(
+            root_hash,
+            3,
+            &public_key,
+            b"releases",
+            &signature
+        ));
+
+        // The signature can't be used as a signature of a file
+        assert!(!StatelessMerkleSignatureScheme::verify_with_context(
+            &public_key.public_key,
+            &FileSet::message(root_hash, 3),
+            b"",
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_unknown_file() {
+        assert!(file_set(3).proof("3.txt".as_ref()).is_err());
+        assert!(FileSet::from_hashes(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_index_roundtrip() {
+        let file_set = file_set(5);
+        let json = serde_json::to_string(&file_set.index()).unwrap();
+        let restored = FileSet::from_index(&serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(restored.root_hash(), file_set.root_hash());
+
+        let mut index = file_set.index();
+        index.files.swap(0, 1);
+        assert!(FileSet::from_index(&index).is_err());
+    }
+
+    #[test]
+    fn test_from_paths() {
+        let directory = std::env::temp_dir().join(format!("file_set_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let paths: Vec<PathBuf> = (0..3u8)
+            .map(|i| {
+                let path = directory.join(format!("{i}.txt"));
+                fs::write(&path, [i]).unwrap();
+                path
+            })
+            .collect();
+
+        let file_set = FileSet::from_paths(&paths).unwrap();
+        assert_eq!(file_set.root_hash(), self::file_set(3).root_hash());
+        assert!(FileSet::from_paths(&[directory.join("missing.txt")]).is_err());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,6 +1,8 @@
 pub mod cli;
+pub mod error;
 pub mod io;
 pub mod merkle_tree;
+pub mod secret;
 pub mod signature;
 pub mod utils;
 pub mod wasm;
@@ -1,7 +1,11 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::{Parser, Subcommand};
-use hash_based_signatures::cli::{keygen, sign, verify};
-use hash_based_signatures::utils::string_to_hash;
+use hash_based_signatures::cli::{
+    derive, keygen, master_keygen, sign, sign_chunked, tree_build, tree_prove, tree_verify_proof,
+    verify, verify_chunked,
+};
+use hash_based_signatures::io::chunked::DEFAULT_CHUNK_SIZE;
+use hash_based_signatures::utils::try_string_to_hash;
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -30,16 +34,69 @@ enum Commands {
         #[clap(default_value_t = 32, long)]
         depth: usize,
         /// The parameter `d` used for Winternitz signatures.
-        /// Needs to be of the form `2^(2^x) - 1`, so possible values are: 1, 3, 15, and 255.
+        /// Needs to be of the form `2^k - 1` for `1 <= k <= 8`, e.g. 1, 3, 7, 15, 31, or 255.
         /// Signing time is proportional to `d`, while the signature size is inversely proportional
         /// to `log(d)`.
         #[clap(default_value_t = 15, long)]
         d: u64,
+        /// Compress the one-time public keys into single hashes before putting them into the
+        /// Merkle trees. The public key is then prefixed by `v2:`.
+        #[clap(long)]
+        compressed: bool,
+        /// Overwrite an existing `.private_key.json`
+        #[clap(long)]
+        force: bool,
+    },
+    /// Generate a new master key, from which child keys can be derived
+    MasterKeyGen {
+        /// Overwrite an existing `.master_key.json`
+        #[clap(long)]
+        force: bool,
+    },
+    /// Derive a child key from the master key and print its public key
+    Derive {
+        /// Label of the child key, e.g. `releases/2026`
+        label: String,
+        /// The width of the tree used in Merkle signatures, see `key-gen`
+        #[clap(default_value_t = 16, long)]
+        width: usize,
+        /// The depth of the tree used in Merkle signatures, see `key-gen`
+        #[clap(default_value_t = 32, long)]
+        depth: usize,
+        /// The parameter `d` used for Winternitz signatures, see `key-gen`
+        #[clap(default_value_t = 15, long)]
+        d: u64,
+        /// Also write the child's private key to `.private_key.json`, so that it can be used to sign
+        #[clap(long)]
+        export: bool,
+        /// Overwrite an existing `.private_key.json` when exporting
+        #[clap(long)]
+        force: bool,
     },
     /// Sign a message
     Sign {
         /// Path of the file to sign. The signature will be placed next to the file.
         path: PathBuf,
+        /// Context string (at most 255 bytes) that is bound into the signature.
+        /// The signature only verifies with the same context.
+        #[clap(default_value = "", long)]
+        context: String,
+        /// Derive the signing path from a fresh randomizer that is included in the signature,
+        /// instead of deterministically from the file hash
+        #[clap(long)]
+        randomized: bool,
+    },
+    /// Sign a file in chunks, so that each chunk can be verified on its own
+    SignChunked {
+        /// Path of the file to sign. The signature and the proofs of all chunks
+        /// will be placed next to the file.
+        path: PathBuf,
+        /// Size of the chunks in bytes
+        #[clap(default_value_t = DEFAULT_CHUNK_SIZE, long)]
+        chunk_size: usize,
+        /// Context string (at most 255 bytes) that is bound into the signature
+        #[clap(default_value = "", long)]
+        context: String,
     },
     /// Verify a signature
     Verify {
@@ -47,8 +104,80 @@ enum Commands {
         file_path: PathBuf,
         /// Path of the signature
         signature_path: PathBuf,
-        /// Public key (should be a hex-encoded 256-bit hash)
+        /// Public key, as printed by `key-gen` (a hex-encoded 256-bit hash, prefixed by
+        /// the version of its format unless it is a legacy key)
+        public_key: String,
+        /// Context string the file was signed with
+        #[clap(default_value = "", long)]
+        context: String,
+        /// Only accept signatures created with `sign --randomized`, checking their path
+        #[clap(long)]
+        randomized: bool,
+    },
+    /// Verify a file signed with `sign-chunked`, chunk by chunk
+    VerifyChunked {
+        /// Path of the file to verify
+        file_path: PathBuf,
+        /// Path of the chunked signature
+        signature_path: PathBuf,
+        /// Public key, as printed by `key-gen`
         public_key: String,
+        /// Context string the file was signed with
+        #[clap(default_value = "", long)]
+        context: String,
+    },
+    /// Commit to a set of files using a Merkle tree and prove their membership
+    Tree {
+        #[clap(subcommand)]
+        command: TreeCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum TreeCommands {
+    /// Build a Merkle tree over the hashes of the given files and print its root hash
+    /// and the number of files. The index of the tree is written to `--index`.
+    Build {
+        /// Paths of the files, in the order of the leaves
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+        /// Path the index of the tree is written to
+        #[clap(default_value = "file_tree.json", long)]
+        index: PathBuf,
+        /// Also sign the root hash and the number of files using the key in `.private_key.json`.
+        /// The signature is written next to the index, e.g. to `file_tree.json.signature`.
+        #[clap(long)]
+        sign: bool,
+        /// Context string (at most 255 bytes) that is bound into the signature
+        #[clap(default_value = "", long)]
+        context: String,
+    },
+    /// Write the proof that a file is part of the tree in `--index` to `<file>.proof`
+    Prove {
+        /// Path of the file, as given to `tree build`
+        file: PathBuf,
+        /// Path of the index written by `tree build`
+        #[clap(default_value = "file_tree.json", long)]
+        index: PathBuf,
+    },
+    /// Verify that a file is part of the tree with the given root hash.
+    /// Exits with a non-zero status if the proof or the signature is invalid.
+    VerifyProof {
+        /// Root hash of the tree (should be a hex-encoded 256-bit hash)
+        root: String,
+        /// Path of the file to verify
+        file: PathBuf,
+        /// Path of the proof
+        proof: PathBuf,
+        /// Path of the signature of the root hash and the number of files, which is verified as well
+        #[clap(long)]
+        signature: Option<PathBuf>,
+        /// Public key the root hash was signed with, as printed by `key-gen`
+        #[clap(long)]
+        public_key: Option<String>,
+        /// Context string the root hash was signed with
+        #[clap(default_value = "", long)]
+        context: String,
     },
 }
 
@@ -56,15 +185,95 @@ fn main() -> Result<()> {
     let args: Arguments = Arguments::parse();
 
     match args.command {
-        Commands::KeyGen { width, depth, d } => keygen(width, depth, d),
-        Commands::Sign { path } => sign(path),
+        Commands::KeyGen {
+            width,
+            depth,
+            d,
+            compressed,
+            force,
+        } => keygen(width, depth, d, compressed, force),
+        Commands::MasterKeyGen { force } => master_keygen(force),
+        Commands::Derive {
+            label,
+            width,
+            depth,
+            d,
+            export,
+            force,
+        } => derive(&label, width, depth, d, export, force),
+        Commands::Sign {
+            path,
+            context,
+            randomized,
+        } => sign(path, context.as_bytes(), randomized),
+        Commands::SignChunked {
+            path,
+            chunk_size,
+            context,
+        } => sign_chunked(path, chunk_size, context.as_bytes()),
         Commands::Verify {
             file_path,
             signature_path,
             public_key,
+            context,
+            randomized,
+        } => {
+            verify(
+                file_path,
+                signature_path,
+                public_key.parse()?,
+                context.as_bytes(),
+                randomized,
+            )?;
+            Ok(())
+        }
+        Commands::VerifyChunked {
+            file_path,
+            signature_path,
+            public_key,
+            context,
         } => {
-            verify(file_path, signature_path, string_to_hash(&public_key))?;
+            verify_chunked(
+                file_path,
+                signature_path,
+                public_key.parse()?,
+                context.as_bytes(),
+            )?;
             Ok(())
         }
+        Commands::Tree { command } => match command {
+            TreeCommands::Build {
+                files,
+                index,
+                sign,
+                context,
+            } => tree_build(&files, &index, sign, context.as_bytes()),
+            TreeCommands::Prove { file, index } => tree_prove(file, &index),
+            TreeCommands::VerifyProof {
+                root,
+                file,
+                proof,
+                signature,
+                public_key,
+                context,
+            } => {
+                let signature = match (signature, public_key) {
+                    (Some(signature), Some(public_key)) => Some((signature, public_key.parse()?)),
+                    (None, None) => None,
+                    _ => bail!("--signature and --public-key need to be given together"),
+                };
+                let verifies = tree_verify_proof(
+                    try_string_to_hash(&root)?,
+                    file,
+                    proof,
+                    signature,
+                    context.as_bytes(),
+                )?;
+                if !verifies {
+                    std::process::exit(1);
+                }
+                Ok(())
+            }
+        },
     }
 }
@@ -1,12 +1,38 @@
+pub mod encoding;
+pub mod log;
+pub mod mmr;
+pub mod multi_proof;
+pub mod sparse;
+pub mod traversal;
+
+use crate::error::Error;
+use crate::merkle_tree::encoding::{canonical_leaf_hash, LeafEncode, LeafEncoding};
 use crate::signature::HashType;
-use crate::utils::{get_least_significant_bits, hash};
+use crate::utils::{ct_eq, get_least_significant_bits, hash};
 use data_encoding::HEXLOWER;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::cmp::min;
 use std::fmt::{Debug, Formatter};
 use std::marker::PhantomData;
+use std::ops::Range;
 
 /// A Merkle tree.
 ///
+/// By default, trees are binary and the number of elements needs to be a power of two.
+/// Using `MerkleTree::with_arity()`, trees can have an arbitrary number of elements and an
+/// arbitrary arity. Nodes are grouped level by level, from left to right: Each group of up to
+/// `arity` nodes is hashed to its parent node. If the last group only contains a single node,
+/// the node is promoted to the next level as is. For binary trees, this results in the same
+/// structure as in [RFC 6962](https://www.rfc-editor.org/rfc/rfc6962#section-2.1).
+///
+/// Only the hashes of the nodes are stored, in a single array, level by level from the leaves
+/// up. Each level of the tree is hashed in parallel.
+///
+/// Leaves are hashed using their canonical encoding (see `LeafEncode`), and leaves and internal
+/// nodes are domain-separated like in RFC 6962 (see `LeafEncoding::node_hash()`). Trees committing
+/// to data with the legacy MessagePack encoding can be built using `MerkleTree::try_with_encoding()`.
+///
 /// # Examples
 /// ```
 /// use hash_based_signatures::merkle_tree::MerkleTree;
@@ -15,176 +41,487 @@ use std::marker::PhantomData;
 /// let tree = MerkleTree::new(&elements);
 /// let proof = tree.get_proof(17);
 /// assert!(proof.verify(*tree.get_root_hash(), &17));
+///
+/// let elements: Vec<u8> = (0..100).collect();
+/// let tree = MerkleTree::with_arity(&elements, 4);
+/// let proof = tree.get_proof(17);
+/// // The verifier needs to know the shape of the tree, just like the root hash
+/// assert!(proof.verify_with_shape(*tree.get_root_hash(), tree.shape(), &17));
 /// ```
 #[derive(Clone)]
 pub struct MerkleTree<T: Serialize> {
-    root_hash: [u8; 32],
-    root_node: Node<T>,
-    depth: usize,
+    /// The node hashes of all levels, starting with the leaves
+    hashes: Vec<HashType>,
+    /// The index in `hashes` at which each level starts, followed by `hashes.len()`
+    level_starts: Vec<usize>,
+    arity: usize,
+    encoding: LeafEncoding,
 
     /// Phantom to keep the information of the element type.
     phantom: PhantomData<T>,
 }
 
-#[derive(Clone)]
-enum Node<T: Serialize> {
-    Leaf(),
-    InternalNode(Box<MerkleTree<T>>, Box<MerkleTree<T>>),
+/// The shape of a Merkle tree that is not a binary tree with a power-of-two number of leaves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeShape {
+    /// The number of leaves
+    pub leaf_count: usize,
+    /// The maximal number of children of a node
+    pub arity: usize,
+}
+
+impl TreeShape {
+    /// The shape of the binary tree with `2^depth` leaves.
+    pub fn binary(depth: usize) -> Option<Self> {
+        Some(Self {
+            leaf_count: 1usize.checked_shl(depth as u32)?,
+            arity: 2,
+        })
+    }
+
+    /// Checks that the tree has at least one leaf and an arity of at least two.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.leaf_count == 0 {
+            return Err(Error::EmptyTree);
+        }
+        if self.arity < 2 {
+            return Err(Error::InvalidArity(self.arity));
+        }
+        Ok(())
+    }
+
+    /// The number of nodes at each level, starting with the leaves.
+    fn level_sizes(&self) -> Vec<usize> {
+        let mut level_sizes = vec![self.leaf_count];
+        while level_sizes[level_sizes.len() - 1] > 1 {
+            level_sizes.push(level_sizes[level_sizes.len() - 1].div_ceil(self.arity));
+        }
+        level_sizes
+    }
+
+    /// The range of indices of the group that the `index`-th node of a level of size `level_size`
+    /// belongs to.
+    fn group(&self, index: usize, level_size: usize) -> Range<usize> {
+        let start = index - index % self.arity;
+        start..min(start + self.arity, level_size)
+    }
 }
 
 /// A proof that a given datum is at a given index.
 /// Note that the proof does not store the data itself, but it needs to be
 /// provided to `MerkleProof::verify()`.
-#[derive(PartialEq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct MerkleProof<T: Serialize> {
     /// The index of the datum for which this is the proof.
     pub index: usize,
-    /// Hash chain leading up to the root node
+    /// Hash chain leading up to the root node.
+    /// For each level, it contains the hashes of the other nodes in the group, from left to right.
+    /// Their positions follow from the index and the shape of the tree.
     pub hash_chain: Vec<[u8; 32]>,
 
     /// Phantom to keep the information of the element type.
     phantom: PhantomData<T>,
+
+    /// The shape of the tree, if it is not a binary tree with a power-of-two number of leaves.
+    /// Omitted otherwise, so that the encoding of those proofs does not change.
+    ///
+    /// This is informational only and never used for verification: Like the root hash,
+    /// the verifier needs to know the shape from a trusted source and pass it to
+    /// `MerkleProof::verify_with_shape()`. Otherwise, a proof could claim a different
+    /// number of leaves to move a datum to another index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shape: Option<TreeShape>,
 }
 
-/// Hash function applied to leaves of the Merkle tree
+/// Hash function applied to leaves of the Merkle tree, using the current encoding.
+/// See `LeafEncoding::leaf_hash()`.
+pub fn leaf_hash<T: LeafEncode + ?Sized>(data: &T) -> [u8; 32] {
+    canonical_leaf_hash(data)
+}
+
+/// Hash function applied to internal nodes of the Merkle tree, using the current encoding.
+/// See `LeafEncoding::node_hash()`.
+pub fn internal_node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    LeafEncoding::CURRENT.node_hash(&[*left, *right])
+}
+
+/// Hash function applied to a group of nodes in n-ary trees, using the current encoding.
 ///
-/// # Panics
-/// Panics if the data can't be serialized.
-pub fn leaf_hash<T: Serialize>(data: &T) -> [u8; 32] {
-    let data = rmp_serde::to_vec(data).expect("Failed to serialize data");
+/// For two nodes, this is the same as `internal_node_hash()`.
+/// A single node is promoted as is.
+pub fn group_hash(children: &[HashType]) -> HashType {
+    LeafEncoding::CURRENT.node_hash(children)
+}
 
-    // For leafs, we need to use a different hash function for security:
-    // https://crypto.stackexchange.com/questions/2106/what-is-the-purpose-of-using-different-hash-functions-for-the-leaves-and-interna
-    // So, we append a zero to all leaves before hashing them
-    let zero = [0u8];
-    let all_elements = [&data, &zero as &[u8]].concat();
-    hash(&all_elements)
+/// The number of leaves that are encoded at once before hashing them in parallel.
+#[cfg(not(target_arch = "wasm32"))]
+const LEAF_BATCH_SIZE: usize = 1024;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn hash_leaves<T: Serialize + LeafEncode>(
+    elements: &[T],
+    leaves: &mut [HashType],
+    encoding: LeafEncoding,
+) {
+    // Encode the elements first, so that only the encodings need to be shared between threads
+    // and `T` does not need to be `Sync`. Encoding them in batches keeps only a bounded number
+    // of encodings in memory at any time.
+    let mut encoded = Vec::with_capacity(min(elements.len(), LEAF_BATCH_SIZE));
+    for (elements, leaves) in elements
+        .chunks(LEAF_BATCH_SIZE)
+        .zip(leaves.chunks_mut(LEAF_BATCH_SIZE))
+    {
+        encoded.clear();
+        encoded.extend(elements.iter().map(|element| encoding.encode(element)));
+        leaves
+            .par_iter_mut()
+            .zip(encoded.par_iter())
+            .for_each(|(leaf, encoded)| *leaf = hash(encoded));
+    }
 }
 
-/// Hash function applied to internal nodes of the Merkle tree
-pub fn internal_node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
-    let all_elements = [*left, *right].concat();
-    hash(&all_elements)
+#[cfg(target_arch = "wasm32")]
+fn hash_leaves<T: Serialize + LeafEncode>(
+    elements: &[T],
+    leaves: &mut [HashType],
+    encoding: LeafEncoding,
+) {
+    // Avoid spawning threads in Web Assembly
+    leaves
+        .iter_mut()
+        .zip(elements.iter())
+        .for_each(|(leaf, element)| *leaf = encoding.leaf_hash(element));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn hash_level(
+    parents: &mut [HashType],
+    children: &[HashType],
+    arity: usize,
+    encoding: LeafEncoding,
+) {
+    parents
+        .par_iter_mut()
+        .zip(children.par_chunks(arity))
+        .for_each(|(parent, children)| *parent = encoding.node_hash(children));
 }
 
-impl<T: Serialize + Debug> MerkleTree<T> {
+#[cfg(target_arch = "wasm32")]
+fn hash_level(
+    parents: &mut [HashType],
+    children: &[HashType],
+    arity: usize,
+    encoding: LeafEncoding,
+) {
+    // Same as above, but using `iter_mut()` instead of `par_iter_mut()` to avoid spawning threads.
+    parents
+        .iter_mut()
+        .zip(children.chunks(arity))
+        .for_each(|(parent, children)| *parent = encoding.node_hash(children));
+}
+
+impl<T: Serialize + LeafEncode + Debug> MerkleTree<T> {
     /// Construct a new Merkle tree from a list of `elements`.
     ///
     /// A single element is of type `Vec<u8>`, so any complex data structure has
     /// to be serialized to a variable-length byte array.
-    /// The tree owns the values.
+    /// The tree only stores the hashes of the values.
     ///
     /// # Panics
     ///
-    /// Panics if the number of elements is not a power of two or if the provided data can't be serialized.
+    /// Panics if the number of elements is not a power of two.
+    /// Consider using `MerkleTree::try_new()`.
     pub fn new(elements: &[T]) -> MerkleTree<T> {
-        let depth = (elements.len() as f64).log2() as usize;
+        Self::try_new(elements).unwrap_or_else(|error| panic!("{error}"))
+    }
 
-        if 1 << depth != elements.len() {
-            panic!(
-                "Number of elements needs to be a power of 2, got {}",
-                elements.len()
-            )
+    /// Like `MerkleTree::new()`, but fails if the number of elements is not a power of two.
+    pub fn try_new(elements: &[T]) -> Result<MerkleTree<T>, Error> {
+        if !elements.len().is_power_of_two() {
+            return Err(Error::NotAPowerOfTwo(elements.len()));
         }
+        Self::try_with_arity(elements, 2)
+    }
 
-        let (root_node, root_hash) = if elements.len() == 1 {
-            let element_hash = leaf_hash(&elements[0]);
-            (Node::Leaf(), element_hash)
-        } else {
-            let mid = elements.len() / 2;
-            let elements_left = &elements[..mid];
-            let elements_right = &elements[mid..];
-            let left_tree = Box::new(MerkleTree::new(elements_left));
-            let right_tree = Box::new(MerkleTree::new(elements_right));
+    /// Construct a new Merkle tree with the given `arity` from any non-empty list of `elements`.
+    ///
+    /// For a power-of-two number of elements and `arity == 2`, this is the same as `MerkleTree::new()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no elements or if the arity is less than two.
+    /// Consider using `MerkleTree::try_with_arity()`.
+    pub fn with_arity(elements: &[T], arity: usize) -> MerkleTree<T> {
+        Self::try_with_arity(elements, arity).unwrap_or_else(|error| panic!("{error}"))
+    }
 
-            let root_hash = internal_node_hash(&left_tree.root_hash, &right_tree.root_hash);
-            let root_node = Node::InternalNode(left_tree, right_tree);
+    /// Like `MerkleTree::with_arity()`, but fails if there are no elements or if the arity
+    /// is less than two.
+    pub fn try_with_arity(elements: &[T], arity: usize) -> Result<MerkleTree<T>, Error> {
+        Self::try_with_encoding(elements, arity, LeafEncoding::CURRENT)
+    }
 
-            (root_node, root_hash)
+    /// Like `MerkleTree::try_with_arity()`, but hashes the leaves using the given `encoding`.
+    ///
+    /// This is only needed to reproduce trees that were built with a previous encoding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the provided data can't be serialized using MessagePack.
+    pub fn try_with_encoding(
+        elements: &[T],
+        arity: usize,
+        encoding: LeafEncoding,
+    ) -> Result<MerkleTree<T>, Error> {
+        let shape = TreeShape {
+            leaf_count: elements.len(),
+            arity,
         };
+        shape.validate()?;
 
-        MerkleTree {
-            root_hash,
-            root_node,
-            depth,
-            phantom: PhantomData,
+        let mut level_starts = vec![0];
+        for level_size in shape.level_sizes() {
+            level_starts.push(level_starts[level_starts.len() - 1] + level_size);
         }
+
+        let mut hashes = vec![[0u8; 32]; level_starts[level_starts.len() - 1]];
+        hash_leaves(elements, &mut hashes[..elements.len()], encoding);
+        for level in 1..level_starts.len() - 1 {
+            let (children, parents) = hashes.split_at_mut(level_starts[level]);
+            hash_level(
+                &mut parents[..level_starts[level + 1] - level_starts[level]],
+                &children[level_starts[level - 1]..],
+                arity,
+                encoding,
+            );
+        }
+
+        Ok(MerkleTree {
+            hashes,
+            level_starts,
+            arity,
+            encoding,
+            phantom: PhantomData,
+        })
     }
 
     /// Get the root hash of the tree.
     pub fn get_root_hash(&self) -> &[u8; 32] {
-        &self.root_hash
+        &self.hashes[self.hashes.len() - 1]
+    }
+
+    /// The number of levels above the leaves.
+    pub fn depth(&self) -> usize {
+        self.level_starts.len() - 2
+    }
+
+    /// The encoding used to hash the leaves.
+    pub fn encoding(&self) -> LeafEncoding {
+        self.encoding
+    }
+
+    /// The shape of the tree.
+    pub fn shape(&self) -> TreeShape {
+        TreeShape {
+            leaf_count: self.level_starts[1],
+            arity: self.arity,
+        }
     }
 
     /// Get a Merkle proof for a given index `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of range.
+    /// Consider using `MerkleTree::try_get_proof()`.
     pub fn get_proof(&self, i: usize) -> MerkleProof<T> {
-        assert!(i < 1 << self.depth);
+        self.try_get_proof(i)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
 
-        match &self.root_node {
-            Node::Leaf() => MerkleProof {
+    /// Like `MerkleTree::get_proof()`, but fails if `i` is out of range.
+    pub fn try_get_proof(&self, i: usize) -> Result<MerkleProof<T>, Error> {
+        let shape = self.shape();
+        if i >= shape.leaf_count {
+            return Err(Error::IndexOutOfRange {
                 index: i,
-                hash_chain: vec![],
-                phantom: PhantomData,
-            },
-            Node::InternalNode(left_tree, right_tree) => {
-                let mut proof = if i < 1 << (self.depth - 1) {
-                    // Element is in left child
-                    let mut proof = left_tree.get_proof(i);
-                    proof.hash_chain.push(right_tree.root_hash);
-                    proof
-                } else {
-                    // Element is in left child
-                    let mut proof = right_tree.get_proof(i - (1 << (self.depth - 1)));
-                    proof.hash_chain.push(left_tree.root_hash);
-                    proof
-                };
-                proof.index = i;
-                proof
+                size: shape.leaf_count,
+            });
+        }
+
+        let mut hash_chain = Vec::with_capacity(self.depth() * (self.arity - 1));
+        let mut index = i;
+        for level in 0..self.depth() {
+            for sibling in shape.group(index, self.level_size(level)) {
+                if sibling != index {
+                    hash_chain.push(self.node_hash(level, sibling));
+                }
             }
+            index /= self.arity;
+        }
+
+        let mut proof = MerkleProof::from_hash_chain(i, hash_chain);
+        if TreeShape::binary(self.depth()) != Some(shape) {
+            proof.shape = Some(shape);
         }
+        Ok(proof)
     }
 
-    fn representation_string(&self, indent: usize) -> String {
-        let mut result = String::new();
-        let indent_str = "  ".repeat(indent).to_string();
-        result += &format!("{}{}\n", indent_str, HEXLOWER.encode(&self.root_hash));
+    /// Gets the number of nodes at the given `level`, counted from the leaves.
+    fn level_size(&self, level: usize) -> usize {
+        self.level_starts[level + 1] - self.level_starts[level]
+    }
 
-        match &self.root_node {
-            Node::Leaf() => {
+    /// Gets the hash of the `index`-th node at the given `level`, counted from the leaves.
+    fn node_hash(&self, level: usize, index: usize) -> HashType {
+        self.hashes[self.level_starts[level] + index]
+    }
+}
+
+impl<T: Serialize> Debug for MerkleTree<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Print the nodes in pre-order, indented by their depth
+        let mut result = String::new();
+        let root_level = self.level_starts.len() - 2;
+        let mut stack = vec![(root_level, 0usize, 0usize)];
+        while let Some((level, index, indent)) = stack.pop() {
+            let indent_str = "  ".repeat(indent);
+            let hash = &self.hashes[self.level_starts[level] + index];
+            result += &format!("{}{}\n", indent_str, HEXLOWER.encode(hash));
+            if level == 0 {
                 result += &format!("{}  Leaf\n", indent_str);
-            }
-            Node::InternalNode(left, right) => {
-                result += &left.representation_string(indent + 1);
-                result += &right.representation_string(indent + 1);
+            } else {
+                let children_level_size = self.level_starts[level] - self.level_starts[level - 1];
+                let children =
+                    index * self.arity..min((index + 1) * self.arity, children_level_size);
+                for child in children.rev() {
+                    stack.push((level - 1, child, indent + 1));
+                }
             }
         }
-
-        result
+        write!(f, "{}", result)
     }
 }
 
-impl<T: Serialize + Debug> Debug for MerkleTree<T> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.representation_string(0))
+impl<T: Serialize> MerkleProof<T> {
+    /// Creates a Merkle proof from the `index` of the datum and the `hash_chain` leading up
+    /// to the root node of a binary tree with a power-of-two number of leaves.
+    pub fn from_hash_chain(index: usize, hash_chain: Vec<[u8; 32]>) -> Self {
+        Self {
+            index,
+            hash_chain,
+            shape: None,
+            phantom: PhantomData,
+        }
     }
 }
 
-impl<T: Serialize> MerkleProof<T> {
-    /// Verifies that the given root hash can be reconstructed from the Merkle proof.
+impl<T: Serialize + LeafEncode> MerkleProof<T> {
+    /// Verifies that the given root hash can be reconstructed from the Merkle proof,
+    /// for a binary tree with a power-of-two number of leaves.
     ///
-    /// # Panics
-    /// Panics if the data can't be serialized.
+    /// Malformed proofs (e.g. with an index that does not fit the length of the hash chain)
+    /// are rejected. For trees of any other shape, use `MerkleProof::verify_with_shape()`.
     pub fn verify(&self, root_hash: HashType, data: &T) -> bool {
+        self.verify_with_encoding(root_hash, data, LeafEncoding::CURRENT)
+    }
+
+    /// Like `MerkleProof::verify()`, for a tree whose leaves were hashed using `encoding`.
+    ///
+    /// # Panics
+    /// Panics if the data can't be serialized using MessagePack.
+    pub fn verify_with_encoding(
+        &self,
+        root_hash: HashType,
+        data: &T,
+        encoding: LeafEncoding,
+    ) -> bool {
+        match self.root_hash_with_encoding(data, encoding) {
+            Some(expected_root_hash) => ct_eq(&expected_root_hash, &root_hash),
+            None => false,
+        }
+    }
+
+    /// Verifies that the given root hash can be reconstructed from the Merkle proof,
+    /// for a tree of the given `shape`, which must come from the same trusted source as
+    /// the root hash. The `shape` stored in the proof is ignored.
+    pub fn verify_with_shape(&self, root_hash: HashType, shape: TreeShape, data: &T) -> bool {
+        match self.root_hash_with_shape(data, shape) {
+            Some(expected_root_hash) => ct_eq(&expected_root_hash, &root_hash),
+            None => false,
+        }
+    }
+
+    /// Computes the root hash of a binary tree with a power-of-two number of leaves from
+    /// the Merkle proof and the datum.
+    ///
+    /// Returns `None` for malformed proofs.
+    pub fn root_hash(&self, data: &T) -> Option<HashType> {
+        self.root_hash_with_encoding(data, LeafEncoding::CURRENT)
+    }
+
+    /// Like `MerkleProof::root_hash()`, for a tree whose leaves were hashed using `encoding`.
+    ///
+    /// # Panics
+    /// Panics if the data can't be serialized using MessagePack.
+    pub fn root_hash_with_encoding(&self, data: &T, encoding: LeafEncoding) -> Option<HashType> {
+        self.binary_root_hash(encoding.leaf_hash(data), encoding)
+    }
+
+    /// Like `MerkleProof::root_hash()`, for a tree of the given `shape`.
+    ///
+    /// Returns `None` for malformed proofs, including proofs that don't fit the shape.
+    pub fn root_hash_with_shape(&self, data: &T, shape: TreeShape) -> Option<HashType> {
+        self.shaped_root_hash(leaf_hash(data), shape)
+    }
+
+    fn binary_root_hash(&self, leaf_hash: HashType, encoding: LeafEncoding) -> Option<HashType> {
+        if self.hash_chain.len() >= usize::BITS as usize || self.index >> self.hash_chain.len() != 0
+        {
+            return None;
+        }
+
         let index_bits = get_least_significant_bits(self.index, self.hash_chain.len());
-        let mut expected_root_hash = leaf_hash(data);
+        let mut root_hash = leaf_hash;
         for (hash, index_bit) in self.hash_chain.iter().zip(index_bits.iter().rev()) {
-            expected_root_hash = match index_bit {
-                false => internal_node_hash(&expected_root_hash, hash),
-                true => internal_node_hash(hash, &expected_root_hash),
+            root_hash = match index_bit {
+                false => encoding.node_hash(&[root_hash, *hash]),
+                true => encoding.node_hash(&[*hash, root_hash]),
             }
         }
 
-        expected_root_hash == root_hash
+        Some(root_hash)
+    }
+
+    fn shaped_root_hash(&self, leaf_hash: HashType, shape: TreeShape) -> Option<HashType> {
+        if shape.validate().is_err() || self.index >= shape.leaf_count {
+            return None;
+        }
+
+        let mut hash_chain = self.hash_chain.iter();
+        let mut root_hash = leaf_hash;
+        let mut index = self.index;
+        for level_size in shape.level_sizes() {
+            if level_size == 1 {
+                break;
+            }
+            let group = shape.group(index, level_size);
+            let mut children = Vec::with_capacity(group.len());
+            for sibling in group {
+                if sibling == index {
+                    children.push(root_hash);
+                } else {
+                    children.push(*hash_chain.next()?);
+                }
+            }
+            root_hash = group_hash(&children);
+            index /= shape.arity;
+        }
+
+        if hash_chain.next().is_some() {
+            return None;
+        }
+        Some(root_hash)
     }
 }
 
@@ -194,13 +531,25 @@ impl<T: Serialize> Debug for MerkleProof<T> {
         for hash in self.hash_chain.iter() {
             representation += &format!("  {}\n", HEXLOWER.encode(hash));
         }
+        if let Some(shape) = &self.shape {
+            representation +=
+                &format!("Leaf count: {}\nArity: {}\n", shape.leaf_count, shape.arity);
+        }
         write!(f, "{}", representation)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::merkle_tree::{MerkleProof, MerkleTree};
+    use crate::error::Error;
+    use crate::merkle_tree::encoding::{LeafEncode, LeafEncoding};
+    use crate::merkle_tree::{
+        group_hash, internal_node_hash, leaf_hash, MerkleProof, MerkleTree, TreeShape,
+        LEAF_BATCH_SIZE,
+    };
+    use data_encoding::HEXLOWER;
+    use serde::Serialize;
+    use std::cell::Cell;
     use std::marker::PhantomData;
 
     fn merkle_tree() -> MerkleTree<Vec<u8>> {
@@ -208,6 +557,254 @@ mod tests {
         MerkleTree::new(&elements)
     }
 
+    #[test]
+    fn test_root_hash_of_small_tree() {
+        let elements: Vec<Vec<u8>> = (0u8..4).map(|x| vec![x]).collect();
+        let tree = MerkleTree::new(&elements);
+        let leaves: Vec<[u8; 32]> = elements.iter().map(leaf_hash).collect();
+        let expected_root_hash = internal_node_hash(
+            &internal_node_hash(&leaves[0], &leaves[1]),
+            &internal_node_hash(&leaves[2], &leaves[3]),
+        );
+        assert_eq!(*tree.get_root_hash(), expected_root_hash);
+        assert_eq!(
+            tree.get_proof(2).hash_chain,
+            vec![leaves[3], internal_node_hash(&leaves[0], &leaves[1])]
+        );
+    }
+
+    /// Computes the root hash as defined in RFC 6962 (except for the leaf and node hash functions)
+    fn rfc6962_root_hash(leaves: &[[u8; 32]]) -> [u8; 32] {
+        if leaves.len() == 1 {
+            return leaves[0];
+        }
+        let k = 1 << (usize::BITS - (leaves.len() - 1).leading_zeros() - 1);
+        internal_node_hash(
+            &rfc6962_root_hash(&leaves[..k]),
+            &rfc6962_root_hash(&leaves[k..]),
+        )
+    }
+
+    #[test]
+    fn test_binary_tree_follows_rfc6962() {
+        for n in 1..=33u8 {
+            let elements: Vec<Vec<u8>> = (0..n).map(|x| vec![x]).collect();
+            let leaves: Vec<[u8; 32]> = elements.iter().map(leaf_hash).collect();
+            let tree = MerkleTree::with_arity(&elements, 2);
+            assert_eq!(*tree.get_root_hash(), rfc6962_root_hash(&leaves));
+        }
+    }
+
+    #[test]
+    fn test_n_ary_tree() {
+        let elements: Vec<Vec<u8>> = (0u8..6).map(|x| vec![x]).collect();
+        let leaves: Vec<[u8; 32]> = elements.iter().map(leaf_hash).collect();
+        let tree = MerkleTree::with_arity(&elements, 4);
+        let expected_root_hash = group_hash(&[group_hash(&leaves[..4]), group_hash(&leaves[4..])]);
+        assert_eq!(*tree.get_root_hash(), expected_root_hash);
+        assert_eq!(tree.depth(), 2);
+
+        // Leaf 5 only has one sibling in its group
+        let proof = tree.get_proof(5);
+        assert_eq!(proof.hash_chain, vec![leaves[4], group_hash(&leaves[..4])]);
+        assert_eq!(
+            proof.shape,
+            Some(TreeShape {
+                leaf_count: 6,
+                arity: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_all_proofs_are_valid_for_any_shape() {
+        for arity in [2, 3, 4, 16] {
+            for n in 1..=40u8 {
+                let elements: Vec<Vec<u8>> = (0..n).map(|x| vec![x]).collect();
+                let tree = MerkleTree::with_arity(&elements, arity);
+                for (i, element) in elements.iter().enumerate() {
+                    assert!(tree.get_proof(i).verify_with_shape(
+                        *tree.get_root_hash(),
+                        tree.shape(),
+                        element
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_power_of_two_binary_tree_is_unchanged() {
+        let elements: Vec<Vec<u8>> = (0u8..128).map(|x| vec![x]).collect();
+        let tree = MerkleTree::with_arity(&elements, 2);
+        assert_eq!(tree.get_root_hash(), merkle_tree().get_root_hash());
+        assert!(tree.get_proof(43) == merkle_tree().get_proof(43));
+        assert_eq!(tree.get_proof(43).shape, None);
+    }
+
+    #[test]
+    fn test_proof_lengths() {
+        let elements: Vec<u16> = (0..4096).collect();
+        for (arity, expected_length) in [(2, 12), (4, 18), (16, 45)] {
+            let tree = MerkleTree::with_arity(&elements, arity);
+            assert_eq!(tree.get_proof(1234).hash_chain.len(), expected_length);
+        }
+    }
+
+    #[test]
+    fn test_invalid_shaped_proofs() {
+        let elements: Vec<Vec<u8>> = (0u8..100).map(|x| vec![x]).collect();
+        let tree = MerkleTree::with_arity(&elements, 4);
+        let root_hash = *tree.get_root_hash();
+        let shape = tree.shape();
+        let proof = tree.get_proof(43);
+        assert!(proof.verify_with_shape(root_hash, shape, &vec![43]));
+
+        let mut wrong_index = proof.clone();
+        wrong_index.index = 42;
+        let mut index_out_of_range = proof.clone();
+        index_out_of_range.index = 143;
+        let mut additional_hash = proof.clone();
+        additional_hash.hash_chain.push([0u8; 32]);
+        for invalid_proof in [wrong_index, index_out_of_range, additional_hash] {
+            assert!(!invalid_proof.verify_with_shape(root_hash, shape, &vec![43]));
+        }
+
+        // The shape is only taken from the verifier
+        for wrong_shape in [
+            TreeShape {
+                leaf_count: 40,
+                arity: 4,
+            },
+            TreeShape {
+                leaf_count: 100,
+                arity: 1,
+            },
+        ] {
+            assert!(!proof.verify_with_shape(root_hash, wrong_shape, &vec![43]));
+        }
+        let mut no_shape = proof.clone();
+        no_shape.shape = None;
+        assert!(no_shape.verify_with_shape(root_hash, shape, &vec![43]));
+        assert!(!proof.verify(root_hash, &vec![43]));
+    }
+
+    #[test]
+    fn test_proof_cannot_move_datum_by_claiming_another_shape() {
+        // The root of three leaves is h(h(l0, l1), l2), so the proof of the last leaf is also
+        // a proof that it is the second leaf of a tree with two leaves.
+        let elements: Vec<Vec<u8>> = (0u8..3).map(|x| vec![x]).collect();
+        let tree = MerkleTree::with_arity(&elements, 2);
+        let root_hash = *tree.get_root_hash();
+        let mut forged = tree.get_proof(2);
+        forged.index = 1;
+        forged.shape = Some(TreeShape {
+            leaf_count: 2,
+            arity: 2,
+        });
+        assert!(forged.verify(root_hash, &vec![2]));
+        assert!(!forged.verify_with_shape(root_hash, tree.shape(), &vec![2]));
+        assert!(tree
+            .get_proof(2)
+            .verify_with_shape(root_hash, tree.shape(), &vec![2]));
+    }
+
+    #[test]
+    fn test_try_with_arity_invalid_parameters() {
+        let elements: Vec<Vec<u8>> = vec![];
+        assert!(matches!(
+            MerkleTree::try_with_arity(&elements, 2),
+            Err(Error::EmptyTree)
+        ));
+        assert!(matches!(
+            MerkleTree::try_with_arity(&[vec![0u8]], 1),
+            Err(Error::InvalidArity(1))
+        ));
+    }
+
+    #[test]
+    fn test_shaped_proof_serialization_roundtrip() {
+        let elements: Vec<Vec<u8>> = (0u8..100).map(|x| vec![x]).collect();
+        let tree = MerkleTree::with_arity(&elements, 4);
+        let proof = tree.get_proof(43);
+        let bytes = rmp_serde::to_vec(&proof).unwrap();
+        let deserialized: MerkleProof<Vec<u8>> = rmp_serde::from_slice(&bytes).unwrap();
+        assert!(deserialized == proof);
+    }
+
+    #[test]
+    fn test_legacy_encoding() {
+        let elements: Vec<Vec<u8>> = (0u8..8).map(|x| vec![x]).collect();
+        let tree = MerkleTree::try_with_encoding(&elements, 2, LeafEncoding::MessagePack).unwrap();
+        assert_eq!(tree.encoding(), LeafEncoding::MessagePack);
+        assert_ne!(
+            tree.get_root_hash(),
+            MerkleTree::new(&elements).get_root_hash()
+        );
+
+        let root_hash = *tree.get_root_hash();
+        let proof = tree.get_proof(3);
+        assert!(proof.verify_with_encoding(root_hash, &vec![3], LeafEncoding::MessagePack));
+        assert!(!proof.verify(root_hash, &vec![3]));
+    }
+
+    #[test]
+    fn test_elements_do_not_need_to_be_sync() {
+        #[derive(Debug, Serialize)]
+        struct NotSync(Cell<u8>);
+        impl LeafEncode for NotSync {
+            fn encode_leaf(&self, out: &mut Vec<u8>) {
+                self.0.get().encode_leaf(out);
+            }
+        }
+
+        let elements = [NotSync(Cell::new(1)), NotSync(Cell::new(2))];
+        assert_eq!(
+            MerkleTree::new(&elements).get_root_hash(),
+            MerkleTree::new(&[1u8, 2u8]).get_root_hash()
+        );
+    }
+
+    #[test]
+    fn test_more_leaves_than_a_batch() {
+        let elements: Vec<u32> = (0..2 * LEAF_BATCH_SIZE as u32 + 5).collect();
+        let tree = MerkleTree::with_arity(&elements, 2);
+        for i in [0, LEAF_BATCH_SIZE - 1, LEAF_BATCH_SIZE, elements.len() - 1] {
+            let proof = tree.get_proof(i);
+            assert!(proof.verify_with_shape(*tree.get_root_hash(), tree.shape(), &elements[i]));
+        }
+    }
+
+    #[test]
+    fn test_single_element() {
+        let tree = MerkleTree::new(&[vec![42u8]]);
+        assert_eq!(*tree.get_root_hash(), leaf_hash(&vec![42u8]));
+        assert!(tree.get_proof(0).hash_chain.is_empty());
+        assert!(tree.get_proof(0).verify(*tree.get_root_hash(), &vec![42u8]));
+    }
+
+    #[test]
+    fn test_all_proofs_are_valid() {
+        let tree = merkle_tree();
+        for i in 0..128u8 {
+            assert!(tree
+                .get_proof(i as usize)
+                .verify(*tree.get_root_hash(), &vec![i]));
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        let tree = MerkleTree::new(&[vec![0u8], vec![1u8]]);
+        let expected = format!(
+            "{}\n  {}\n    Leaf\n  {}\n    Leaf\n",
+            HEXLOWER.encode(tree.get_root_hash()),
+            HEXLOWER.encode(&leaf_hash(&vec![0u8])),
+            HEXLOWER.encode(&leaf_hash(&vec![1u8])),
+        );
+        assert_eq!(format!("{:?}", tree), expected);
+    }
+
     #[test]
     fn test_valid_proofs() {
         let tree = merkle_tree();
@@ -216,6 +813,17 @@ mod tests {
         assert!(proof.verify(*tree.get_root_hash(), &vec![43]));
     }
 
+    #[test]
+    fn test_root_hash() {
+        let tree = merkle_tree();
+        let proof = tree.get_proof(43);
+        assert_eq!(proof.root_hash(&vec![43]), Some(*tree.get_root_hash()));
+        assert_ne!(proof.root_hash(&vec![42]), Some(*tree.get_root_hash()));
+
+        let malformed_proof = MerkleProof::<Vec<u8>>::from_hash_chain(128, proof.hash_chain);
+        assert_eq!(malformed_proof.root_hash(&vec![43]), None);
+    }
+
     #[test]
     fn test_invalid_proofs() {
         let tree = merkle_tree();
@@ -225,23 +833,26 @@ mod tests {
         let invalid_proof_wrong_index = MerkleProof {
             hash_chain: proof1.hash_chain.clone(),
             index: proof2.index,
+            shape: None,
             phantom: PhantomData,
         };
-        assert!(!invalid_proof_wrong_index.verify(tree.root_hash, &vec![43]));
+        assert!(!invalid_proof_wrong_index.verify(*tree.get_root_hash(), &vec![43]));
 
         let invalid_proof_wrong_hash_chain = MerkleProof {
             hash_chain: proof2.hash_chain.clone(),
             index: proof1.index,
+            shape: None,
             phantom: PhantomData,
         };
-        assert!(!invalid_proof_wrong_hash_chain.verify(tree.root_hash, &vec![43]));
+        assert!(!invalid_proof_wrong_hash_chain.verify(*tree.get_root_hash(), &vec![43]));
 
         let invalid_proof_wrong_data = MerkleProof {
             hash_chain: proof2.hash_chain.clone(),
             index: proof2.index,
+            shape: None,
             phantom: PhantomData,
         };
-        assert!(!invalid_proof_wrong_data.verify(tree.root_hash, &vec![43]));
+        assert!(!invalid_proof_wrong_data.verify(*tree.get_root_hash(), &vec![43]));
     }
 
     #[test]
@@ -251,4 +862,42 @@ mod tests {
         let proof = tree.get_proof(43);
         assert!(proof.verify(*tree.get_root_hash(), &(43, 44, (45,))));
     }
+
+    #[test]
+    fn test_try_new_not_a_power_of_two() {
+        let elements: Vec<u8> = (0..12).collect();
+        assert_eq!(
+            MerkleTree::try_new(&elements).unwrap_err(),
+            Error::NotAPowerOfTwo(12)
+        );
+        assert_eq!(
+            MerkleTree::<u8>::try_new(&[]).unwrap_err(),
+            Error::NotAPowerOfTwo(0)
+        );
+    }
+
+    #[test]
+    fn test_try_get_proof_out_of_range() {
+        let tree = merkle_tree();
+        assert_eq!(
+            tree.try_get_proof(128).unwrap_err(),
+            Error::IndexOutOfRange {
+                index: 128,
+                size: 128
+            }
+        );
+    }
+
+    #[test]
+    fn test_rejects_index_not_matching_hash_chain() {
+        let tree = merkle_tree();
+        let proof = tree.get_proof(43);
+        let invalid_proof = MerkleProof {
+            hash_chain: proof.hash_chain.clone(),
+            index: proof.index + 128,
+            shape: None,
+            phantom: PhantomData,
+        };
+        assert!(!invalid_proof.verify(*tree.get_root_hash(), &vec![43]));
+    }
 }
@@ -0,0 +1,317 @@
+use crate::signature::HashType;
+use crate::utils::hash;
+use serde::{Deserialize, Serialize};
+
+/// A canonical byte encoding of values that are stored in the leaves of a Merkle tree.
+///
+/// Unlike MessagePack, the encoding is simple enough to be reproduced by other implementations:
+/// - Integers are encoded as fixed-width big-endian values, `usize` as 64 bits.
+/// - Fixed-size byte arrays (e.g. hashes) are encoded as is.
+/// - Variable-length values are prefixed with their length as a 64-bit big-endian integer:
+///   the number of bytes for byte vectors and strings, and the number of hashes for
+///   vectors of hashes (like `WinternitzKey`).
+/// - Tuples are encoded as the concatenation of their elements.
+///
+/// Because every encoding is self-delimiting, the encoding of a tuple is unambiguous as well.
+pub trait LeafEncode {
+    /// Appends the encoding of `self` to `out`.
+    fn encode_leaf(&self, out: &mut Vec<u8>);
+}
+
+/// Prefix of the encoded leaves of the canonical encoding, as in RFC 6962.
+pub const LEAF_PREFIX: u8 = 0;
+/// Prefix of the children of internal nodes of the canonical encoding, as in RFC 6962.
+pub const NODE_PREFIX: u8 = 1;
+
+/// The version of the encoding used to hash leaves, see `LeafEncoding::leaf_hash()`.
+///
+/// The version determines how leaves and internal nodes are hashed (see `LeafEncoding::leaf_hash()`
+/// and `LeafEncoding::node_hash()`).
+/// Changing the encoding changes all root hashes, e.g. the public keys of the signature schemes.
+/// Keys and signatures created before the canonical encoding was introduced use
+/// `LeafEncoding::MessagePack` and can still be used, but need to be verified explicitly
+/// using that encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "u8", try_from = "u8")]
+pub enum LeafEncoding {
+    /// Version 0: The leaf is encoded using `rmp_serde`.
+    MessagePack = 0,
+    /// Version 1: The leaf is encoded using `LeafEncode`.
+    Canonical = 1,
+}
+
+impl LeafEncoding {
+    /// The encoding used for new trees and keys.
+    pub const CURRENT: LeafEncoding = LeafEncoding::Canonical;
+
+    /// The encoding of data that does not specify its version.
+    pub fn legacy() -> Self {
+        LeafEncoding::MessagePack
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        *self == Self::legacy()
+    }
+
+    /// Hash function applied to leaves of the Merkle tree.
+    ///
+    /// For leafs, we need to use a different hash function for security:
+    /// https://crypto.stackexchange.com/questions/2106/what-is-the-purpose-of-using-different-hash-functions-for-the-leaves-and-interna
+    /// The canonical encoding follows RFC 6962 and prefixes leaves with `LEAF_PREFIX` and
+    /// internal nodes with `NODE_PREFIX` (see `LeafEncoding::node_hash()`).
+    /// The legacy MessagePack encoding appends a zero to all encoded leaves instead.
+    ///
+    /// The encoding is part of the key, so verifiers need to know it instead of taking it from
+    /// a signature (see `QIndexedSignatureScheme::verify_with_encoding()`).
+    ///
+    /// # Panics
+    /// Panics if the data can't be serialized using MessagePack.
+    pub fn leaf_hash<T: Serialize + LeafEncode + ?Sized>(&self, data: &T) -> HashType {
+        hash(&self.encode(data))
+    }
+
+    /// The input of the leaf hash, see `LeafEncoding::leaf_hash()`.
+    pub(crate) fn encode<T: Serialize + LeafEncode + ?Sized>(&self, data: &T) -> Vec<u8> {
+        match self {
+            LeafEncoding::MessagePack => {
+                let mut encoded = Vec::new();
+                rmp_serde::encode::write(&mut encoded, data).expect("Failed to serialize data");
+                encoded.push(0);
+                encoded
+            }
+            LeafEncoding::Canonical => canonical_encode(data),
+        }
+    }
+
+    /// Hash function applied to a group of nodes, e.g. the two children of a node in a
+    /// binary tree. A single node is promoted as is.
+    ///
+    /// The canonical encoding prefixes the children with `NODE_PREFIX`, the legacy
+    /// MessagePack encoding hashes their concatenation.
+    pub fn node_hash(&self, children: &[HashType]) -> HashType {
+        match (self, children) {
+            (_, [child]) => *child,
+            (LeafEncoding::MessagePack, _) => hash(&children.concat()),
+            (LeafEncoding::Canonical, _) => {
+                let mut encoded = Vec::with_capacity(1 + 32 * children.len());
+                encoded.push(NODE_PREFIX);
+                encoded.extend(children.iter().flatten());
+                hash(&encoded)
+            }
+        }
+    }
+}
+
+/// Leaf hash of the canonical encoding, `SHA256(LEAF_PREFIX || encode_leaf(data))`.
+///
+/// Unlike `LeafEncoding::leaf_hash()`, this does not require the data to implement `Serialize`.
+pub fn canonical_leaf_hash<T: LeafEncode + ?Sized>(data: &T) -> HashType {
+    hash(&canonical_encode(data))
+}
+
+fn canonical_encode<T: LeafEncode + ?Sized>(data: &T) -> Vec<u8> {
+    let mut encoded = vec![LEAF_PREFIX];
+    data.encode_leaf(&mut encoded);
+    encoded
+}
+
+impl From<LeafEncoding> for u8 {
+    fn from(encoding: LeafEncoding) -> u8 {
+        encoding as u8
+    }
+}
+
+impl TryFrom<u8> for LeafEncoding {
+    type Error = String;
+
+    fn try_from(version: u8) -> Result<Self, String> {
+        match version {
+            0 => Ok(LeafEncoding::MessagePack),
+            1 => Ok(LeafEncoding::Canonical),
+            _ => Err(format!("Unknown leaf encoding version {version}")),
+        }
+    }
+}
+
+fn encode_length(length: usize, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(length as u64).to_be_bytes());
+}
+
+macro_rules! impl_leaf_encode_for_integers {
+    ($($t:ty),*) => {
+        $(
+            impl LeafEncode for $t {
+                fn encode_leaf(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_be_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_leaf_encode_for_integers!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl LeafEncode for usize {
+    fn encode_leaf(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode_leaf(out);
+    }
+}
+
+impl LeafEncode for HashType {
+    fn encode_leaf(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+impl LeafEncode for [u8] {
+    fn encode_leaf(&self, out: &mut Vec<u8>) {
+        encode_length(self.len(), out);
+        out.extend_from_slice(self);
+    }
+}
+
+impl LeafEncode for Vec<u8> {
+    fn encode_leaf(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_leaf(out);
+    }
+}
+
+impl LeafEncode for str {
+    fn encode_leaf(&self, out: &mut Vec<u8>) {
+        self.as_bytes().encode_leaf(out);
+    }
+}
+
+impl LeafEncode for String {
+    fn encode_leaf(&self, out: &mut Vec<u8>) {
+        self.as_str().encode_leaf(out);
+    }
+}
+
+impl LeafEncode for [HashType] {
+    fn encode_leaf(&self, out: &mut Vec<u8>) {
+        encode_length(self.len(), out);
+        for hash in self {
+            out.extend_from_slice(hash);
+        }
+    }
+}
+
+/// Also the encoding of `WinternitzKey`.
+impl LeafEncode for Vec<HashType> {
+    fn encode_leaf(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_leaf(out);
+    }
+}
+
+impl<T: LeafEncode + ?Sized> LeafEncode for &T {
+    fn encode_leaf(&self, out: &mut Vec<u8>) {
+        (**self).encode_leaf(out);
+    }
+}
+
+macro_rules! impl_leaf_encode_for_tuples {
+    ($(($($name:ident),+)),*) => {
+        $(
+            impl<$($name: LeafEncode),+> LeafEncode for ($($name,)+) {
+                #[allow(non_snake_case)]
+                fn encode_leaf(&self, out: &mut Vec<u8>) {
+                    let ($($name,)+) = self;
+                    $($name.encode_leaf(out);)+
+                }
+            }
+        )*
+    };
+}
+
+impl_leaf_encode_for_tuples!((A), (A, B), (A, B, C), (A, B, C, D));
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::encoding::{canonical_leaf_hash, LeafEncode, LeafEncoding};
+    use crate::signature::winternitz::WinternitzKey;
+    use crate::utils::hash;
+
+    fn encode<T: LeafEncode + ?Sized>(data: &T) -> Vec<u8> {
+        let mut out = Vec::new();
+        data.encode_leaf(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(encode(&0x0102u16), vec![1, 2]);
+        assert_eq!(encode(&1usize), vec![0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(encode(&[7u8; 32]), vec![7u8; 32]);
+        assert_eq!(encode(&vec![1u8, 2]), vec![0, 0, 0, 0, 0, 0, 0, 2, 1, 2]);
+        assert_eq!(encode("ab"), vec![0, 0, 0, 0, 0, 0, 0, 2, b'a', b'b']);
+        assert_eq!(encode(&(1u8, 2u8)), vec![1, 2]);
+
+        let key: WinternitzKey = vec![[1u8; 32], [2u8; 32]];
+        let expected = [&[0, 0, 0, 0, 0, 0, 0, 2], &[1u8; 32] as &[u8], &[2u8; 32]].concat();
+        assert_eq!(encode(&key), expected);
+    }
+
+    #[test]
+    fn test_length_prefix_makes_tuples_unambiguous() {
+        let a = (vec![1u8], vec![2u8, 3]);
+        let b = (vec![1u8, 2], vec![3u8]);
+        assert_ne!(encode(&a), encode(&b));
+    }
+
+    #[test]
+    fn test_leaf_hash() {
+        let data = vec![1u8, 2];
+        assert_eq!(
+            LeafEncoding::Canonical.leaf_hash(&data),
+            hash(&[0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2])
+        );
+        assert_eq!(
+            canonical_leaf_hash(&data),
+            LeafEncoding::Canonical.leaf_hash(&data)
+        );
+        assert_eq!(
+            LeafEncoding::MessagePack.leaf_hash(&data),
+            hash(&[&rmp_serde::to_vec(&data).unwrap() as &[u8], &[0]].concat())
+        );
+    }
+
+    #[test]
+    fn test_node_hash() {
+        let (left, right) = ([1u8; 32], [2u8; 32]);
+        assert_eq!(
+            LeafEncoding::Canonical.node_hash(&[left, right]),
+            hash(&[&[1u8] as &[u8], &left, &right].concat())
+        );
+        assert_eq!(
+            LeafEncoding::MessagePack.node_hash(&[left, right]),
+            hash(&[left, right].concat())
+        );
+        for encoding in [LeafEncoding::MessagePack, LeafEncoding::Canonical] {
+            assert_eq!(encoding.node_hash(&[left]), left);
+        }
+    }
+
+    #[test]
+    fn test_canonical_leaves_and_nodes_are_separated() {
+        // A leaf can't have the same hash input as a node, even if its encoding is 64 bytes long
+        let (left, right) = ([1u8; 32], [2u8; 32]);
+        assert_ne!(
+            canonical_leaf_hash(&(left, right)),
+            LeafEncoding::Canonical.node_hash(&[left, right])
+        );
+    }
+
+    #[test]
+    fn test_version_serialization() {
+        for encoding in [LeafEncoding::MessagePack, LeafEncoding::Canonical] {
+            let json = serde_json::to_string(&encoding).unwrap();
+            assert_eq!(json, (encoding as u8).to_string());
+            assert_eq!(
+                serde_json::from_str::<LeafEncoding>(&json).unwrap(),
+                encoding
+            );
+        }
+        assert!(serde_json::from_str::<LeafEncoding>("2").is_err());
+    }
+}
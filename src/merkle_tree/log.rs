@@ -0,0 +1,715 @@
+use crate::error::Error;
+use crate::signature::key_format::VersionedPublicKey;
+use crate::signature::stateless_merkle::{
+    StatelessMerkleSignature, StatelessMerkleSignatureScheme,
+};
+use crate::signature::HashType;
+use crate::utils::{ct_eq, hash, slice_to_hash};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Context string used to sign tree heads, see `TreeHead::sign()`.
+pub const TREE_HEAD_CONTEXT: &[u8] = b"hash-based-signatures merkle log tree head";
+
+/// Hash of a log entry, as defined in RFC 6962: `SHA256(0x00 || data)`
+pub fn leaf_hash(data: &[u8]) -> HashType {
+    hash(&[&[0u8], data].concat())
+}
+
+/// Hash of an internal node, as defined in RFC 6962: `SHA256(0x01 || left || right)`
+pub fn node_hash(left: &HashType, right: &HashType) -> HashType {
+    hash(&[&[1u8], left.as_slice(), right.as_slice()].concat())
+}
+
+/// Largest power of two smaller than `n`, for `n > 1`.
+fn split_point(n: usize) -> usize {
+    1 << (usize::BITS - (n - 1).leading_zeros() - 1)
+}
+
+/// An append-only Merkle log, following the semantics of
+/// [RFC 6962](https://www.rfc-editor.org/rfc/rfc6962) and [RFC 9162](https://www.rfc-editor.org/rfc/rfc9162).
+///
+/// Entries are appended one at a time. For any tree size, the log can prove that an entry is
+/// included in the tree (`MerkleLog::inclusion_proof()`), and that the tree is an extension of
+/// the tree of a smaller size (`MerkleLog::consistency_proof()`).
+///
+/// Unlike `MerkleTree`, the leaf and node hashes follow RFC 6962, so the proofs can be verified
+/// by other implementations.
+///
+/// Besides the leaves, the log keeps the root hashes of all complete subtrees whose size is a
+/// power of two, which are never modified once they are created. Appending an entry only
+/// hashes the subtrees that are completed by it, i.e. `O(1)` hashes on average, and root hashes
+/// and proofs for any tree size are assembled from `O(log n)` of those subtrees.
+///
+/// The log can be backed by a file, which stores the 32-byte leaf hashes one after another.
+/// When opening the file, a partially written trailing leaf hash (e.g. of an append that was
+/// interrupted) is truncated.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::merkle_tree::log::{leaf_hash, MerkleLog};
+///
+/// let mut log = MerkleLog::new();
+/// log.append(b"first entry").unwrap();
+/// let old_head = log.tree_head();
+/// log.append(b"second entry").unwrap();
+/// let new_head = log.tree_head();
+///
+/// let proof = log.inclusion_proof(0, 2).unwrap();
+/// assert!(proof.verify(&leaf_hash(b"first entry"), &new_head));
+///
+/// let proof = log.consistency_proof(1, 2).unwrap();
+/// assert!(proof.verify(&old_head, &new_head));
+/// ```
+pub struct MerkleLog {
+    /// The root hashes of the complete subtrees of each height, starting with the leaf hashes.
+    /// The `i`-th hash of height `h` is the root hash of the leaves `i * 2^h..(i + 1) * 2^h`.
+    subtrees: Vec<Vec<HashType>>,
+    file: Option<File>,
+}
+
+/// The size and root hash of the log at some point in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeHead {
+    pub tree_size: usize,
+    pub root_hash: HashType,
+}
+
+/// A tree head, signed by the operator of the log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignedTreeHead {
+    pub tree_head: TreeHead,
+    pub signature: StatelessMerkleSignature,
+}
+
+/// A proof that the leaf at `leaf_index` is included in the tree of size `tree_size`.
+///
+/// The tree size is only included for reference: The proof is verified against a trusted
+/// `TreeHead`, whose size it needs to match.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub leaf_index: usize,
+    pub tree_size: usize,
+    pub path: Vec<HashType>,
+}
+
+/// A proof that the tree of size `new_size` is an extension of the tree of size `old_size`.
+///
+/// Like for `InclusionProof`, the sizes need to match the trusted `TreeHead`s it is
+/// verified against.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsistencyProof {
+    pub old_size: usize,
+    pub new_size: usize,
+    pub path: Vec<HashType>,
+}
+
+impl Default for MerkleLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MerkleLog {
+    /// Creates an empty log that is only kept in memory.
+    pub fn new() -> Self {
+        Self {
+            subtrees: vec![Vec::new()],
+            file: None,
+        }
+    }
+
+    /// Opens the log stored in the file at `path`, creating it if it doesn't exist.
+    /// All entries appended to the log are written to the file.
+    ///
+    /// If the length of the file is not a multiple of 32 bytes, the last append was interrupted
+    /// and its partial leaf hash is removed from the file.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        let complete_length = contents.len() - contents.len() % 32;
+        if complete_length != contents.len() {
+            file.set_len(complete_length as u64)?;
+            file.sync_data()?;
+        }
+
+        let mut log = Self::new();
+        for leaf_hash in contents[..complete_length].chunks_exact(32) {
+            log.push_leaf_hash(slice_to_hash(leaf_hash));
+        }
+        log.file = Some(file);
+        Ok(log)
+    }
+
+    /// Appends an entry to the log, returning its index.
+    pub fn append(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.append_leaf_hash(leaf_hash(data))
+    }
+
+    /// Appends an entry, given by its leaf hash, to the log, returning its index.
+    pub fn append_leaf_hash(&mut self, leaf_hash: HashType) -> std::io::Result<usize> {
+        if let Some(file) = &mut self.file {
+            file.write_all(&leaf_hash)?;
+            file.sync_data()?;
+        }
+        self.push_leaf_hash(leaf_hash);
+        Ok(self.size() - 1)
+    }
+
+    /// Adds the leaf hash and the root hashes of the subtrees completed by it.
+    fn push_leaf_hash(&mut self, leaf_hash: HashType) {
+        let mut node = leaf_hash;
+        let mut height = 0;
+        loop {
+            self.subtrees[height].push(node);
+            let level = &self.subtrees[height];
+            if level.len() % 2 == 1 {
+                break;
+            }
+            node = node_hash(&level[level.len() - 2], &node);
+            height += 1;
+            if height == self.subtrees.len() {
+                self.subtrees.push(Vec::new());
+            }
+        }
+    }
+
+    /// The number of entries in the log.
+    pub fn size(&self) -> usize {
+        self.subtrees[0].len()
+    }
+
+    /// The leaf hash of the entry at `index`.
+    pub fn leaf_hash(&self, index: usize) -> Option<&HashType> {
+        self.subtrees[0].get(index)
+    }
+
+    /// The tree head of the current tree.
+    pub fn tree_head(&self) -> TreeHead {
+        TreeHead {
+            tree_size: self.size(),
+            root_hash: self.tree_hash(0, self.size()),
+        }
+    }
+
+    /// The root hash of the tree of the first `tree_size` entries.
+    pub fn root_hash(&self, tree_size: usize) -> Result<HashType, Error> {
+        self.check_tree_size(tree_size)?;
+        Ok(self.tree_hash(0, tree_size))
+    }
+
+    /// Proves that the entry at `leaf_index` is included in the tree of size `tree_size`.
+    pub fn inclusion_proof(
+        &self,
+        leaf_index: usize,
+        tree_size: usize,
+    ) -> Result<InclusionProof, Error> {
+        self.check_tree_size(tree_size)?;
+        if leaf_index >= tree_size {
+            return Err(Error::IndexOutOfRange {
+                index: leaf_index,
+                size: tree_size,
+            });
+        }
+        Ok(InclusionProof {
+            leaf_index,
+            tree_size,
+            path: self.inclusion_path(leaf_index, 0, tree_size),
+        })
+    }
+
+    /// Proves that the tree of size `new_size` is an extension of the tree of size `old_size`.
+    ///
+    /// Fails unless `0 < old_size <= new_size <= self.size()`.
+    pub fn consistency_proof(
+        &self,
+        old_size: usize,
+        new_size: usize,
+    ) -> Result<ConsistencyProof, Error> {
+        self.check_tree_size(new_size)?;
+        if old_size == 0 || old_size > new_size {
+            return Err(Error::InvalidTreeSize {
+                size: old_size,
+                max_size: new_size,
+            });
+        }
+        Ok(ConsistencyProof {
+            old_size,
+            new_size,
+            path: self.consistency_path(old_size, 0, new_size, true),
+        })
+    }
+
+    fn check_tree_size(&self, tree_size: usize) -> Result<(), Error> {
+        if tree_size > self.size() {
+            return Err(Error::InvalidTreeSize {
+                size: tree_size,
+                max_size: self.size(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The Merkle tree hash `MTH` of RFC 6962 of the `len` leaves starting at `start`.
+    ///
+    /// The left subtree of each split is complete, so its root hash is looked up, and only
+    /// the right subtrees are hashed.
+    fn tree_hash(&self, start: usize, len: usize) -> HashType {
+        if len == 0 {
+            return hash(&[]);
+        }
+        let height = len.trailing_zeros() as usize;
+        if len.is_power_of_two() && start % len == 0 {
+            return self.subtrees[height][start >> height];
+        }
+        let k = split_point(len);
+        node_hash(
+            &self.tree_hash(start, k),
+            &self.tree_hash(start + k, len - k),
+        )
+    }
+
+    /// The audit path `PATH(m, D[start:start + len])` of RFC 6962.
+    fn inclusion_path(&self, m: usize, start: usize, len: usize) -> Vec<HashType> {
+        if len <= 1 {
+            return vec![];
+        }
+        let k = split_point(len);
+        let (mut path, sibling) = if m < k {
+            (
+                self.inclusion_path(m, start, k),
+                self.tree_hash(start + k, len - k),
+            )
+        } else {
+            (
+                self.inclusion_path(m - k, start + k, len - k),
+                self.tree_hash(start, k),
+            )
+        };
+        path.push(sibling);
+        path
+    }
+
+    /// The consistency proof `SUBPROOF(m, D[start:start + len], b)` of RFC 6962.
+    fn consistency_path(
+        &self,
+        m: usize,
+        start: usize,
+        len: usize,
+        complete_subtree: bool,
+    ) -> Vec<HashType> {
+        if m == len {
+            return if complete_subtree {
+                vec![]
+            } else {
+                vec![self.tree_hash(start, len)]
+            };
+        }
+        let k = split_point(len);
+        let (mut path, sibling) = if m <= k {
+            (
+                self.consistency_path(m, start, k, complete_subtree),
+                self.tree_hash(start + k, len - k),
+            )
+        } else {
+            (
+                self.consistency_path(m - k, start + k, len - k, false),
+                self.tree_hash(start, k),
+            )
+        };
+        path.push(sibling);
+        path
+    }
+}
+
+impl InclusionProof {
+    /// Verifies that the entry with the given `leaf_hash` is included in the tree described
+    /// by `tree_head`, following Section 2.1.3.2 of RFC 9162.
+    ///
+    /// The tree head needs to come from a trusted source, e.g. a verified `SignedTreeHead`.
+    /// The tree size is taken from it, not from the proof, because the size determines the
+    /// position of the leaf that the path proves.
+    pub fn verify(&self, leaf_hash: &HashType, tree_head: &TreeHead) -> bool {
+        let tree_size = tree_head.tree_size;
+        if self.tree_size != tree_size || self.leaf_index >= tree_size {
+            return false;
+        }
+
+        let mut f_n = self.leaf_index;
+        let mut s_n = tree_size - 1;
+        let mut r = *leaf_hash;
+        for p in &self.path {
+            if s_n == 0 {
+                return false;
+            }
+            if f_n & 1 == 1 || f_n == s_n {
+                r = node_hash(p, &r);
+                while f_n & 1 == 0 && f_n != 0 {
+                    f_n >>= 1;
+                    s_n >>= 1;
+                }
+            } else {
+                r = node_hash(&r, p);
+            }
+            f_n >>= 1;
+            s_n >>= 1;
+        }
+
+        s_n == 0 && ct_eq(&r, &tree_head.root_hash)
+    }
+}
+
+impl ConsistencyProof {
+    /// Verifies that the tree described by `new_head` is an extension of the tree described
+    /// by `old_head`, following Section 2.1.4.2 of RFC 9162.
+    ///
+    /// Both tree heads need to come from a trusted source, see `InclusionProof::verify()`.
+    pub fn verify(&self, old_head: &TreeHead, new_head: &TreeHead) -> bool {
+        let (old_size, new_size) = (old_head.tree_size, new_head.tree_size);
+        let (old_root_hash, new_root_hash) = (&old_head.root_hash, &new_head.root_hash);
+        if self.old_size != old_size || self.new_size != new_size {
+            return false;
+        }
+        if old_size == 0 || old_size > new_size {
+            return false;
+        }
+        if old_size == new_size {
+            return self.path.is_empty() && ct_eq(old_root_hash, new_root_hash);
+        }
+
+        let mut path = self.path.clone();
+        if old_size.is_power_of_two() {
+            path.insert(0, *old_root_hash);
+        }
+        let Some((first, path)) = path.split_first() else {
+            return false;
+        };
+
+        let mut f_n = old_size - 1;
+        let mut s_n = new_size - 1;
+        while f_n & 1 == 1 {
+            f_n >>= 1;
+            s_n >>= 1;
+        }
+
+        let mut f_r = *first;
+        let mut s_r = *first;
+        for c in path {
+            if s_n == 0 {
+                return false;
+            }
+            if f_n & 1 == 1 || f_n == s_n {
+                f_r = node_hash(c, &f_r);
+                s_r = node_hash(c, &s_r);
+                while f_n & 1 == 0 && f_n != 0 {
+                    f_n >>= 1;
+                    s_n >>= 1;
+                }
+            } else {
+                s_r = node_hash(&s_r, c);
+            }
+            f_n >>= 1;
+            s_n >>= 1;
+        }
+
+        s_n == 0 && ct_eq(&f_r, old_root_hash) && ct_eq(&s_r, new_root_hash)
+    }
+}
+
+impl TreeHead {
+    /// The message that is signed: the SHA256 hash of the 8-byte big-endian tree size,
+    /// followed by the root hash.
+    fn message(&self) -> HashType {
+        hash(
+            &[
+                &(self.tree_size as u64).to_be_bytes(),
+                self.root_hash.as_slice(),
+            ]
+            .concat(),
+        )
+    }
+
+    /// Signs the tree head, using `TREE_HEAD_CONTEXT` as the context string.
+    pub fn sign(&self, signature_scheme: &StatelessMerkleSignatureScheme) -> SignedTreeHead {
+        let signature = signature_scheme
+            .sign_with_context(self.message(), TREE_HEAD_CONTEXT)
+            .expect("Context is short enough");
+        SignedTreeHead {
+            tree_head: *self,
+            signature,
+        }
+    }
+}
+
+impl SignedTreeHead {
+    /// Verifies the signature of the tree head, for a key in the format of `public_key`.
+    pub fn verify(&self, public_key: &VersionedPublicKey) -> bool {
+        StatelessMerkleSignatureScheme::verify_versioned(
+            public_key,
+            &self.tree_head.message(),
+            TREE_HEAD_CONTEXT,
+            &self.signature,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::merkle_tree::log::{leaf_hash, MerkleLog, TreeHead};
+    use crate::merkle_tree::mmr::MerkleMountainRange;
+    use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+    use crate::signature::winternitz::d::D;
+    use crate::signature::Signer;
+    use crate::utils::string_to_hash;
+    use data_encoding::HEXLOWER;
+    use std::fs;
+
+    /// Test vectors of the Certificate Transparency reference implementation
+    fn test_log() -> MerkleLog {
+        let mut log = MerkleLog::new();
+        for entry in [
+            "",
+            "00",
+            "10",
+            "2021",
+            "3031",
+            "40414243",
+            "5051525354555657",
+            "606162636465666768696a6b6c6d6e6f",
+        ] {
+            log.append(&HEXLOWER.decode(entry.as_bytes()).unwrap())
+                .unwrap();
+        }
+        log
+    }
+
+    #[test]
+    fn test_root_hashes() {
+        let log = test_log();
+        let expected_root_hashes = [
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+            "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+            "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+            "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+            "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+            "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+        ];
+        for (tree_size, expected_root_hash) in expected_root_hashes.iter().enumerate() {
+            assert_eq!(
+                log.root_hash(tree_size).unwrap(),
+                string_to_hash(expected_root_hash)
+            );
+        }
+        assert_eq!(
+            log.tree_head().root_hash,
+            string_to_hash(expected_root_hashes[8])
+        );
+    }
+
+    fn tree_head(log: &MerkleLog, tree_size: usize) -> TreeHead {
+        TreeHead {
+            tree_size,
+            root_hash: log.root_hash(tree_size).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_inclusion_proofs() {
+        let log = test_log();
+        for tree_size in 1..=8 {
+            let tree_head = tree_head(&log, tree_size);
+            for leaf_index in 0..tree_size {
+                let leaf_hash = log.leaf_hash(leaf_index).unwrap();
+                let proof = log.inclusion_proof(leaf_index, tree_size).unwrap();
+                assert!(proof.verify(leaf_hash, &tree_head));
+
+                let other_leaf_hash = log.leaf_hash((leaf_index + 1) % 8).unwrap();
+                assert!(!proof.verify(other_leaf_hash, &tree_head));
+
+                let mut wrong_index = proof.clone();
+                wrong_index.leaf_index = (leaf_index + 1) % tree_size;
+                if tree_size > 1 {
+                    assert!(!wrong_index.verify(leaf_hash, &tree_head));
+                }
+
+                let mut additional_hash = proof;
+                additional_hash.path.push([0u8; 32]);
+                assert!(!additional_hash.verify(leaf_hash, &tree_head));
+            }
+        }
+    }
+
+    #[test]
+    fn test_inclusion_proof_size_comes_from_tree_head() {
+        // In the tree of size 3, the path of leaf 2 is the root of the first two leaves, which is
+        // also a valid path for leaf 1 in a tree of size 2 with the same root hash.
+        let log = test_log();
+        let tree_head = tree_head(&log, 3);
+        let leaf_hash = log.leaf_hash(2).unwrap();
+        let mut forged = log.inclusion_proof(2, 3).unwrap();
+        forged.leaf_index = 1;
+        forged.tree_size = 2;
+        assert!(!forged.verify(leaf_hash, &tree_head));
+        forged.tree_size = 3;
+        assert!(!forged.verify(leaf_hash, &tree_head));
+    }
+
+    #[test]
+    fn test_inclusion_proof_vector() {
+        // Proof for leaf 0 in the tree of size 8 from the reference implementation
+        let log = test_log();
+        let proof = log.inclusion_proof(0, 8).unwrap();
+        let expected_path: Vec<[u8; 32]> = [
+            "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+        ]
+        .iter()
+        .map(|h| string_to_hash(h))
+        .collect();
+        assert_eq!(proof.path, expected_path);
+    }
+
+    #[test]
+    fn test_consistency_proofs() {
+        let log = test_log();
+        for new_size in 1..=8 {
+            let new_head = tree_head(&log, new_size);
+            for old_size in 1..=new_size {
+                let old_head = tree_head(&log, old_size);
+                let proof = log.consistency_proof(old_size, new_size).unwrap();
+                assert!(proof.verify(&old_head, &new_head));
+
+                if old_size < new_size {
+                    assert!(!proof.verify(&new_head, &new_head));
+                    assert!(!proof.verify(&old_head, &old_head));
+
+                    // The sizes of the proof need to match the trusted tree heads
+                    let wrong_old_size = TreeHead {
+                        tree_size: new_size,
+                        root_hash: old_head.root_hash,
+                    };
+                    assert!(!proof.verify(&wrong_old_size, &new_head));
+
+                    let mut missing_hash = proof.clone();
+                    missing_hash.path.pop();
+                    assert!(!missing_hash.verify(&old_head, &new_head));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_sizes() {
+        let log = test_log();
+        assert_eq!(
+            log.root_hash(9),
+            Err(Error::InvalidTreeSize {
+                size: 9,
+                max_size: 8
+            })
+        );
+        assert_eq!(
+            log.inclusion_proof(5, 5),
+            Err(Error::IndexOutOfRange { index: 5, size: 5 })
+        );
+        assert_eq!(
+            log.consistency_proof(0, 5),
+            Err(Error::InvalidTreeSize {
+                size: 0,
+                max_size: 5
+            })
+        );
+        assert_eq!(
+            log.consistency_proof(6, 5),
+            Err(Error::InvalidTreeSize {
+                size: 6,
+                max_size: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_larger_log() {
+        let mut log = MerkleLog::new();
+        let mut mmr = MerkleMountainRange::new();
+        for i in 0..100u8 {
+            log.append(&[i]).unwrap();
+            mmr.append(&[i]).unwrap();
+        }
+        for tree_size in [1, 2, 3, 31, 32, 33, 64, 99, 100] {
+            let tree_head = tree_head(&log, tree_size);
+            assert_eq!(
+                tree_head.root_hash,
+                mmr.root_at(tree_size).unwrap().root_hash
+            );
+            for leaf_index in [0, tree_size / 2, tree_size - 1] {
+                let proof = log.inclusion_proof(leaf_index, tree_size).unwrap();
+                assert!(proof.verify(log.leaf_hash(leaf_index).unwrap(), &tree_head));
+            }
+            let proof = log.consistency_proof(tree_size, 100).unwrap();
+            assert!(proof.verify(&tree_head, &log.tree_head()));
+        }
+    }
+
+    #[test]
+    fn test_file_backed_log() {
+        let path = std::env::temp_dir().join(format!("merkle_log_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        {
+            let mut log = MerkleLog::open(&path).unwrap();
+            assert_eq!(log.append(b"first").unwrap(), 0);
+            assert_eq!(log.append(b"second").unwrap(), 1);
+        }
+        assert_eq!(fs::metadata(&path).unwrap().len(), 64);
+
+        let mut log = MerkleLog::open(&path).unwrap();
+        assert_eq!(log.size(), 2);
+        assert_eq!(log.leaf_hash(1), Some(&leaf_hash(b"second")));
+        assert_eq!(log.append(b"third").unwrap(), 2);
+
+        // A partially written leaf hash is removed
+        let mut contents = fs::read(&path).unwrap();
+        contents.extend([0u8; 5]);
+        fs::write(&path, contents).unwrap();
+        let log = MerkleLog::open(&path).unwrap();
+        assert_eq!(log.size(), 3);
+        assert_eq!(log.leaf_hash(2), Some(&leaf_hash(b"third")));
+        assert_eq!(fs::metadata(&path).unwrap().len(), 96);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_signed_tree_head() {
+        let signature_scheme = StatelessMerkleSignatureScheme::new([0u8; 32], 16, 5, D::new(255));
+        let log = test_log();
+        let signed_tree_head = log.tree_head().sign(&signature_scheme);
+        assert!(signed_tree_head.verify(&signature_scheme.versioned_public_key()));
+
+        let mut wrong_size = signed_tree_head.clone();
+        wrong_size.tree_head.tree_size = 7;
+        assert!(!wrong_size.verify(&signature_scheme.versioned_public_key()));
+
+        // The signature can't be used as a signature of the root hash
+        assert!(!StatelessMerkleSignatureScheme::verify_with_context(
+            &signature_scheme.public_key(),
+            &signed_tree_head.tree_head.root_hash,
+            b"",
+            &signed_tree_head.signature
+        ));
+    }
+}
@@ -0,0 +1,589 @@
+use crate::error::Error;
+use crate::merkle_tree::log::{leaf_hash, node_hash};
+use crate::signature::key_format::VersionedPublicKey;
+use crate::signature::stateless_merkle::{
+    StatelessMerkleSignature, StatelessMerkleSignatureScheme,
+};
+use crate::signature::HashType;
+use crate::utils::{ct_eq, hash, slice_to_hash};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Context string used to sign MMR roots, see `MmrRoot::sign()`.
+pub const MMR_ROOT_CONTEXT: &[u8] = b"hash-based-signatures merkle mountain range root";
+
+/// The number of nodes of a Merkle Mountain Range with `leaf_count` leaves.
+fn node_count(leaf_count: usize) -> usize {
+    2 * leaf_count - leaf_count.count_ones() as usize
+}
+
+/// The position of the node with the given `height` that is the `index`-th node of that height.
+///
+/// Nodes are stored in the order in which they are created when appending leaves (post-order).
+/// The node is created when its last leaf `end - 1` is appended, after which all nodes of the
+/// first `end` leaves exist, except for its ancestors up to height `trailing_zeros(end)`.
+fn node_position(height: usize, index: usize) -> usize {
+    let end = (index + 1) << height;
+    node_count(end) - 1 - (end.trailing_zeros() as usize - height)
+}
+
+/// The heights of the mountains of a range with `leaf_count` leaves, from left to right.
+///
+/// Each set bit of `leaf_count` corresponds to a perfect binary tree (a "mountain"),
+/// from the highest to the lowest one.
+fn mountain_heights(leaf_count: usize) -> Vec<usize> {
+    (0..usize::BITS as usize)
+        .rev()
+        .filter(|height| leaf_count >> height & 1 == 1)
+        .collect()
+}
+
+/// The index of the mountain containing the leaf at `leaf_index`, and its height.
+fn mountain_of(leaf_index: usize, leaf_count: usize) -> (usize, usize) {
+    let mut start = 0;
+    for (mountain, height) in mountain_heights(leaf_count).into_iter().enumerate() {
+        start += 1 << height;
+        if leaf_index < start {
+            return (mountain, height);
+        }
+    }
+    unreachable!("Leaf index needs to be smaller than the leaf count")
+}
+
+/// Combines the peaks, from right to left, into a single root hash.
+///
+/// Because the mountains are sorted by decreasing height, this is the same as the
+/// Merkle tree hash of RFC 6962, so the root hash equals the root hash of a `MerkleLog`
+/// with the same entries.
+fn bag_peaks(peaks: &[HashType]) -> HashType {
+    match peaks.split_last() {
+        None => hash(&[]),
+        Some((last, rest)) => rest
+            .iter()
+            .rev()
+            .fold(*last, |right, left| node_hash(left, &right)),
+    }
+}
+
+/// A Merkle Mountain Range (MMR), an accumulator for append-heavy data.
+///
+/// The leaves form a list of perfect binary trees ("mountains") of decreasing height,
+/// whose root hashes ("peaks") are bagged into the root hash of the range. Appending a leaf
+/// only hashes the nodes that are completed by it, i.e. `O(1)` hashes on average.
+///
+/// Nodes are never modified once they are created. Therefore, the range can produce
+/// inclusion proofs (`MmrProof`) of any leaf for any previous size, and a proof can be
+/// extended to a larger size by only appending hashes (`MerkleMountainRange::extend_proof()`).
+///
+/// Leaf and node hashes are the same as for `MerkleLog`, and so are the root hashes.
+/// Like a `MerkleLog`, the range can be backed by a file, which stores all node hashes.
+/// When opening the file, the partially written nodes of an interrupted append are truncated.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::merkle_tree::log::leaf_hash;
+/// use hash_based_signatures::merkle_tree::mmr::MerkleMountainRange;
+///
+/// let mut mmr = MerkleMountainRange::new();
+/// mmr.append(b"first entry").unwrap();
+/// let old_root = mmr.root();
+/// let proof = mmr.inclusion_proof(0, 1).unwrap();
+/// mmr.append(b"second entry").unwrap();
+/// mmr.append(b"third entry").unwrap();
+///
+/// assert!(proof.verify(&leaf_hash(b"first entry"), &old_root));
+/// let proof = mmr.extend_proof(&proof, 3).unwrap();
+/// assert!(proof.verify(&leaf_hash(b"first entry"), &mmr.root()));
+/// ```
+pub struct MerkleMountainRange {
+    nodes: Vec<HashType>,
+    leaf_count: usize,
+    file: Option<File>,
+}
+
+/// The number of leaves and the root hash of the range at some point in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MmrRoot {
+    pub leaf_count: usize,
+    pub root_hash: HashType,
+}
+
+/// An MMR root, signed by the operator of the range.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignedMmrRoot {
+    pub root: MmrRoot,
+    pub signature: StatelessMerkleSignature,
+}
+
+/// A proof that the leaf at `leaf_index` is included in the range of `leaf_count` leaves.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MmrProof {
+    pub leaf_index: usize,
+    pub leaf_count: usize,
+    /// The sibling hashes from the leaf up to the peak of its mountain.
+    pub path: Vec<HashType>,
+    /// The peaks of all other mountains, from left to right.
+    pub peaks: Vec<HashType>,
+}
+
+impl Default for MerkleMountainRange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MerkleMountainRange {
+    /// Creates an empty range that is only kept in memory.
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            leaf_count: 0,
+            file: None,
+        }
+    }
+
+    /// Opens the range stored in the file at `path`, creating it if it doesn't exist.
+    /// All nodes created when appending to the range are written to the file.
+    ///
+    /// If the file ends with the partially written nodes of an interrupted append, they are
+    /// removed from the file.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        let mut nodes: Vec<HashType> = contents.chunks_exact(32).map(slice_to_hash).collect();
+        // The largest range whose nodes were written completely. The number of leaves is at
+        // most the number of nodes.
+        let leaf_count = (0..=nodes.len())
+            .take_while(|n| node_count(*n) <= nodes.len())
+            .last()
+            .unwrap_or(0);
+        if node_count(leaf_count) * 32 != contents.len() {
+            nodes.truncate(node_count(leaf_count));
+            file.set_len((nodes.len() * 32) as u64)?;
+            file.sync_data()?;
+        }
+
+        Ok(Self {
+            nodes,
+            leaf_count,
+            file: Some(file),
+        })
+    }
+
+    /// Appends an entry to the range, returning its index.
+    pub fn append(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.append_leaf_hash(leaf_hash(data))
+    }
+
+    /// Appends an entry, given by its leaf hash, to the range, returning its index.
+    pub fn append_leaf_hash(&mut self, leaf_hash: HashType) -> std::io::Result<usize> {
+        let leaf_index = self.leaf_count;
+
+        // The new leaf completes one mountain for each trailing one of its index
+        let mut new_nodes = vec![leaf_hash];
+        let mut node = leaf_hash;
+        for height in 0..leaf_index.trailing_ones() as usize {
+            let left = self.nodes[node_position(height, (leaf_index >> height) - 1)];
+            node = node_hash(&left, &node);
+            new_nodes.push(node);
+        }
+
+        if let Some(file) = &mut self.file {
+            file.write_all(&new_nodes.concat())?;
+            file.sync_data()?;
+        }
+        self.nodes.extend(new_nodes);
+        self.leaf_count += 1;
+        Ok(leaf_index)
+    }
+
+    /// The number of entries in the range.
+    pub fn size(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// The leaf hash of the entry at `index`.
+    pub fn leaf_hash(&self, index: usize) -> Option<&HashType> {
+        if index >= self.leaf_count {
+            return None;
+        }
+        self.nodes.get(node_position(0, index))
+    }
+
+    /// The root of the current range.
+    pub fn root(&self) -> MmrRoot {
+        MmrRoot {
+            leaf_count: self.leaf_count,
+            root_hash: bag_peaks(&self.peaks(self.leaf_count)),
+        }
+    }
+
+    /// The root of the range of the first `leaf_count` entries.
+    pub fn root_at(&self, leaf_count: usize) -> Result<MmrRoot, Error> {
+        self.check_leaf_count(leaf_count)?;
+        Ok(MmrRoot {
+            leaf_count,
+            root_hash: bag_peaks(&self.peaks(leaf_count)),
+        })
+    }
+
+    /// Proves that the entry at `leaf_index` is included in the range of `leaf_count` entries.
+    pub fn inclusion_proof(&self, leaf_index: usize, leaf_count: usize) -> Result<MmrProof, Error> {
+        self.check_leaf_count(leaf_count)?;
+        if leaf_index >= leaf_count {
+            return Err(Error::IndexOutOfRange {
+                index: leaf_index,
+                size: leaf_count,
+            });
+        }
+        let proof = MmrProof {
+            leaf_index,
+            leaf_count,
+            path: vec![],
+            peaks: vec![],
+        };
+        self.extend_proof(&proof, leaf_count)
+    }
+
+    /// Extends a proof for a smaller range to the range of `leaf_count` entries.
+    ///
+    /// The path of the new proof starts with the path of the given proof, because the mountain
+    /// of the leaf can only grow. Only the hashes above the old peak and the peaks are added.
+    /// Fails if `leaf_count` is smaller than the size of the proof or larger than the range.
+    pub fn extend_proof(&self, proof: &MmrProof, leaf_count: usize) -> Result<MmrProof, Error> {
+        self.check_leaf_count(leaf_count)?;
+        if proof.leaf_count > leaf_count {
+            return Err(Error::InvalidTreeSize {
+                size: proof.leaf_count,
+                max_size: leaf_count,
+            });
+        }
+
+        let (mountain, height) = mountain_of(proof.leaf_index, leaf_count);
+        let mut path = proof.path.clone();
+        path.truncate(height);
+        for level in path.len()..height {
+            let sibling = (proof.leaf_index >> level) ^ 1;
+            path.push(self.nodes[node_position(level, sibling)]);
+        }
+
+        let mut peaks = self.peaks(leaf_count);
+        peaks.remove(mountain);
+
+        Ok(MmrProof {
+            leaf_index: proof.leaf_index,
+            leaf_count,
+            path,
+            peaks,
+        })
+    }
+
+    /// The peaks of the range of the first `leaf_count` entries, from left to right.
+    fn peaks(&self, leaf_count: usize) -> Vec<HashType> {
+        let mut start = 0;
+        mountain_heights(leaf_count)
+            .into_iter()
+            .map(|height| {
+                start += 1 << height;
+                self.nodes[node_position(height, (start >> height) - 1)]
+            })
+            .collect()
+    }
+
+    fn check_leaf_count(&self, leaf_count: usize) -> Result<(), Error> {
+        if leaf_count > self.leaf_count {
+            return Err(Error::InvalidTreeSize {
+                size: leaf_count,
+                max_size: self.leaf_count,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl MmrProof {
+    /// Verifies that the entry with the given `leaf_hash` is included in the range with
+    /// the given `root`.
+    ///
+    /// Like for RFC 6962, the root hash alone does not determine the number of leaves,
+    /// which therefore needs to match as well.
+    pub fn verify(&self, leaf_hash: &HashType, root: &MmrRoot) -> bool {
+        if self.leaf_count != root.leaf_count {
+            return false;
+        }
+        match self.root_hash(leaf_hash) {
+            Some(expected_root_hash) => ct_eq(&expected_root_hash, &root.root_hash),
+            None => false,
+        }
+    }
+
+    /// Computes the root hash of the range from the proof and the leaf hash.
+    ///
+    /// Returns `None` for malformed proofs.
+    pub fn root_hash(&self, leaf_hash: &HashType) -> Option<HashType> {
+        if self.leaf_index >= self.leaf_count {
+            return None;
+        }
+        let (mountain, height) = mountain_of(self.leaf_index, self.leaf_count);
+        let mountain_count = self.leaf_count.count_ones() as usize;
+        if self.path.len() != height || self.peaks.len() != mountain_count - 1 {
+            return None;
+        }
+
+        let peak = self.peak_hash(leaf_hash);
+        let mut peaks = self.peaks.clone();
+        peaks.insert(mountain, peak);
+        Some(bag_peaks(&peaks))
+    }
+
+    /// The hash of the peak of the leaf's mountain, computed from the path and the leaf hash.
+    ///
+    /// The peak remains a node of the range when it grows, so the path proves the inclusion
+    /// of the leaf in any larger range that includes the peak.
+    pub fn peak_hash(&self, leaf_hash: &HashType) -> HashType {
+        self.path
+            .iter()
+            .enumerate()
+            .fold(*leaf_hash, |node, (level, sibling)| {
+                if self.leaf_index >> level & 1 == 0 {
+                    node_hash(&node, sibling)
+                } else {
+                    node_hash(sibling, &node)
+                }
+            })
+    }
+}
+
+impl MmrRoot {
+    /// The message that is signed: the SHA256 hash of the 8-byte big-endian number of leaves,
+    /// followed by the root hash.
+    fn message(&self) -> HashType {
+        hash(
+            &[
+                &(self.leaf_count as u64).to_be_bytes(),
+                self.root_hash.as_slice(),
+            ]
+            .concat(),
+        )
+    }
+
+    /// Signs the root, using `MMR_ROOT_CONTEXT` as the context string.
+    pub fn sign(&self, signature_scheme: &StatelessMerkleSignatureScheme) -> SignedMmrRoot {
+        let signature = signature_scheme
+            .sign_with_context(self.message(), MMR_ROOT_CONTEXT)
+            .expect("Context is short enough");
+        SignedMmrRoot {
+            root: *self,
+            signature,
+        }
+    }
+}
+
+impl SignedMmrRoot {
+    /// Verifies the signature of the root, for a key in the format of `public_key`.
+    pub fn verify(&self, public_key: &VersionedPublicKey) -> bool {
+        StatelessMerkleSignatureScheme::verify_versioned(
+            public_key,
+            &self.root.message(),
+            MMR_ROOT_CONTEXT,
+            &self.signature,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::merkle_tree::log::{leaf_hash, MerkleLog, SignedTreeHead, TreeHead};
+    use crate::merkle_tree::mmr::{node_count, node_position, MerkleMountainRange};
+    use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+    use crate::signature::winternitz::d::D;
+    use crate::signature::Signer;
+    use std::fs;
+
+    fn mmr_and_log(size: u8) -> (MerkleMountainRange, MerkleLog) {
+        let mut mmr = MerkleMountainRange::new();
+        let mut log = MerkleLog::new();
+        for i in 0..size {
+            mmr.append(&[i]).unwrap();
+            log.append(&[i]).unwrap();
+        }
+        (mmr, log)
+    }
+
+    #[test]
+    fn test_node_positions() {
+        // Heights of the nodes of an MMR with 8 leaves, in post-order
+        let heights = [0, 0, 1, 0, 0, 1, 2, 0, 0, 1, 0, 0, 1, 2, 3];
+        let mut counts = [0; 4];
+        for (position, height) in heights.into_iter().enumerate() {
+            assert_eq!(node_position(height, counts[height]), position);
+            counts[height] += 1;
+        }
+        assert_eq!(node_count(8), heights.len());
+        assert_eq!(node_count(7), 11);
+    }
+
+    #[test]
+    fn test_root_hashes_match_merkle_log() {
+        let (mmr, log) = mmr_and_log(33);
+        for leaf_count in 0..=33 {
+            assert_eq!(
+                mmr.root_at(leaf_count).unwrap().root_hash,
+                log.root_hash(leaf_count).unwrap()
+            );
+        }
+        assert_eq!(mmr.root().root_hash, log.tree_head().root_hash);
+        assert_eq!(mmr.leaf_hash(17), log.leaf_hash(17));
+    }
+
+    #[test]
+    fn test_inclusion_proofs() {
+        let (mmr, _) = mmr_and_log(20);
+        for leaf_count in 1..=20 {
+            let root = mmr.root_at(leaf_count).unwrap();
+            for leaf_index in 0..leaf_count {
+                let leaf_hash = mmr.leaf_hash(leaf_index).unwrap();
+                let proof = mmr.inclusion_proof(leaf_index, leaf_count).unwrap();
+                assert!(proof.verify(leaf_hash, &root));
+
+                let other_leaf_hash = mmr.leaf_hash((leaf_index + 1) % 20).unwrap();
+                assert!(!proof.verify(other_leaf_hash, &root));
+
+                // Some ranges of different sizes have the same root hash
+                let mut wrong_count = proof.clone();
+                wrong_count.leaf_count += 1;
+                assert!(!wrong_count.verify(leaf_hash, &root));
+                let mut wrong_root = root;
+                wrong_root.leaf_count += 1;
+                assert!(!proof.verify(leaf_hash, &wrong_root));
+
+                let mut additional_peak = proof;
+                additional_peak.peaks.push([0u8; 32]);
+                assert!(!additional_peak.verify(leaf_hash, &root));
+            }
+        }
+    }
+
+    #[test]
+    fn test_extended_proofs_stay_valid() {
+        let (mmr, _) = mmr_and_log(20);
+        for old_count in 1..=20 {
+            for leaf_index in 0..old_count {
+                let leaf_hash = mmr.leaf_hash(leaf_index).unwrap();
+                let old_proof = mmr.inclusion_proof(leaf_index, old_count).unwrap();
+                for new_count in old_count..=20 {
+                    let new_proof = mmr.extend_proof(&old_proof, new_count).unwrap();
+                    assert_eq!(
+                        new_proof,
+                        mmr.inclusion_proof(leaf_index, new_count).unwrap()
+                    );
+                    assert!(new_proof.path.starts_with(&old_proof.path));
+                    assert!(new_proof.verify(leaf_hash, &mmr.root_at(new_count).unwrap()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_sizes() {
+        let (mmr, _) = mmr_and_log(5);
+        assert_eq!(
+            mmr.root_at(6),
+            Err(Error::InvalidTreeSize {
+                size: 6,
+                max_size: 5
+            })
+        );
+        assert_eq!(
+            mmr.inclusion_proof(5, 5),
+            Err(Error::IndexOutOfRange { index: 5, size: 5 })
+        );
+        let proof = mmr.inclusion_proof(1, 4).unwrap();
+        assert_eq!(
+            mmr.extend_proof(&proof, 3),
+            Err(Error::InvalidTreeSize {
+                size: 4,
+                max_size: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_file_backed_mmr() {
+        let path = std::env::temp_dir().join(format!("mmr_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        {
+            let mut mmr = MerkleMountainRange::open(&path).unwrap();
+            for i in 0..3u8 {
+                assert_eq!(mmr.append(&[i]).unwrap(), i as usize);
+            }
+        }
+        assert_eq!(fs::metadata(&path).unwrap().len(), 4 * 32);
+
+        let mut mmr = MerkleMountainRange::open(&path).unwrap();
+        assert_eq!(mmr.size(), 3);
+        assert_eq!(mmr.leaf_hash(2), Some(&leaf_hash(&[2])));
+        assert_eq!(mmr.append(&[3]).unwrap(), 3);
+        assert_eq!(mmr.root().root_hash, mmr_and_log(4).0.root().root_hash);
+
+        drop(mmr);
+
+        // The partially written nodes of an interrupted append are removed
+        // Appending the sixth leaf writes the leaf and its parent, the first of which completes
+        // the range of five leaves
+        for (length, leaf_count) in [(7 * 32 + 5, 4), (9 * 32, 5), (9 * 32 + 31, 5)] {
+            let (expected, _) = mmr_and_log(leaf_count);
+            let mut contents = expected.nodes.concat();
+            contents.resize(length, 0);
+            fs::write(&path, contents).unwrap();
+            let mmr = MerkleMountainRange::open(&path).unwrap();
+            assert_eq!(mmr.size(), leaf_count as usize);
+            assert_eq!(mmr.root(), expected.root());
+            assert_eq!(
+                fs::metadata(&path).unwrap().len(),
+                32 * node_count(leaf_count as usize) as u64
+            );
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_signed_root() {
+        let (mmr, _) = mmr_and_log(7);
+
+        let signature_scheme = StatelessMerkleSignatureScheme::new([0u8; 32], 16, 5, D::new(255));
+        let signed_root = mmr.root().sign(&signature_scheme);
+        assert!(signed_root.verify(&signature_scheme.versioned_public_key()));
+
+        let mut wrong_count = signed_root.clone();
+        wrong_count.root.leaf_count = 6;
+        assert!(!wrong_count.verify(&signature_scheme.versioned_public_key()));
+
+        // The signature can't be used as a signature of a file, or of a log's tree head
+        assert!(!StatelessMerkleSignatureScheme::verify_with_context(
+            &signature_scheme.public_key(),
+            &signed_root.root.message(),
+            b"",
+            &signed_root.signature
+        ));
+        let signed_tree_head = SignedTreeHead {
+            tree_head: TreeHead {
+                tree_size: 7,
+                root_hash: mmr.root().root_hash,
+            },
+            signature: signed_root.signature,
+        };
+        assert!(!signed_tree_head.verify(&signature_scheme.versioned_public_key()));
+    }
+}
@@ -0,0 +1,322 @@
+use crate::error::Error;
+use crate::merkle_tree::encoding::LeafEncode;
+use crate::merkle_tree::{group_hash, leaf_hash, MerkleTree, TreeShape};
+use crate::signature::HashType;
+use crate::utils::ct_eq;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// A proof that several data are at the given indices of the same tree.
+///
+/// Unlike a list of `MerkleProof`s, each sibling hash is included at most once, and
+/// no hash is included that the verifier can compute from the data itself.
+/// Like `MerkleProof`, it can be serialized (e.g. using MessagePack); the hashes are
+/// stored level by level, from the leaves up, and from left to right within each level.
+///
+/// # Examples
+/// ```
+/// use hash_based_signatures::merkle_tree::MerkleTree;
+///
+/// let elements: Vec<u8> = (0..128).collect();
+/// let tree = MerkleTree::new(&elements);
+/// let proof = tree.get_multi_proof(&[17, 18, 100]);
+/// assert!(proof.verify(*tree.get_root_hash(), &[(17, &17), (18, &18), (100, &100)]));
+/// ```
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultiMerkleProof<T: Serialize> {
+    /// The depth of the tree
+    pub depth: usize,
+    /// The sibling hashes needed to compute the root hash
+    pub hashes: Vec<HashType>,
+
+    /// Phantom to keep the information of the element type.
+    phantom: PhantomData<T>,
+
+    /// The shape of the tree, if it is not a binary tree with a power-of-two number of leaves.
+    /// Informational only, see `MerkleProof::shape`: The verifier passes the shape to
+    /// `MultiMerkleProof::verify_with_shape()`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shape: Option<TreeShape>,
+}
+
+impl<T: Serialize + LeafEncode + Debug> MerkleTree<T> {
+    /// Get a Merkle proof for all given `indices` at once, see `MultiMerkleProof`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is out of range.
+    /// Consider using `MerkleTree::try_get_multi_proof()`.
+    pub fn get_multi_proof(&self, indices: &[usize]) -> MultiMerkleProof<T> {
+        self.try_get_multi_proof(indices)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Like `MerkleTree::get_multi_proof()`, but fails if any index is out of range.
+    pub fn try_get_multi_proof(&self, indices: &[usize]) -> Result<MultiMerkleProof<T>, Error> {
+        let shape = self.shape();
+        let size = shape.leaf_count;
+        if let Some(&index) = indices.iter().find(|&&index| index >= size) {
+            return Err(Error::IndexOutOfRange { index, size });
+        }
+
+        let mut known_indices = indices.to_vec();
+        known_indices.sort_unstable();
+        known_indices.dedup();
+
+        let mut hashes = Vec::new();
+        for level in 0..self.depth() {
+            let mut i = 0;
+            let mut parent_indices = Vec::with_capacity(known_indices.len());
+            while i < known_indices.len() {
+                let group = shape.group(known_indices[i], self.level_size(level));
+                parent_indices.push(group.start / shape.arity);
+                for sibling in group {
+                    if known_indices.get(i) == Some(&sibling) {
+                        i += 1;
+                    } else {
+                        hashes.push(self.node_hash(level, sibling));
+                    }
+                }
+            }
+            known_indices = parent_indices;
+        }
+
+        Ok(MultiMerkleProof {
+            depth: self.depth(),
+            hashes,
+            shape: Some(shape).filter(|shape| TreeShape::binary(self.depth()) != Some(*shape)),
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<T: Serialize + LeafEncode> MultiMerkleProof<T> {
+    /// Verifies that the given root hash can be reconstructed from the proof and
+    /// the `(index, datum)` pairs, for a binary tree with a power-of-two number of leaves.
+    ///
+    /// The data can be given in any order, but each index may only appear once.
+    /// Malformed proofs (e.g. with missing or superfluous hashes) are rejected.
+    /// For trees of any other shape, use `MultiMerkleProof::verify_with_shape()`.
+    pub fn verify(&self, root_hash: HashType, data: &[(usize, &T)]) -> bool {
+        match self.root_hash(data) {
+            Some(expected_root_hash) => ct_eq(&expected_root_hash, &root_hash),
+            None => false,
+        }
+    }
+
+    /// Like `MultiMerkleProof::verify()`, for a tree of the given `shape`, which must come
+    /// from the same trusted source as the root hash. The `shape` stored in the proof is ignored.
+    pub fn verify_with_shape(
+        &self,
+        root_hash: HashType,
+        shape: TreeShape,
+        data: &[(usize, &T)],
+    ) -> bool {
+        match self.root_hash_with_shape(data, shape) {
+            Some(expected_root_hash) => ct_eq(&expected_root_hash, &root_hash),
+            None => false,
+        }
+    }
+
+    /// Computes the root hash of a binary tree with a power-of-two number of leaves from
+    /// the proof and the `(index, datum)` pairs.
+    ///
+    /// Returns `None` for malformed proofs.
+    pub fn root_hash(&self, data: &[(usize, &T)]) -> Option<HashType> {
+        self.root_hash_with_shape(data, TreeShape::binary(self.depth)?)
+    }
+
+    /// Like `MultiMerkleProof::root_hash()`, for a tree of the given `shape`.
+    pub fn root_hash_with_shape(&self, data: &[(usize, &T)], shape: TreeShape) -> Option<HashType> {
+        shape.validate().ok()?;
+        let level_sizes = shape.level_sizes();
+        if data.is_empty() || level_sizes.len() != self.depth + 1 {
+            return None;
+        }
+
+        let mut nodes: Vec<(usize, HashType)> = data
+            .iter()
+            .map(|(index, datum)| (*index, leaf_hash(*datum)))
+            .collect();
+        nodes.sort_unstable_by_key(|(index, _)| *index);
+        let indices_are_unique = nodes.windows(2).all(|pair| pair[0].0 != pair[1].0);
+        let (last_index, _) = nodes[nodes.len() - 1];
+        if !indices_are_unique || last_index >= shape.leaf_count {
+            return None;
+        }
+
+        let mut hashes = self.hashes.iter();
+        for &level_size in &level_sizes[..self.depth] {
+            let mut i = 0;
+            let mut parent_nodes = Vec::with_capacity(nodes.len());
+            while i < nodes.len() {
+                let group = shape.group(nodes[i].0, level_size);
+                let parent_index = group.start / shape.arity;
+                let mut children = Vec::with_capacity(group.len());
+                for sibling in group {
+                    match nodes.get(i) {
+                        Some((index, hash)) if *index == sibling => {
+                            children.push(*hash);
+                            i += 1;
+                        }
+                        _ => children.push(*hashes.next()?),
+                    }
+                }
+                parent_nodes.push((parent_index, group_hash(&children)));
+            }
+            nodes = parent_nodes;
+        }
+
+        if hashes.next().is_some() {
+            return None;
+        }
+        Some(nodes[0].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::merkle_tree::multi_proof::MultiMerkleProof;
+    use crate::merkle_tree::MerkleTree;
+
+    fn merkle_tree() -> MerkleTree<Vec<u8>> {
+        let elements: Vec<Vec<u8>> = (0u8..128).map(|x| vec![x]).collect();
+        MerkleTree::new(&elements)
+    }
+
+    fn data(indices: &[usize]) -> Vec<(usize, Vec<u8>)> {
+        indices.iter().map(|&i| (i, vec![i as u8])).collect()
+    }
+
+    fn verify(
+        proof: &MultiMerkleProof<Vec<u8>>,
+        root_hash: [u8; 32],
+        data: &[(usize, Vec<u8>)],
+    ) -> bool {
+        let data: Vec<(usize, &Vec<u8>)> = data.iter().map(|(i, datum)| (*i, datum)).collect();
+        proof.verify(root_hash, &data)
+    }
+
+    #[test]
+    fn test_valid_proofs() {
+        let tree = merkle_tree();
+        for indices in [
+            vec![43],
+            vec![0, 1],
+            vec![0, 127],
+            vec![3, 17, 18, 100],
+            (0..128).collect(),
+        ] {
+            let proof = tree.get_multi_proof(&indices);
+            assert!(verify(&proof, *tree.get_root_hash(), &data(&indices)));
+        }
+    }
+
+    #[test]
+    fn test_data_in_any_order() {
+        let tree = merkle_tree();
+        let proof = tree.get_multi_proof(&[100, 3, 17]);
+        assert!(verify(&proof, *tree.get_root_hash(), &data(&[17, 100, 3])));
+    }
+
+    #[test]
+    fn test_minimal_number_of_hashes() {
+        let tree = merkle_tree();
+        assert_eq!(
+            tree.get_multi_proof(&[43]).hashes,
+            tree.get_proof(43).hash_chain
+        );
+        // Siblings only need the 6 hashes above their parent
+        assert_eq!(tree.get_multi_proof(&[42, 43]).hashes.len(), 6);
+        assert_eq!(tree.get_multi_proof(&[0, 127]).hashes.len(), 12);
+        assert!(tree
+            .get_multi_proof(&(0..128).collect::<Vec<_>>())
+            .hashes
+            .is_empty());
+        // Duplicates are ignored
+        assert_eq!(
+            tree.get_multi_proof(&[43, 43]).hashes,
+            tree.get_multi_proof(&[43]).hashes
+        );
+    }
+
+    #[test]
+    fn test_invalid_proofs() {
+        let tree = merkle_tree();
+        let root_hash = *tree.get_root_hash();
+        let proof = tree.get_multi_proof(&[3, 17, 100]);
+
+        // Wrong data, wrong indices, missing, additional or duplicate data
+        assert!(!verify(
+            &proof,
+            root_hash,
+            &[(3, vec![4]), (17, vec![17]), (100, vec![100])]
+        ));
+        assert!(!verify(&proof, root_hash, &data(&[3, 17, 101])));
+        assert!(!verify(&proof, root_hash, &data(&[3, 17])));
+        assert!(!verify(&proof, root_hash, &data(&[3, 17, 18, 100])));
+        assert!(!verify(&proof, root_hash, &data(&[3, 17, 17, 100])));
+        assert!(!verify(&proof, root_hash, &data(&[3, 17, 228])));
+        assert!(!verify(&proof, root_hash, &[]));
+
+        // Missing, additional or wrong hashes
+        let mut missing_hash = proof.clone();
+        missing_hash.hashes.pop();
+        let mut additional_hash = proof.clone();
+        additional_hash.hashes.push([0u8; 32]);
+        let mut wrong_hash = proof.clone();
+        wrong_hash.hashes[0] = [0u8; 32];
+        let mut wrong_depth = proof;
+        wrong_depth.depth = 64;
+        for proof in [missing_hash, additional_hash, wrong_hash, wrong_depth] {
+            assert!(!verify(&proof, root_hash, &data(&[3, 17, 100])));
+        }
+    }
+
+    #[test]
+    fn test_index_out_of_range() {
+        let tree = merkle_tree();
+        assert!(matches!(
+            tree.try_get_multi_proof(&[3, 128]),
+            Err(Error::IndexOutOfRange {
+                index: 128,
+                size: 128
+            })
+        ));
+    }
+
+    #[test]
+    fn test_any_shape() {
+        for arity in [2, 3, 4, 16] {
+            let elements: Vec<Vec<u8>> = (0u8..100).map(|x| vec![x]).collect();
+            let tree = MerkleTree::with_arity(&elements, arity);
+            for indices in [vec![99], vec![0, 1, 2, 3], vec![3, 17, 18, 96, 99]] {
+                let proof = tree.get_multi_proof(&indices);
+                let verify_with_shape = |indices: &[usize]| {
+                    let data = data(indices);
+                    let data: Vec<(usize, &Vec<u8>)> =
+                        data.iter().map(|(i, datum)| (*i, datum)).collect();
+                    proof.verify_with_shape(*tree.get_root_hash(), tree.shape(), &data)
+                };
+                assert!(verify_with_shape(&indices));
+                assert!(!verify_with_shape(&indices[1..]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_serialization_roundtrip() {
+        let tree = merkle_tree();
+        let proof = tree.get_multi_proof(&[3, 17, 100]);
+        let bytes = rmp_serde::to_vec(&proof).unwrap();
+        let deserialized: MultiMerkleProof<Vec<u8>> = rmp_serde::from_slice(&bytes).unwrap();
+        assert!(deserialized == proof);
+        assert!(verify(
+            &deserialized,
+            *tree.get_root_hash(),
+            &data(&[3, 17, 100])
+        ));
+    }
+}
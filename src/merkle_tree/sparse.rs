@@ -0,0 +1,405 @@
+use crate::merkle_tree::encoding::LeafEncode;
+use crate::merkle_tree::{internal_node_hash, leaf_hash};
+use crate::signature::HashType;
+use crate::utils::ct_eq;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::sync::OnceLock;
+
+/// Number of key bits, and thus the depth of the tree.
+const KEY_BITS: usize = 256;
+
+/// Hash of an empty leaf.
+const EMPTY_LEAF_HASH: HashType = [0u8; 32];
+
+/// Hashes of empty subtrees, indexed by their height (0 for leaves).
+fn default_hashes() -> &'static [HashType; KEY_BITS + 1] {
+    static DEFAULT_HASHES: OnceLock<[HashType; KEY_BITS + 1]> = OnceLock::new();
+    DEFAULT_HASHES.get_or_init(|| {
+        let mut hashes = [EMPTY_LEAF_HASH; KEY_BITS + 1];
+        for height in 1..=KEY_BITS {
+            hashes[height] = internal_node_hash(&hashes[height - 1], &hashes[height - 1]);
+        }
+        hashes
+    })
+}
+
+/// Bit `index` of `bytes`, counting from the most significant bit.
+fn bit(bytes: &[u8; 32], index: usize) -> bool {
+    bytes[index / 8] & (0x80 >> (index % 8)) != 0
+}
+
+fn flip_bit(bytes: &mut [u8; 32], index: usize) {
+    bytes[index / 8] ^= 0x80 >> (index % 8);
+}
+
+/// Identifies the node at `height` on the path of `key`, by clearing the lowest `height` bits.
+fn node_id(key: &HashType, height: usize) -> HashType {
+    let mut id = *key;
+    for index in KEY_BITS - height..KEY_BITS {
+        id[index / 8] &= !(0x80 >> (index % 8));
+    }
+    id
+}
+
+/// A Merkle tree with a leaf for every 256-bit key, most of which are empty.
+///
+/// The path from the root to a leaf is given by the bits of its key, starting with the most
+/// significant bit. Empty leaves have the hash `[0; 32]`, and subtrees without any entries
+/// have precomputed default hashes, so only the nodes above non-empty leaves are stored.
+///
+/// Proofs show that a key has a given value (membership), or that it has no value at all
+/// (non-membership), e.g. that a key ID is not on a revocation list.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::merkle_tree::sparse::SparseMerkleTree;
+///
+/// let mut tree = SparseMerkleTree::new();
+/// tree.insert([1u8; 32], "revoked");
+///
+/// let proof = tree.get_proof(&[1u8; 32]);
+/// assert!(proof.verify(tree.root_hash(), &[1u8; 32], Some(&"revoked")));
+///
+/// let proof = tree.get_proof(&[2u8; 32]);
+/// assert!(proof.verify(tree.root_hash(), &[2u8; 32], None));
+/// ```
+pub struct SparseMerkleTree<T: Serialize> {
+    values: HashMap<HashType, T>,
+    /// Non-default node hashes, indexed by height and node ID
+    nodes: HashMap<(usize, HashType), HashType>,
+}
+
+/// A proof that a key has a given value in a `SparseMerkleTree`, or that it is empty.
+///
+/// Sibling hashes that equal the default hash of an empty subtree are omitted;
+/// `bitmap` contains a set bit for each height whose sibling hash is included.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct SparseMerkleProof<T: Serialize> {
+    pub bitmap: [u8; 32],
+    /// The included sibling hashes, from the leaves up
+    pub hashes: Vec<HashType>,
+
+    /// Phantom to keep the information of the element type.
+    phantom: PhantomData<T>,
+}
+
+impl<T: Serialize + LeafEncode> Default for SparseMerkleTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Serialize + LeafEncode> SparseMerkleTree<T> {
+    /// Creates an empty tree.
+    pub fn new() -> Self {
+        Self {
+            values: HashMap::new(),
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// The root hash of the tree, which can be signed like any other message.
+    pub fn root_hash(&self) -> HashType {
+        self.node_hash(KEY_BITS, &[0u8; 32])
+    }
+
+    /// The value of `key`, if any.
+    pub fn get(&self, key: &HashType) -> Option<&T> {
+        self.values.get(key)
+    }
+
+    /// The number of non-empty keys.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Sets the value of `key`, returning its previous value.
+    pub fn insert(&mut self, key: HashType, value: T) -> Option<T> {
+        let previous = self.values.remove(&key);
+        self.update(vec![(key, Some(value))]);
+        previous
+    }
+
+    /// Removes `key` from the tree, returning its previous value.
+    pub fn remove(&mut self, key: &HashType) -> Option<T> {
+        let previous = self.values.remove(key);
+        self.update(vec![(*key, None)]);
+        previous
+    }
+
+    /// Sets (`Some`) or removes (`None`) the values of several keys at once.
+    ///
+    /// Nodes shared by the paths of several keys are only rehashed once.
+    /// If a key appears more than once, the last update wins.
+    pub fn update(&mut self, updates: Vec<(HashType, Option<T>)>) {
+        let mut keys = Vec::with_capacity(updates.len());
+        for (key, value) in updates {
+            let leaf = match &value {
+                Some(value) => leaf_hash(value),
+                None => EMPTY_LEAF_HASH,
+            };
+            self.set_node(0, key, leaf);
+            match value {
+                Some(value) => self.values.insert(key, value),
+                None => self.values.remove(&key),
+            };
+            keys.push(key);
+        }
+        keys.sort_unstable();
+        keys.dedup();
+
+        let default_hashes = default_hashes();
+        for height in 1..=KEY_BITS {
+            for key in &mut keys {
+                *key = node_id(key, height);
+            }
+            keys.dedup();
+
+            for id in &keys {
+                let mut right_id = *id;
+                flip_bit(&mut right_id, KEY_BITS - height);
+                let left = self.node_hash(height - 1, id);
+                let right = self.node_hash(height - 1, &right_id);
+                let hash =
+                    if left == default_hashes[height - 1] && right == default_hashes[height - 1] {
+                        default_hashes[height]
+                    } else {
+                        internal_node_hash(&left, &right)
+                    };
+                self.set_node(height, *id, hash);
+            }
+        }
+    }
+
+    /// Gets a (non-)membership proof for `key`, see `SparseMerkleProof`.
+    pub fn get_proof(&self, key: &HashType) -> SparseMerkleProof<T> {
+        let mut bitmap = [0u8; 32];
+        let mut hashes = Vec::new();
+        for (height, default_hash) in default_hashes().iter().take(KEY_BITS).enumerate() {
+            let mut sibling_id = node_id(key, height);
+            flip_bit(&mut sibling_id, KEY_BITS - 1 - height);
+            let sibling = self.node_hash(height, &sibling_id);
+            if sibling != *default_hash {
+                flip_bit(&mut bitmap, height);
+                hashes.push(sibling);
+            }
+        }
+        SparseMerkleProof {
+            bitmap,
+            hashes,
+            phantom: PhantomData,
+        }
+    }
+
+    fn node_hash(&self, height: usize, id: &HashType) -> HashType {
+        match self.nodes.get(&(height, *id)) {
+            Some(hash) => *hash,
+            None => default_hashes()[height],
+        }
+    }
+
+    fn set_node(&mut self, height: usize, id: HashType, hash: HashType) {
+        if hash == default_hashes()[height] {
+            self.nodes.remove(&(height, id));
+        } else {
+            self.nodes.insert((height, id), hash);
+        }
+    }
+}
+
+impl<T: Serialize + LeafEncode> SparseMerkleProof<T> {
+    /// Verifies that `key` has the given `value` in the tree with the given root hash,
+    /// or that `key` is empty if `value` is `None`.
+    pub fn verify(&self, root_hash: HashType, key: &HashType, value: Option<&T>) -> bool {
+        match self.root_hash(key, value) {
+            Some(expected_root_hash) => ct_eq(&expected_root_hash, &root_hash),
+            None => false,
+        }
+    }
+
+    /// Computes the root hash of the tree from the proof, `key` and `value`.
+    ///
+    /// Returns `None` if the number of hashes doesn't match the bitmap.
+    pub fn root_hash(&self, key: &HashType, value: Option<&T>) -> Option<HashType> {
+        let default_hashes = default_hashes();
+        let mut hashes = self.hashes.iter();
+        let mut node = match value {
+            Some(value) => leaf_hash(value),
+            None => EMPTY_LEAF_HASH,
+        };
+        for (height, default_hash) in default_hashes.iter().take(KEY_BITS).enumerate() {
+            let sibling = if bit(&self.bitmap, height) {
+                hashes.next()?
+            } else {
+                default_hash
+            };
+            node = if bit(key, KEY_BITS - 1 - height) {
+                internal_node_hash(sibling, &node)
+            } else {
+                internal_node_hash(&node, sibling)
+            };
+        }
+
+        if hashes.next().is_some() {
+            return None;
+        }
+        Some(node)
+    }
+}
+
+impl<T: Serialize> Debug for SparseMerkleProof<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Sparse Merkle proof with {} hashes", self.hashes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::sparse::{default_hashes, SparseMerkleProof, SparseMerkleTree};
+    use crate::merkle_tree::{internal_node_hash, leaf_hash};
+    use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+    use crate::signature::winternitz::d::D;
+    use crate::signature::{HashType, Signer, Verifier};
+
+    fn key(i: u8) -> HashType {
+        let mut key = [0u8; 32];
+        key[0] = i.wrapping_mul(97);
+        key[31] = i;
+        key
+    }
+
+    fn tree(n: u8) -> SparseMerkleTree<u8> {
+        let mut tree = SparseMerkleTree::new();
+        for i in 0..n {
+            tree.insert(key(i), i);
+        }
+        tree
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let tree = SparseMerkleTree::<u8>::new();
+        assert_eq!(tree.root_hash(), default_hashes()[256]);
+        assert!(tree.is_empty());
+        let proof = tree.get_proof(&key(1));
+        assert!(proof.hashes.is_empty());
+        assert!(proof.verify(tree.root_hash(), &key(1), None));
+        assert!(!proof.verify(tree.root_hash(), &key(1), Some(&1)));
+    }
+
+    #[test]
+    fn test_single_entry_root_hash() {
+        let tree = tree(1);
+        // Key 0 is the leftmost leaf, so all siblings are empty subtrees on the right
+        let mut expected = leaf_hash(&0u8);
+        for default_hash in &default_hashes()[..256] {
+            expected = internal_node_hash(&expected, default_hash);
+        }
+        assert_eq!(tree.root_hash(), expected);
+    }
+
+    #[test]
+    fn test_membership_and_non_membership() {
+        let tree = tree(50);
+        let root_hash = tree.root_hash();
+        for i in 0..100u8 {
+            let proof = tree.get_proof(&key(i));
+            if i < 50 {
+                assert_eq!(tree.get(&key(i)), Some(&i));
+                assert!(proof.verify(root_hash, &key(i), Some(&i)));
+                assert!(!proof.verify(root_hash, &key(i), Some(&(i + 1))));
+                assert!(!proof.verify(root_hash, &key(i), None));
+            } else {
+                assert!(proof.verify(root_hash, &key(i), None));
+                assert!(!proof.verify(root_hash, &key(i), Some(&i)));
+            }
+            assert!(!proof.verify(root_hash, &key(i.wrapping_add(1)), Some(&i)));
+        }
+    }
+
+    #[test]
+    fn test_invalid_proofs() {
+        let tree = tree(10);
+        let root_hash = tree.root_hash();
+        let proof = tree.get_proof(&key(3));
+
+        let mut missing_hash = proof.clone();
+        missing_hash.hashes.pop();
+        let mut additional_hash = proof.clone();
+        additional_hash.hashes.push([0u8; 32]);
+        let mut wrong_bitmap = proof.clone();
+        wrong_bitmap.bitmap[31] ^= 1;
+        let mut wrong_hash = proof;
+        wrong_hash.hashes[0][0] ^= 1;
+        for proof in [missing_hash, additional_hash, wrong_bitmap, wrong_hash] {
+            assert!(!proof.verify(root_hash, &key(3), Some(&3)));
+        }
+    }
+
+    #[test]
+    fn test_root_hash_is_independent_of_history() {
+        let mut tree = tree(20);
+        for i in 10..20 {
+            assert_eq!(tree.remove(&key(i)), Some(i));
+        }
+        assert_eq!(tree.root_hash(), self::tree(10).root_hash());
+        assert_eq!(tree.len(), 10);
+
+        for i in 10..20 {
+            tree.remove(&key(i));
+        }
+        assert_eq!(tree.root_hash(), self::tree(10).root_hash());
+
+        for i in 0..10 {
+            tree.remove(&key(i));
+        }
+        assert_eq!(tree.root_hash(), default_hashes()[256]);
+        assert!(tree.nodes.is_empty());
+    }
+
+    #[test]
+    fn test_batch_update() {
+        let mut tree = SparseMerkleTree::new();
+        tree.update((0..50).map(|i| (key(i), Some(i))).collect());
+        assert_eq!(tree.root_hash(), self::tree(50).root_hash());
+
+        tree.update(
+            (20..50)
+                .map(|i| (key(i), None))
+                .chain([(key(3), Some(103)), (key(3), Some(3))])
+                .collect(),
+        );
+        assert_eq!(tree.root_hash(), self::tree(20).root_hash());
+    }
+
+    #[test]
+    fn test_serialization_roundtrip() {
+        let tree = tree(10);
+        let proof = tree.get_proof(&key(3));
+        let bytes = rmp_serde::to_vec(&proof).unwrap();
+        let deserialized: SparseMerkleProof<u8> = rmp_serde::from_slice(&bytes).unwrap();
+        assert!(deserialized == proof);
+        assert!(deserialized.verify(tree.root_hash(), &key(3), Some(&3)));
+    }
+
+    #[test]
+    fn test_sign_root_hash() {
+        let tree = tree(10);
+        let mut signature_scheme =
+            StatelessMerkleSignatureScheme::new([0u8; 32], 16, 5, D::new(255));
+        let signature = signature_scheme.sign(tree.root_hash());
+        assert!(StatelessMerkleSignatureScheme::verify(
+            &signature_scheme.public_key(),
+            &tree.root_hash(),
+            &signature
+        ));
+    }
+}
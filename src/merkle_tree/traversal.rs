@@ -0,0 +1,516 @@
+use crate::error::Error;
+use crate::merkle_tree::encoding::LeafEncoding;
+use crate::signature::HashType;
+use serde::{Deserialize, Serialize};
+use std::cmp::min;
+
+/// Computes the authentication paths of all leaves of a binary Merkle tree, one after another,
+/// without storing the tree.
+///
+/// This is the log space and time traversal of
+/// [Szydlo (2004)](https://www.iacr.org/archive/eurocrypt2004/30270536/szydlo-loglog.pdf):
+/// For each level `h`, a "treehash" instance computes the node that will be needed as the next
+/// authentication node at that level. Before each path is returned, the leaves are computed
+/// one at a time, always advancing the instance whose lowest node is the lowest. This keeps the
+/// state at `O(height)` nodes and computes each authentication path using `O(height)` leaf
+/// and node hashes, instead of rebuilding the whole tree.
+///
+/// Like the [BDS algorithm](https://eprint.iacr.org/2008/356), the nodes of the top `K` levels
+/// below the root can be retained (see `AuthPathTraversal::with_retained_levels()`): They are
+/// computed once by `new()` and stored, which takes `2^(K + 1) - 2` nodes, and treehash
+/// instances are only used for the `height - K` lower levels. Then, `2 * (height - K) - 1`
+/// leaves are computed per authentication path. The remaining parts of BDS, which further
+/// reduce this to `(height - K) / 2` leaves, are not implemented.
+///
+/// Leaves are computed on demand by a function that is passed to `next()`, e.g. from the seed
+/// of the one-time key. The state is serializable, so that it can be stored alongside the key.
+/// A deserialized state should be checked using `AuthPathTraversal::validate()`.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::merkle_tree::traversal::AuthPathTraversal;
+/// use hash_based_signatures::merkle_tree::{leaf_hash, MerkleTree};
+///
+/// let elements: Vec<u64> = (0..16).collect();
+/// let tree = MerkleTree::new(&elements);
+///
+/// let leaf = |i: usize| leaf_hash(&elements[i]);
+/// let (mut traversal, root_hash) = AuthPathTraversal::new(4, leaf);
+/// assert_eq!(&root_hash, tree.get_root_hash());
+///
+/// while let Some((index, auth_path)) = traversal.next(leaf) {
+///     assert_eq!(auth_path, tree.get_proof(index).hash_chain);
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuthPathTraversal {
+    height: usize,
+    next_leaf: usize,
+    auth_path: Vec<HashType>,
+    /// The treehash instances of the levels below the retained ones.
+    stacks: Vec<TreeHashStack>,
+    /// All nodes of each retained level, starting with the lowest one.
+    /// Omitted by states that have been serialized before levels could be retained.
+    #[serde(default)]
+    retained: Vec<Vec<HashType>>,
+    /// The encoding whose node hash is used for internal nodes.
+    /// Omitted by states that have been serialized before the encoding was stored, which all
+    /// used the legacy node hash.
+    #[serde(default = "LeafEncoding::legacy")]
+    encoding: LeafEncoding,
+}
+
+/// A treehash instance, computing a node at `height` from its leaves, one leaf at a time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct TreeHashStack {
+    height: usize,
+    /// The next leaf to compute, or `None` if the node is not needed anymore.
+    next_leaf: Option<usize>,
+    /// Nodes computed so far, as `(height, hash)`, the lowest one on top.
+    nodes: Vec<(usize, HashType)>,
+}
+
+impl TreeHashStack {
+    fn new(height: usize) -> Self {
+        Self {
+            height,
+            next_leaf: None,
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Starts computing the node whose leftmost leaf is `start_leaf`.
+    fn initialize(&mut self, start_leaf: usize, leaf_count: usize) {
+        self.nodes.clear();
+        self.next_leaf = Some(start_leaf).filter(|start_leaf| *start_leaf < leaf_count);
+    }
+
+    fn is_complete(&self) -> bool {
+        matches!(self.nodes.as_slice(), [(height, _)] if *height == self.height)
+    }
+
+    /// The height of the lowest node on the stack, `height` if it is empty and
+    /// `None` (i.e. infinity) if there is nothing to compute.
+    fn low(&self) -> Option<usize> {
+        if self.next_leaf.is_none() || self.is_complete() {
+            return None;
+        }
+        Some(self.nodes.last().map_or(self.height, |(height, _)| *height))
+    }
+
+    /// Checks that the stack computes the node whose leftmost leaf is `start_leaf`,
+    /// and that its nodes are the ones computed from the leaves so far.
+    fn validate(&self, start_leaf: usize, leaf_count: usize) -> Result<(), Error> {
+        let computed_leaves = match self.next_leaf {
+            None if start_leaf >= leaf_count && self.nodes.is_empty() => return Ok(()),
+            Some(next_leaf) if start_leaf < leaf_count => next_leaf.checked_sub(start_leaf),
+            _ => None,
+        };
+        let computed_leaves = computed_leaves
+            .filter(|computed_leaves| *computed_leaves <= 1 << self.height)
+            .ok_or(Error::InvalidTraversalState(
+                "treehash instance computes the wrong node",
+            ))?;
+
+        // Like a binary counter, there is one node for each bit that is set
+        let expected_heights = (0..=self.height)
+            .rev()
+            .filter(|height| computed_leaves & (1 << height) != 0);
+        if !expected_heights.eq(self.nodes.iter().map(|(height, _)| *height)) {
+            return Err(Error::InvalidTraversalState(
+                "treehash instance has unexpected nodes",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Computes the next leaf and merges it with the nodes on the stack.
+    fn update<F: Fn(usize) -> HashType>(&mut self, leaf: &F, encoding: LeafEncoding) {
+        let Some(leaf_index) = self.next_leaf else {
+            return;
+        };
+        let mut node = (0, leaf(leaf_index));
+        while let Some((height, left)) = self.nodes.last().copied() {
+            if height != node.0 {
+                break;
+            }
+            self.nodes.pop();
+            node = (height + 1, encoding.node_hash(&[left, node.1]));
+        }
+        self.nodes.push(node);
+        self.next_leaf = Some(leaf_index + 1);
+    }
+}
+
+impl AuthPathTraversal {
+    /// Computes the initial state for a tree with `2^height` leaves, as well as its root hash.
+    ///
+    /// This computes every leaf once, like building the tree. The internal nodes are hashed
+    /// using the current encoding, like in a `MerkleTree`.
+    pub fn new<F: Fn(usize) -> HashType>(height: usize, leaf: F) -> (Self, HashType) {
+        Self::with_retained_levels(height, 0, LeafEncoding::CURRENT, leaf)
+    }
+
+    /// Like `AuthPathTraversal::new()`, but retains all nodes of the top `retained_levels` levels
+    /// below the root, so that only the lower levels are computed while traversing the tree.
+    /// The internal nodes are hashed using the node hash of `encoding`, which needs to be the
+    /// encoding of the leaves.
+    ///
+    /// At most `height` levels are retained, which keeps the whole tree.
+    pub fn with_retained_levels<F: Fn(usize) -> HashType>(
+        height: usize,
+        retained_levels: usize,
+        encoding: LeafEncoding,
+        leaf: F,
+    ) -> (Self, HashType) {
+        let lower_levels = height - min(retained_levels, height);
+        let mut auth_path = vec![[0u8; 32]; height];
+        let mut stacks: Vec<TreeHashStack> = (0..lower_levels).map(TreeHashStack::new).collect();
+        let mut retained = vec![Vec::new(); height - lower_levels];
+
+        // Compute the root, keeping the first two nodes of each level:
+        // The second one is the first authentication node, the first one the next.
+        // All nodes of the retained levels are kept.
+        let mut stack: Vec<(usize, HashType)> = Vec::with_capacity(height + 1);
+        for leaf_index in 0..1usize << height {
+            let mut node = (0, leaf(leaf_index));
+            loop {
+                let (height_of_node, hash) = node;
+                let index = leaf_index >> height_of_node;
+                if height_of_node >= lower_levels && height_of_node < height {
+                    retained[height_of_node - lower_levels].push(hash);
+                }
+                if height_of_node < height && index < 2 {
+                    if index == 1 {
+                        auth_path[height_of_node] = hash;
+                    } else if height_of_node < lower_levels {
+                        stacks[height_of_node].nodes = vec![node];
+                        stacks[height_of_node].next_leaf = Some(1 << height_of_node);
+                    }
+                }
+                match stack.last() {
+                    Some((top_height, left)) if *top_height == height_of_node => {
+                        node = (height_of_node + 1, encoding.node_hash(&[*left, hash]));
+                        stack.pop();
+                    }
+                    _ => break,
+                }
+            }
+            stack.push(node);
+        }
+
+        let traversal = Self {
+            height,
+            next_leaf: 0,
+            auth_path,
+            stacks,
+            retained,
+            encoding,
+        };
+        (traversal, stack[0].1)
+    }
+
+    /// Checks that the state is consistent, e.g. after deserializing it.
+    ///
+    /// This checks the structure of the state, not the hashes: A state with wrong hashes
+    /// returns wrong authentication paths. It also can't detect a state that has been rolled
+    /// back to an earlier leaf.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.height >= usize::BITS as usize - 1 {
+            return Err(Error::InvalidTraversalState("height is too large"));
+        }
+        if self.auth_path.len() != self.height {
+            return Err(Error::InvalidTraversalState(
+                "authentication path has the wrong length",
+            ));
+        }
+        if self.retained.len() > self.height {
+            return Err(Error::InvalidTraversalState(
+                "more levels are retained than the tree has",
+            ));
+        }
+        let leaf_count = self.leaf_count();
+        if self.next_leaf > leaf_count {
+            return Err(Error::InvalidTraversalState("next leaf is out of range"));
+        }
+        if self.next_leaf == leaf_count {
+            // The stacks are not used anymore
+            return Ok(());
+        }
+
+        let lower_levels = self.lower_levels();
+        for (level, nodes) in self.retained.iter().enumerate() {
+            if nodes.len() != 1 << (self.retained.len() - level) {
+                return Err(Error::InvalidTraversalState(
+                    "retained level has the wrong number of nodes",
+                ));
+            }
+        }
+        if self.stacks.len() != lower_levels {
+            return Err(Error::InvalidTraversalState(
+                "number of treehash instances does not match the height",
+            ));
+        }
+        for (height, stack) in self.stacks.iter().enumerate() {
+            if stack.height != height {
+                return Err(Error::InvalidTraversalState(
+                    "treehash instance has the wrong height",
+                ));
+            }
+            // Since the last refresh at this height, the stack computes the node
+            // that will be the authentication node of the next block of leaves
+            let block_start = self.next_leaf & !((1 << height) - 1);
+            let start_leaf = (block_start + (1 << height)) ^ (1 << height);
+            stack.validate(start_leaf, leaf_count)?;
+        }
+        Ok(())
+    }
+
+    /// The index of the leaf whose authentication path is returned next.
+    pub fn next_leaf(&self) -> usize {
+        self.next_leaf
+    }
+
+    /// The number of leaves of the tree.
+    pub fn leaf_count(&self) -> usize {
+        1 << self.height
+    }
+
+    /// The number of levels below the retained ones, which are computed by treehash instances.
+    fn lower_levels(&self) -> usize {
+        self.height - self.retained.len()
+    }
+
+    /// Returns the index and authentication path of the next leaf and updates the state
+    /// for the following one, or returns `None` if all leaves have been used.
+    ///
+    /// The authentication path has the same format as the `hash_chain` of a `MerkleProof`.
+    /// `leaf` needs to compute the same leaves as the ones passed to `AuthPathTraversal::new()`.
+    pub fn next<F: Fn(usize) -> HashType>(&mut self, leaf: F) -> Option<(usize, Vec<HashType>)> {
+        let leaf_index = self.next_leaf;
+        if leaf_index >= self.leaf_count() {
+            return None;
+        }
+        let auth_path = self.auth_path.clone();
+        self.next_leaf += 1;
+        if self.next_leaf == self.leaf_count() {
+            // No need to prepare a path that is never used
+            self.stacks.clear();
+            self.retained.clear();
+            return Some((leaf_index, auth_path));
+        }
+
+        // Refresh the authentication nodes of all levels at which the path changes
+        for height in 0..self.height {
+            if self.next_leaf % (1 << height) != 0 {
+                break;
+            }
+            if height >= self.lower_levels() {
+                let level = &self.retained[height - self.lower_levels()];
+                self.auth_path[height] = level[(self.next_leaf >> height) ^ 1];
+                continue;
+            }
+            let stack = &mut self.stacks[height];
+            // Only happens for states that have not been created by `next()`
+            while !stack.is_complete() && stack.next_leaf.is_some() {
+                stack.update(&leaf, self.encoding);
+            }
+            self.auth_path[height] = stack.nodes.first().map_or([0u8; 32], |(_, hash)| *hash);
+            let start_leaf = (self.next_leaf + (1 << height)) ^ (1 << height);
+            stack.initialize(start_leaf, 1 << self.height);
+        }
+
+        // Spend 2 * (height - K) - 1 leaf computations on the treehash instances with the
+        // lowest node
+        for _ in 0..(2 * self.lower_levels()).saturating_sub(1) {
+            let focus = self
+                .stacks
+                .iter_mut()
+                .filter_map(|stack| stack.low().map(|low| (low, stack)))
+                .min_by_key(|(low, stack)| (*low, stack.height));
+            match focus {
+                Some((_, stack)) => stack.update(&leaf, self.encoding),
+                None => break,
+            }
+        }
+
+        Some((leaf_index, auth_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::merkle_tree::encoding::LeafEncoding;
+    use crate::merkle_tree::traversal::AuthPathTraversal;
+    use crate::merkle_tree::{leaf_hash, MerkleTree};
+    use std::cell::Cell;
+
+    #[test]
+    fn test_all_auth_paths_match_merkle_tree() {
+        for height in 0..=7 {
+            let elements: Vec<u64> = (0..1 << height).collect();
+            let tree = MerkleTree::new(&elements);
+            let leaf = |i: usize| leaf_hash(&elements[i]);
+
+            for retained_levels in 0..=height + 1 {
+                let (mut traversal, root_hash) = AuthPathTraversal::with_retained_levels(
+                    height,
+                    retained_levels,
+                    LeafEncoding::CURRENT,
+                    leaf,
+                );
+                assert_eq!(&root_hash, tree.get_root_hash());
+                for expected_index in 0..elements.len() {
+                    let (index, auth_path) = traversal.next(leaf).unwrap();
+                    assert_eq!(index, expected_index);
+                    assert_eq!(auth_path, tree.get_proof(index).hash_chain);
+                }
+                assert_eq!(traversal.next(leaf), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_legacy_encoding() {
+        let elements: Vec<u64> = (0..16).collect();
+        let tree = MerkleTree::try_with_encoding(&elements, 2, LeafEncoding::MessagePack).unwrap();
+        let leaf = |i: usize| LeafEncoding::MessagePack.leaf_hash(&elements[i]);
+
+        let (mut traversal, root_hash) =
+            AuthPathTraversal::with_retained_levels(4, 2, LeafEncoding::MessagePack, leaf);
+        assert_eq!(&root_hash, tree.get_root_hash());
+        while let Some((index, auth_path)) = traversal.next(leaf) {
+            assert_eq!(auth_path, tree.get_proof(index).hash_chain);
+        }
+    }
+
+    #[test]
+    fn test_leaf_computations_are_logarithmic() {
+        let height = 8;
+        let elements: Vec<u64> = (0..1 << height).collect();
+        let calls = Cell::new(0);
+        let leaf = |i: usize| {
+            calls.set(calls.get() + 1);
+            leaf_hash(&elements[i])
+        };
+
+        for retained_levels in [0, 2, 4, 8] {
+            calls.set(0);
+            let (mut traversal, _) = AuthPathTraversal::with_retained_levels(
+                height,
+                retained_levels,
+                LeafEncoding::CURRENT,
+                leaf,
+            );
+            assert_eq!(calls.get(), elements.len());
+
+            let mut max_calls = 0;
+            loop {
+                calls.set(0);
+                if traversal.next(leaf).is_none() {
+                    break;
+                }
+                max_calls = max_calls.max(calls.get());
+            }
+            assert!(max_calls <= (2 * (height - retained_levels)).saturating_sub(1));
+        }
+    }
+
+    #[test]
+    fn test_reachable_states_are_valid() {
+        for height in 0..=6 {
+            let elements: Vec<u64> = (0..1 << height).collect();
+            let leaf = |i: usize| leaf_hash(&elements[i]);
+            for retained_levels in 0..=height {
+                let (mut traversal, _) = AuthPathTraversal::with_retained_levels(
+                    height,
+                    retained_levels,
+                    LeafEncoding::CURRENT,
+                    leaf,
+                );
+                assert_eq!(traversal.validate(), Ok(()));
+                while traversal.next(leaf).is_some() {
+                    assert_eq!(traversal.validate(), Ok(()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_states() {
+        let elements: Vec<u64> = (0..32).collect();
+        let leaf = |i: usize| leaf_hash(&elements[i]);
+        let (mut traversal, _) = AuthPathTraversal::new(5, leaf);
+        for _ in 0..11 {
+            traversal.next(leaf);
+        }
+
+        let mut too_high = traversal.clone();
+        too_high.height = usize::BITS as usize;
+        let mut next_leaf_out_of_range = traversal.clone();
+        next_leaf_out_of_range.next_leaf = 33;
+        let mut short_auth_path = traversal.clone();
+        short_auth_path.auth_path.pop();
+        let mut missing_stack = traversal.clone();
+        missing_stack.stacks.pop();
+        let mut swapped_stacks = traversal.clone();
+        swapped_stacks.stacks.swap(1, 2);
+        let mut moved_stack = traversal.clone();
+        moved_stack.stacks[2].next_leaf = moved_stack.stacks[2].next_leaf.map(|i| i + 4);
+        let mut missing_node = traversal.clone();
+        missing_node.stacks[3].nodes.clear();
+        let mut later_leaf = traversal;
+        later_leaf.next_leaf = 12;
+
+        let (mut retaining, _) =
+            AuthPathTraversal::with_retained_levels(5, 2, LeafEncoding::CURRENT, leaf);
+        for _ in 0..11 {
+            retaining.next(leaf);
+        }
+        let mut missing_retained_node = retaining.clone();
+        missing_retained_node.retained[1].pop();
+        let mut missing_retained_level = retaining.clone();
+        missing_retained_level.retained.pop();
+        let mut too_many_retained_levels = retaining;
+        too_many_retained_levels.retained = vec![vec![]; 6];
+
+        for traversal in [
+            too_high,
+            next_leaf_out_of_range,
+            short_auth_path,
+            missing_stack,
+            swapped_stacks,
+            moved_stack,
+            missing_node,
+            later_leaf,
+            missing_retained_node,
+            missing_retained_level,
+            too_many_retained_levels,
+        ] {
+            assert!(matches!(
+                traversal.validate(),
+                Err(Error::InvalidTraversalState(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_state_serialization_roundtrip() {
+        let elements: Vec<u64> = (0..32).collect();
+        let tree = MerkleTree::new(&elements);
+        let leaf = |i: usize| leaf_hash(&elements[i]);
+        let (mut traversal, _) = AuthPathTraversal::new(5, leaf);
+        for _ in 0..11 {
+            traversal.next(leaf);
+        }
+
+        let json = serde_json::to_string(&traversal).unwrap();
+        let mut restored: AuthPathTraversal = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, traversal);
+        assert_eq!(restored.next_leaf(), 11);
+        let (index, auth_path) = restored.next(leaf).unwrap();
+        assert_eq!(auth_path, tree.get_proof(index).hash_chain);
+    }
+}
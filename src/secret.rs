@@ -0,0 +1,66 @@
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use zeroize::Zeroize;
+
+/// Wrapper for secret key material.
+///
+/// The wrapped value is zeroized when it is dropped, and it is never printed by `Debug`.
+/// Note that clones are independent copies, which are zeroized on drop as well.
+#[derive(Clone, Default)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret::new(value)
+    }
+}
+
+impl<T: Zeroize> Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> DerefMut for Secret<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> Debug for Secret<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret([REDACTED])")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::secret::Secret;
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let secret = Secret::new([42u8; 32]);
+        assert_eq!(format!("{:?}", secret), "Secret([REDACTED])");
+    }
+
+    #[test]
+    fn test_deref() {
+        let mut secret = Secret::new(vec![1u8, 2, 3]);
+        secret.push(4);
+        assert_eq!(*secret, vec![1, 2, 3, 4]);
+    }
+}
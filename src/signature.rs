@@ -1,12 +1,52 @@
+pub mod algorithm;
 pub mod basic_lamport;
+pub mod key_format;
 pub mod q_indexed_signature;
 pub mod stateless_merkle;
 pub mod winternitz;
 
+use crate::error::Error;
+
 pub type HashType = [u8; 32];
 
+/// The verifying half of a signature scheme.
+///
+/// Verification does not take `self`, hence does not need an instance of the
+/// signature scheme (which would contain the signing key).
+/// Code that only verifies signatures can be written generically over `V: Verifier`.
+pub trait Verifier {
+    type PublicKey;
+    type Message;
+    type Signature;
+
+    /// Verifies a signature.
+    fn verify(pk: &Self::PublicKey, message: &Self::Message, signature: &Self::Signature) -> bool;
+}
+
+/// The signing half of a signature scheme, holding the secret key.
+pub trait Signer: Verifier {
+    /// Returns a copy of the public key
+    fn public_key(&self) -> Self::PublicKey;
+
+    /// Signs a message
+    fn sign(&mut self, message: Self::Message) -> Self::Signature;
+}
+
+/// Key generation of a signature scheme.
+///
+/// Generating a key pair is deterministic given the `seed` and the scheme's `Params`.
+pub trait KeyGen: Signer + Sized {
+    type Params;
+
+    /// Generates a new key pair from the given `seed`.
+    fn key_gen(seed: HashType, params: &Self::Params) -> Result<Self, Error>;
+}
+
 /// A generic trait that describes a signature scheme.
 ///
+/// This trait is implemented for every `Signer`; new code should prefer
+/// `Signer`, `Verifier` and `KeyGen`.
+///
 /// The general workflow is:
 /// - Instantiating a signature scheme generates a new key pair.
 ///   Concrete instantiations have to provide their own way of instantiating
@@ -28,3 +68,17 @@ pub trait SignatureScheme<PK, M, SIG> {
     /// which is typically not available for the verifier.
     fn verify(pk: PK, message: M, signature: &SIG) -> bool;
 }
+
+impl<S: Signer> SignatureScheme<S::PublicKey, S::Message, S::Signature> for S {
+    fn public_key(&self) -> S::PublicKey {
+        Signer::public_key(self)
+    }
+
+    fn sign(&mut self, message: S::Message) -> S::Signature {
+        Signer::sign(self, message)
+    }
+
+    fn verify(pk: S::PublicKey, message: S::Message, signature: &S::Signature) -> bool {
+        <S as Verifier>::verify(&pk, &message, signature)
+    }
+}
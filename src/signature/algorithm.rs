@@ -0,0 +1,232 @@
+use crate::error::Error;
+use crate::signature::basic_lamport::{
+    BasicLamportKey, BasicLamportSignature, BasicLamportSignatureScheme,
+};
+use crate::signature::stateless_merkle::{
+    StatelessMerkleParams, StatelessMerkleSignature, StatelessMerkleSignatureScheme,
+};
+use crate::signature::winternitz::d::D;
+use crate::signature::winternitz::{WinternitzKey, WinternitzSignature, WinternitzSignatureScheme};
+use crate::signature::{HashType, KeyGen, Signer, Verifier};
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+
+/// A registry of the signature schemes that sign 256-bit messages, along with their parameters.
+///
+/// This allows picking a scheme at runtime, e.g. from a configuration file.
+/// `QIndexedSignatureScheme` is not included, because it also needs an index to sign a message.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::signature::algorithm::{AnySigner, SignatureAlgorithm};
+/// use hash_based_signatures::signature::{KeyGen, Signer, Verifier};
+///
+/// let algorithm: SignatureAlgorithm =
+///     serde_json::from_str(r#"{"algorithm": "winternitz", "d": 15}"#).unwrap();
+/// let mut signer = AnySigner::key_gen([0u8; 32], &algorithm).unwrap();
+/// let signature = signer.sign([1u8; 32]);
+/// assert!(AnySigner::verify(&signer.public_key(), &[1u8; 32], &signature));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum SignatureAlgorithm {
+    BasicLamport,
+    Winternitz { d: u64 },
+    StatelessMerkle { width: usize, depth: usize, d: u64 },
+}
+
+/// A signer of any of the schemes listed in `SignatureAlgorithm`.
+pub enum AnySigner {
+    BasicLamport(Box<BasicLamportSignatureScheme>),
+    Winternitz(WinternitzSignatureScheme),
+    StatelessMerkle(StatelessMerkleSignatureScheme),
+}
+
+/// A public key of any of the schemes listed in `SignatureAlgorithm`.
+// `BigArray` can't (de)serialize a boxed key, so the Lamport variant stays large.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum AnyPublicKey {
+    BasicLamport(#[serde(with = "BigArray")] BasicLamportKey),
+    Winternitz(WinternitzKey),
+    StatelessMerkle(HashType),
+}
+
+/// A signature of any of the schemes listed in `SignatureAlgorithm`.
+#[derive(PartialEq, Serialize, Deserialize)]
+pub enum AnySignature {
+    BasicLamport(Box<BasicLamportSignature>),
+    Winternitz(WinternitzSignature),
+    StatelessMerkle(StatelessMerkleSignature),
+}
+
+impl AnySigner {
+    /// The algorithm and parameters of this signer.
+    pub fn algorithm(&self) -> SignatureAlgorithm {
+        match self {
+            AnySigner::BasicLamport(_) => SignatureAlgorithm::BasicLamport,
+            AnySigner::Winternitz(scheme) => SignatureAlgorithm::Winternitz { d: scheme.d().d },
+            AnySigner::StatelessMerkle(scheme) => {
+                let params = scheme.params();
+                SignatureAlgorithm::StatelessMerkle {
+                    width: params.width,
+                    depth: params.depth,
+                    d: params.d.d,
+                }
+            }
+        }
+    }
+}
+
+impl Verifier for AnySigner {
+    type PublicKey = AnyPublicKey;
+    type Message = HashType;
+    type Signature = AnySignature;
+
+    /// Verifies a signature.
+    /// Signatures of a different algorithm than the public key are rejected.
+    fn verify(pk: &AnyPublicKey, message: &HashType, signature: &AnySignature) -> bool {
+        match (pk, signature) {
+            (AnyPublicKey::BasicLamport(pk), AnySignature::BasicLamport(signature)) => {
+                BasicLamportSignatureScheme::verify(pk, message, signature)
+            }
+            (AnyPublicKey::Winternitz(pk), AnySignature::Winternitz(signature)) => {
+                WinternitzSignatureScheme::verify(pk, message, signature)
+            }
+            (AnyPublicKey::StatelessMerkle(pk), AnySignature::StatelessMerkle(signature)) => {
+                StatelessMerkleSignatureScheme::verify(pk, message, signature)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Signer for AnySigner {
+    fn public_key(&self) -> AnyPublicKey {
+        match self {
+            AnySigner::BasicLamport(scheme) => AnyPublicKey::BasicLamport(scheme.public_key()),
+            AnySigner::Winternitz(scheme) => AnyPublicKey::Winternitz(scheme.public_key()),
+            AnySigner::StatelessMerkle(scheme) => {
+                AnyPublicKey::StatelessMerkle(scheme.public_key())
+            }
+        }
+    }
+
+    /// Signs a message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a one-time scheme is used to sign *different* messages.
+    fn sign(&mut self, message: HashType) -> AnySignature {
+        match self {
+            AnySigner::BasicLamport(scheme) => {
+                AnySignature::BasicLamport(Box::new(scheme.sign(message)))
+            }
+            AnySigner::Winternitz(scheme) => AnySignature::Winternitz(scheme.sign(message)),
+            AnySigner::StatelessMerkle(scheme) => {
+                AnySignature::StatelessMerkle(scheme.sign(message))
+            }
+        }
+    }
+}
+
+impl KeyGen for AnySigner {
+    type Params = SignatureAlgorithm;
+
+    fn key_gen(seed: HashType, algorithm: &SignatureAlgorithm) -> Result<Self, Error> {
+        Ok(match *algorithm {
+            SignatureAlgorithm::BasicLamport => {
+                AnySigner::BasicLamport(Box::new(BasicLamportSignatureScheme::key_gen(seed, &())?))
+            }
+            SignatureAlgorithm::Winternitz { d } => {
+                AnySigner::Winternitz(WinternitzSignatureScheme::key_gen(seed, &D::try_new(d)?)?)
+            }
+            SignatureAlgorithm::StatelessMerkle { width, depth, d } => {
+                let params = StatelessMerkleParams {
+                    width,
+                    depth,
+                    d: D::try_new(d)?,
+                };
+                AnySigner::StatelessMerkle(StatelessMerkleSignatureScheme::key_gen(seed, &params)?)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::signature::algorithm::{AnySigner, SignatureAlgorithm};
+    use crate::signature::{KeyGen, Signer, Verifier};
+
+    fn algorithms() -> Vec<SignatureAlgorithm> {
+        vec![
+            SignatureAlgorithm::BasicLamport,
+            SignatureAlgorithm::Winternitz { d: 15 },
+            SignatureAlgorithm::StatelessMerkle {
+                width: 4,
+                depth: 3,
+                d: 15,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_correct_signatures() {
+        for algorithm in algorithms() {
+            let mut signer = AnySigner::key_gen([0u8; 32], &algorithm).unwrap();
+            assert_eq!(signer.algorithm(), algorithm);
+
+            let signature = signer.sign([1u8; 32]);
+            assert!(AnySigner::verify(
+                &signer.public_key(),
+                &[1u8; 32],
+                &signature
+            ));
+            assert!(!AnySigner::verify(
+                &signer.public_key(),
+                &[2u8; 32],
+                &signature
+            ));
+        }
+    }
+
+    #[test]
+    fn test_rejects_signature_of_other_algorithm() {
+        let mut winternitz =
+            AnySigner::key_gen([0u8; 32], &SignatureAlgorithm::Winternitz { d: 15 }).unwrap();
+        let lamport = AnySigner::key_gen([0u8; 32], &SignatureAlgorithm::BasicLamport).unwrap();
+
+        let signature = winternitz.sign([1u8; 32]);
+        assert!(!AnySigner::verify(
+            &lamport.public_key(),
+            &[1u8; 32],
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_parse_algorithm() {
+        let algorithm: SignatureAlgorithm = serde_json::from_str(
+            r#"{"algorithm": "stateless_merkle", "width": 16, "depth": 32, "d": 15}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            algorithm,
+            SignatureAlgorithm::StatelessMerkle {
+                width: 16,
+                depth: 32,
+                d: 15
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert_eq!(
+            AnySigner::key_gen([0u8; 32], &SignatureAlgorithm::Winternitz { d: 16 }).err(),
+            Some(Error::InvalidD(16))
+        );
+    }
+}
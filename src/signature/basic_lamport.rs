@@ -3,8 +3,10 @@ use rand_chacha::ChaCha20Rng;
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
 
-use crate::signature::{HashType, SignatureScheme};
-use crate::utils::hash;
+use crate::error::Error;
+use crate::secret::Secret;
+use crate::signature::{HashType, KeyGen, Signer, Verifier};
+use crate::utils::{ct_eq, hash};
 
 pub type BasicLamportKey = [[[u8; 32]; 2]; 256];
 
@@ -29,19 +31,19 @@ pub struct BasicLamportSignature {
 ///
 /// ```
 /// use hash_based_signatures::signature::basic_lamport::BasicLamportSignatureScheme;
-/// use hash_based_signatures::signature::SignatureScheme;
+/// use hash_based_signatures::signature::{Signer, Verifier};
 ///
 /// let mut signature_scheme = BasicLamportSignatureScheme::new([0u8; 32]);
 /// let signature = signature_scheme.sign([1u8; 32]);
 /// assert!(BasicLamportSignatureScheme::verify(
-///     signature_scheme.public_key(),
-///     [1u8; 32],
+///     &signature_scheme.public_key(),
+///     &[1u8; 32],
 ///     &signature
 /// ))
 /// ```
 #[derive(Clone)]
 pub struct BasicLamportSignatureScheme {
-    sk: BasicLamportKey,
+    sk: Secret<BasicLamportKey>,
     pk: BasicLamportKey,
     message: Option<HashType>,
 }
@@ -50,20 +52,14 @@ impl BasicLamportSignatureScheme {
     /// Generates a new one-time key pair from the given `seed` and instantiates the scheme.
     pub fn new(seed: [u8; 32]) -> Self {
         let mut rng = ChaCha20Rng::from_seed(seed);
-        let mut sk = [[[0; 32]; 2]; 256];
-
-        // create secrets
-        for bit_to_sign in 0..256 {
-            for bit in 0..2 {
-                rng.fill_bytes(&mut sk[bit_to_sign][bit]);
-            }
-        }
-
-        // hash secrets to public keys
+        let mut sk = Secret::new([[[0; 32]; 2]; 256]);
         let mut pk = [[[0; 32]; 2]; 256];
-        for bit_to_sign in 0..256 {
-            for bit in 0..2 {
-                pk[bit_to_sign][bit] = hash(&sk[bit_to_sign][bit]);
+
+        // create secrets and hash them to public keys
+        for (secrets, public_keys) in sk.iter_mut().zip(pk.iter_mut()) {
+            for (secret, public_key) in secrets.iter_mut().zip(public_keys.iter_mut()) {
+                rng.fill_bytes(secret);
+                *public_key = hash(secret);
             }
         }
         Self {
@@ -72,33 +68,22 @@ impl BasicLamportSignatureScheme {
             message: None,
         }
     }
-}
-
-impl SignatureScheme<BasicLamportKey, HashType, BasicLamportSignature>
-    for BasicLamportSignatureScheme
-{
-    fn public_key(&self) -> BasicLamportKey {
-        self.pk
-    }
 
     /// Signs a message.
     ///
-    /// # Panics
-    ///
-    /// Panics if the scheme is used more than once to sign *different* messages.
+    /// Fails if the scheme is used more than once to sign *different* messages.
     /// Note that there could still be a different instance with the same secret key,
     /// which would not be detected.
-    fn sign(&mut self, message: HashType) -> BasicLamportSignature {
+    pub fn try_sign(&mut self, message: HashType) -> Result<BasicLamportSignature, Error> {
         if let Some(existing_message) = self.message {
             if existing_message != message {
-                panic!("One-time signature has been used to sign more than one message!")
+                return Err(Error::OneTimeKeyReuse);
             }
         }
         self.message = Some(message);
 
         let mut signature: [[u8; 32]; 256] = [[0; 32]; 256];
-        for byte_index in 0..32 {
-            let byte = message[byte_index];
+        for (byte_index, byte) in message.iter().enumerate() {
             for local_bit_index in 0..8 {
                 let bit_index = byte_index * 8 + local_bit_index;
                 if byte & (1 << local_bit_index) != 0 {
@@ -108,28 +93,59 @@ impl SignatureScheme<BasicLamportKey, HashType, BasicLamportSignature>
                 }
             }
         }
-        BasicLamportSignature {
+        Ok(BasicLamportSignature {
             preimages: signature,
-        }
+        })
     }
+}
+
+impl Verifier for BasicLamportSignatureScheme {
+    type PublicKey = BasicLamportKey;
+    type Message = HashType;
+    type Signature = BasicLamportSignature;
 
-    fn verify(pk: BasicLamportKey, message: HashType, signature: &BasicLamportSignature) -> bool {
+    fn verify(pk: &BasicLamportKey, message: &HashType, signature: &BasicLamportSignature) -> bool {
         let mut is_correct = true;
-        for byte_index in 0..32 {
-            let byte = message[byte_index];
+        for (byte_index, byte) in message.iter().enumerate() {
             for local_bit_index in 0..8 {
                 let bit_index = byte_index * 8 + local_bit_index;
                 let hash = hash(&signature.preimages[bit_index]);
                 let pk_index_to_expect = (byte & (1 << local_bit_index) != 0) as usize;
-                is_correct &= hash == pk[bit_index][pk_index_to_expect];
+                is_correct &= ct_eq(&hash, &pk[bit_index][pk_index_to_expect]);
             }
         }
         is_correct
     }
 }
 
+impl Signer for BasicLamportSignatureScheme {
+    fn public_key(&self) -> BasicLamportKey {
+        self.pk
+    }
+
+    /// Signs a message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scheme is used more than once to sign *different* messages.
+    /// Consider using `BasicLamportSignatureScheme::try_sign()`.
+    fn sign(&mut self, message: HashType) -> BasicLamportSignature {
+        self.try_sign(message)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+}
+
+impl KeyGen for BasicLamportSignatureScheme {
+    type Params = ();
+
+    fn key_gen(seed: HashType, _params: &()) -> Result<Self, Error> {
+        Ok(Self::new(seed))
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::signature::basic_lamport::{BasicLamportSignature, BasicLamportSignatureScheme};
     use crate::signature::SignatureScheme;
 
@@ -176,4 +192,15 @@ mod tests {
         signature_scheme.sign([1u8; 32]);
         signature_scheme.sign([2u8; 32]);
     }
+
+    #[test]
+    fn test_try_sign_different_messages() {
+        let mut signature_scheme = get_signature_scheme();
+        assert!(signature_scheme.try_sign([1u8; 32]).is_ok());
+        assert!(signature_scheme.try_sign([1u8; 32]).is_ok());
+        assert_eq!(
+            signature_scheme.try_sign([2u8; 32]).err(),
+            Some(Error::OneTimeKeyReuse)
+        );
+    }
 }
@@ -0,0 +1,158 @@
+use crate::error::Error;
+use crate::merkle_tree::encoding::LeafEncoding;
+use crate::signature::HashType;
+use crate::utils::try_string_to_hash;
+use data_encoding::HEXLOWER;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// How the Merkle trees of a key commit to its one-time public keys.
+///
+/// The format is part of the public key: The same seed results in a different public key for
+/// each format, and a signature only verifies for the format of its key. Therefore, verifiers
+/// need to know the format from the same trusted source as the public key, which is why it is
+/// included in the textual form of the key (see `VersionedPublicKey`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// Version 0: Leaves are encoded using MessagePack (`LeafEncoding::MessagePack`).
+    /// Keys created before the format was versioned use this format.
+    Legacy = 0,
+    /// Version 1: Leaves are encoded canonically (`LeafEncoding::Canonical`).
+    Canonical = 1,
+    /// Version 2: Like `KeyFormat::Canonical`, but the one-time public keys are compressed
+    /// using an L-tree (see `QIndexedSignatureScheme::try_with_compressed_public_keys()`).
+    Compressed = 2,
+}
+
+impl KeyFormat {
+    /// The format of new keys.
+    pub const CURRENT: KeyFormat = KeyFormat::Canonical;
+
+    /// The format of keys with the given leaf encoding and compression of their one-time
+    /// public keys, or `None` for a combination that is not supported.
+    pub fn new(leaf_encoding: LeafEncoding, compressed_public_keys: bool) -> Option<Self> {
+        match (leaf_encoding, compressed_public_keys) {
+            (LeafEncoding::MessagePack, false) => Some(KeyFormat::Legacy),
+            (LeafEncoding::Canonical, false) => Some(KeyFormat::Canonical),
+            (LeafEncoding::Canonical, true) => Some(KeyFormat::Compressed),
+            // Signatures encode whether keys are compressed in a field that requires
+            // a non-legacy encoding, see `QIndexedSignature`.
+            (LeafEncoding::MessagePack, true) => None,
+        }
+    }
+
+    /// The encoding of the leaves of the Merkle trees.
+    pub fn leaf_encoding(&self) -> LeafEncoding {
+        match self {
+            KeyFormat::Legacy => LeafEncoding::MessagePack,
+            KeyFormat::Canonical | KeyFormat::Compressed => LeafEncoding::Canonical,
+        }
+    }
+
+    /// Whether the one-time public keys are compressed before they are put into the Merkle trees.
+    pub fn compressed_public_keys(&self) -> bool {
+        *self == KeyFormat::Compressed
+    }
+}
+
+/// A public key along with its format.
+///
+/// Its textual form is the hex-encoded public key, prefixed by the version of the format,
+/// e.g. `v1:9e25...`. Keys in the legacy format have no prefix, so that public keys that were
+/// published before the format was versioned keep verifying as is.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::signature::key_format::{KeyFormat, VersionedPublicKey};
+///
+/// let key: VersionedPublicKey = format!("v1:{}", "00".repeat(32)).parse().unwrap();
+/// assert_eq!(key.format, KeyFormat::Canonical);
+/// assert_eq!(key.public_key, [0u8; 32]);
+///
+/// let legacy_key: VersionedPublicKey = "00".repeat(32).parse().unwrap();
+/// assert_eq!(legacy_key.format, KeyFormat::Legacy);
+/// assert_eq!(legacy_key.to_string(), "00".repeat(32));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionedPublicKey {
+    pub public_key: HashType,
+    pub format: KeyFormat,
+}
+
+impl VersionedPublicKey {
+    pub fn new(public_key: HashType, format: KeyFormat) -> Self {
+        Self { public_key, format }
+    }
+}
+
+impl Display for VersionedPublicKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.format {
+            KeyFormat::Legacy => write!(f, "{}", HEXLOWER.encode(&self.public_key)),
+            format => write!(f, "v{}:{}", format as u8, HEXLOWER.encode(&self.public_key)),
+        }
+    }
+}
+
+impl FromStr for VersionedPublicKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let (format, public_key) = match s.split_once(':') {
+            None => (KeyFormat::Legacy, s),
+            Some(("v1", public_key)) => (KeyFormat::Canonical, public_key),
+            Some(("v2", public_key)) => (KeyFormat::Compressed, public_key),
+            Some((version, _)) => return Err(Error::UnknownKeyVersion(version.to_string())),
+        };
+        Ok(Self {
+            public_key: try_string_to_hash(public_key)?,
+            format,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::merkle_tree::encoding::LeafEncoding;
+    use crate::signature::key_format::{KeyFormat, VersionedPublicKey};
+
+    #[test]
+    fn test_roundtrip() {
+        for format in [
+            KeyFormat::Legacy,
+            KeyFormat::Canonical,
+            KeyFormat::Compressed,
+        ] {
+            let key = VersionedPublicKey::new([7u8; 32], format);
+            assert_eq!(key.to_string().parse(), Ok(key));
+            assert_eq!(
+                KeyFormat::new(format.leaf_encoding(), format.compressed_public_keys()),
+                Some(format)
+            );
+        }
+        assert_eq!(KeyFormat::new(LeafEncoding::MessagePack, true), None);
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        let hex = "07".repeat(32);
+        assert_eq!(
+            format!("v0:{hex}").parse::<VersionedPublicKey>(),
+            Err(Error::UnknownKeyVersion("v0".to_string()))
+        );
+        assert_eq!(
+            format!("v3:{hex}").parse::<VersionedPublicKey>(),
+            Err(Error::UnknownKeyVersion("v3".to_string()))
+        );
+        assert!(matches!(
+            format!("v1:{}", &hex[2..]).parse::<VersionedPublicKey>(),
+            Err(Error::InvalidHashLength(31))
+        ));
+        assert!(matches!(
+            format!("v1:v1:{hex}").parse::<VersionedPublicKey>(),
+            Err(Error::InvalidHex(_))
+        ));
+    }
+}
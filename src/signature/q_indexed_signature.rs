@@ -1,10 +1,18 @@
+pub mod sequential;
+
+use crate::error::Error;
+use crate::merkle_tree::encoding::LeafEncoding;
 use crate::merkle_tree::{MerkleProof, MerkleTree};
+use crate::signature::key_format::KeyFormat;
+use crate::signature::winternitz::checkpoints::WinternitzCheckpoints;
 use crate::signature::winternitz::d::D;
+use crate::signature::winternitz::l_tree::l_tree;
 use crate::signature::winternitz::{WinternitzKey, WinternitzSignature, WinternitzSignatureScheme};
-use crate::signature::{HashType, SignatureScheme};
+use crate::signature::{HashType, KeyGen, Signer, Verifier};
 use rand::prelude::*;
 use rand_chacha::ChaCha20Rng;
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
 
 /// The q-indexed signature scheme, as described in Section 14.6.1
 /// in the [textbook](http://toc.cryptobook.us/) by Boneh & Shoup.
@@ -15,11 +23,17 @@ use serde::{Deserialize, Serialize};
 /// The signatures contains the one-time public key that was used, along with a Merkle
 /// proof.
 ///
+/// Optionally, the one-time public keys are compressed into a single hash using an L-tree
+/// before they are put into the Merkle tree (see
+/// `QIndexedSignatureScheme::try_with_compressed_public_keys()`).
+/// `SequentialQIndexedSignatureScheme` and `StatelessMerkleSignatureScheme`, which are built
+/// on this scheme, support this as well (`KeyFormat::Compressed`).
+///
 /// # Examples
 ///
 /// ```
 /// use hash_based_signatures::signature::q_indexed_signature::QIndexedSignatureScheme;
-/// use hash_based_signatures::signature::SignatureScheme;
+/// use hash_based_signatures::signature::{Signer, Verifier};
 /// use hash_based_signatures::signature::winternitz::d::D;
 ///
 /// let mut signature_scheme = QIndexedSignatureScheme::new(2, [0; 32], D::new(255));
@@ -27,13 +41,13 @@ use serde::{Deserialize, Serialize};
 /// let signature1 = signature_scheme.sign((1, [1u8; 32]));
 ///
 /// assert!(QIndexedSignatureScheme::verify(
-///     signature_scheme.public_key(),
-///     (0, [0u8; 32]),
+///     &signature_scheme.public_key(),
+///     &(0, [0u8; 32]),
 ///     &signature0
 /// ));
 /// assert!(QIndexedSignatureScheme::verify(
-///     signature_scheme.public_key(),
-///     (1, [1u8; 32]),
+///     &signature_scheme.public_key(),
+///     &(1, [1u8; 32]),
 ///     &signature1
 /// ));
 /// ```
@@ -41,9 +55,19 @@ use serde::{Deserialize, Serialize};
 pub struct QIndexedSignatureScheme {
     one_time_signatures: Vec<WinternitzSignatureScheme>,
     public_key_merkle_tree: MerkleTree<WinternitzKey>,
+    compressed_public_keys: bool,
+}
+
+/// Parameters of `QIndexedSignatureScheme`
+#[derive(Clone, Copy)]
+pub struct QIndexedParams {
+    /// The number of messages that can be signed, needs to be a power of two
+    pub q: usize,
+    /// Winternitz parameter of the one-time signatures
+    pub d: D,
 }
 
-#[derive(PartialEq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct QIndexedSignature {
     /// Merkle proof used to verify that the used Winternitz public key
     /// is actually valid.
@@ -54,6 +78,33 @@ pub struct QIndexedSignature {
 
     /// Winternitz signature of the data being signed
     pub one_time_signature: WinternitzSignature,
+
+    /// The encoding of the Winternitz public keys in the leaves of the Merkle tree.
+    /// Omitted for the legacy encoding, so that signatures of existing keys do not change.
+    /// The verifier does not rely on it, but rejects signatures that claim another encoding
+    /// than the one of the key, see `QIndexedSignatureScheme::verify_with_encoding()`.
+    #[serde(
+        default = "LeafEncoding::legacy",
+        skip_serializing_if = "LeafEncoding::is_legacy"
+    )]
+    pub leaf_encoding: LeafEncoding,
+
+    /// Whether the leaves of the Merkle tree are the one-time public keys compressed using
+    /// `l_tree()`. Omitted if they are not.
+    /// Like `leaf_encoding`, it has to match the key, see `QIndexedSignatureScheme::verify_compressed()`.
+    /// Since fields are encoded positionally, this requires a non-legacy `leaf_encoding`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compressed_public_key: bool,
+}
+
+/// The datum in the leaf of the Merkle tree for the given one-time public key:
+/// Either the key itself, or a key consisting of its compressed form only.
+pub(crate) fn merkle_leaf(public_key: WinternitzKey, compressed_public_key: bool) -> WinternitzKey {
+    if compressed_public_key {
+        vec![l_tree(&public_key)]
+    } else {
+        public_key
+    }
 }
 
 impl QIndexedSignatureScheme {
@@ -62,53 +113,238 @@ impl QIndexedSignatureScheme {
     /// # Panics
     ///
     /// Panics if `q` is not a power of two.
+    /// Consider using `QIndexedSignatureScheme::try_new()`.
     pub fn new(q: usize, seed: [u8; 32], d: D) -> Self {
+        Self::try_new(q, seed, d).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Like `QIndexedSignatureScheme::new()`, but fails if `q` is not a power of two.
+    pub fn try_new(q: usize, seed: [u8; 32], d: D) -> Result<Self, Error> {
+        Self::try_with_encoding(q, seed, d, LeafEncoding::CURRENT)
+    }
+
+    /// Like `QIndexedSignatureScheme::try_new()`, but encodes the one-time public keys in the
+    /// leaves of the Merkle tree using `leaf_encoding`.
+    ///
+    /// This changes the public key, so existing keys need to keep using the encoding they
+    /// were created with.
+    pub fn try_with_encoding(
+        q: usize,
+        seed: [u8; 32],
+        d: D,
+        leaf_encoding: LeafEncoding,
+    ) -> Result<Self, Error> {
+        let format =
+            KeyFormat::new(leaf_encoding, false).expect("Uncompressed keys support all encodings");
+        Self::try_with_format(q, seed, d, format)
+    }
+
+    /// Like `QIndexedSignatureScheme::try_new()`, but compresses each one-time public key
+    /// into a single hash using an L-tree (see `l_tree()`) before putting it into the
+    /// Merkle tree. Leaf hashing becomes cheaper, and verifiers only need to handle
+    /// 32-byte one-time public keys.
+    ///
+    /// This changes the public key.
+    pub fn try_with_compressed_public_keys(q: usize, seed: [u8; 32], d: D) -> Result<Self, Error> {
+        Self::try_with_format(q, seed, d, KeyFormat::Compressed)
+    }
+
+    /// Like `QIndexedSignatureScheme::try_new()`, for a key in the given `format`, i.e. with the
+    /// leaf encoding and compression of one-time public keys of the format.
+    pub fn try_with_format(
+        q: usize,
+        seed: [u8; 32],
+        d: D,
+        format: KeyFormat,
+    ) -> Result<Self, Error> {
+        Self::build(q, seed, format, |seed| {
+            Ok(WinternitzSignatureScheme::new(seed, d))
+        })
+    }
+
+    /// Like `QIndexedSignatureScheme::try_new()`, for a key in the given `format`, which keeps
+    /// the checkpoints of the hash chains of all one-time keys (see
+    /// `WinternitzSignatureScheme::with_checkpoints()`).
+    ///
+    /// The scheme can then be rebuilt from `QIndexedSignatureScheme::checkpoints()` without
+    /// computing the hash chains again. Fails if `interval` is 0 or larger than `d`.
+    pub fn try_with_checkpoints(
+        q: usize,
+        seed: [u8; 32],
+        d: D,
+        format: KeyFormat,
+        interval: u8,
+    ) -> Result<Self, Error> {
+        Self::build(q, seed, format, |seed| {
+            WinternitzSignatureScheme::with_checkpoints(seed, d, interval)
+        })
+    }
+
+    /// Rebuilds the scheme created by `QIndexedSignatureScheme::try_with_checkpoints()` from the
+    /// checkpoints of its one-time keys, e.g. from a cache.
+    ///
+    /// Fails if there are not `q` checkpoints, or if they don't belong to the key
+    /// (see `WinternitzSignatureScheme::from_checkpoints()`).
+    pub fn from_checkpoints(
+        q: usize,
+        seed: [u8; 32],
+        d: D,
+        format: KeyFormat,
+        checkpoints: Vec<WinternitzCheckpoints>,
+    ) -> Result<Self, Error> {
+        if checkpoints.len() != q {
+            return Err(Error::CheckpointMismatch);
+        }
+        let mut checkpoints = checkpoints.into_iter();
+        Self::build(q, seed, format, |seed| {
+            let checkpoints = checkpoints.next().ok_or(Error::CheckpointMismatch)?;
+            WinternitzSignatureScheme::from_checkpoints(seed, d, checkpoints)
+        })
+    }
+
+    /// Builds the scheme from the one-time signature schemes created by `one_time_signature`
+    /// from the seeds derived from `seed`.
+    fn build(
+        q: usize,
+        seed: [u8; 32],
+        format: KeyFormat,
+        mut one_time_signature: impl FnMut([u8; 32]) -> Result<WinternitzSignatureScheme, Error>,
+    ) -> Result<Self, Error> {
+        if !q.is_power_of_two() {
+            return Err(Error::NotAPowerOfTwo(q));
+        }
+
         let mut rng = ChaCha20Rng::from_seed(seed);
         let mut seed_for_sub_scheme: [u8; 32] = [0; 32];
         let mut one_time_signatures = Vec::new();
         for _ in 0..q {
             rng.fill_bytes(&mut seed_for_sub_scheme);
-            one_time_signatures.push(WinternitzSignatureScheme::new(seed_for_sub_scheme, d));
+            match one_time_signature(seed_for_sub_scheme) {
+                Ok(signature_scheme) => one_time_signatures.push(signature_scheme),
+                Err(error) => {
+                    seed_for_sub_scheme.zeroize();
+                    return Err(error);
+                }
+            }
         }
+        seed_for_sub_scheme.zeroize();
 
-        let public_keys: Vec<WinternitzKey> =
-            one_time_signatures.iter().map(|s| s.public_key()).collect();
+        let compressed_public_keys = format.compressed_public_keys();
+        let public_keys: Vec<WinternitzKey> = one_time_signatures
+            .iter()
+            .map(|s| merkle_leaf(s.public_key(), compressed_public_keys))
+            .collect();
 
-        let public_key_merkle_tree = MerkleTree::new(&public_keys);
+        let public_key_merkle_tree =
+            MerkleTree::try_with_encoding(&public_keys, 2, format.leaf_encoding())?;
 
-        Self {
+        Ok(Self {
             one_time_signatures,
             public_key_merkle_tree,
-        }
+            compressed_public_keys,
+        })
     }
-}
 
-impl SignatureScheme<HashType, (usize, HashType), QIndexedSignature> for QIndexedSignatureScheme {
-    fn public_key(&self) -> HashType {
-        *self.public_key_merkle_tree.get_root_hash()
+    /// The checkpoints of the one-time keys, if the scheme was created with
+    /// `QIndexedSignatureScheme::try_with_checkpoints()` or `QIndexedSignatureScheme::from_checkpoints()`.
+    pub fn checkpoints(&self) -> Option<Vec<WinternitzCheckpoints>> {
+        self.one_time_signatures
+            .iter()
+            .map(|s| s.checkpoints().cloned())
+            .collect()
     }
 
-    /// Signs a message.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the scheme is used more than once to sign *different* messages with the
-    /// same index.
-    /// Note that there could still be a different instance with the same secret key,
-    /// which would not be detected.
-    fn sign(&mut self, message: (usize, HashType)) -> QIndexedSignature {
+    /// The format of the public key.
+    pub fn key_format(&self) -> KeyFormat {
+        KeyFormat::new(
+            self.public_key_merkle_tree.encoding(),
+            self.compressed_public_keys,
+        )
+        .expect("Compressed public keys always use the current encoding")
+    }
+
+    /// Signs a message, failing if the index is out of range.
+    pub fn try_sign(&mut self, message: (usize, HashType)) -> Result<QIndexedSignature, Error> {
         let (i, message) = message;
-        let proof = self.public_key_merkle_tree.get_proof(i);
-        QIndexedSignature {
+        let proof = self.public_key_merkle_tree.try_get_proof(i)?;
+        Ok(QIndexedSignature {
             proof,
             one_time_signature: self.one_time_signatures[i].sign(message),
-        }
+            leaf_encoding: self.public_key_merkle_tree.encoding(),
+            compressed_public_key: self.compressed_public_keys,
+        })
     }
+}
 
-    fn verify(pk: HashType, message: (usize, HashType), signature: &QIndexedSignature) -> bool {
-        let (i_m, message) = message;
+impl Verifier for QIndexedSignatureScheme {
+    type PublicKey = HashType;
+    type Message = (usize, HashType);
+    type Signature = QIndexedSignature;
 
-        if i_m != signature.proof.index {
+    /// Verifies a signature of a key created with `QIndexedSignatureScheme::try_new()`.
+    ///
+    /// For keys created with another leaf encoding or with compressed one-time public keys,
+    /// use `QIndexedSignatureScheme::verify_with_encoding()` or
+    /// `QIndexedSignatureScheme::verify_compressed()`.
+    fn verify(pk: &HashType, message: &(usize, HashType), signature: &QIndexedSignature) -> bool {
+        Self::verify_with_encoding(pk, message, signature, LeafEncoding::CURRENT)
+    }
+}
+
+impl QIndexedSignatureScheme {
+    /// Verifies a signature of a key created with
+    /// `QIndexedSignatureScheme::try_with_encoding()`.
+    ///
+    /// The encoding is part of the key, so it needs to come from the verifier:
+    /// Signatures that claim a different encoding are rejected.
+    pub fn verify_with_encoding(
+        pk: &HashType,
+        message: &(usize, HashType),
+        signature: &QIndexedSignature,
+        leaf_encoding: LeafEncoding,
+    ) -> bool {
+        Self::verify_key_format(pk, message, signature, leaf_encoding, false)
+    }
+
+    /// Verifies a signature of a key created with
+    /// `QIndexedSignatureScheme::try_with_compressed_public_keys()`.
+    pub fn verify_compressed(
+        pk: &HashType,
+        message: &(usize, HashType),
+        signature: &QIndexedSignature,
+    ) -> bool {
+        Self::verify_key_format(pk, message, signature, LeafEncoding::CURRENT, true)
+    }
+
+    /// Verifies a signature of a key in the given `format`, see `QIndexedSignatureScheme::key_format()`.
+    pub fn verify_with_format(
+        pk: &HashType,
+        message: &(usize, HashType),
+        signature: &QIndexedSignature,
+        format: KeyFormat,
+    ) -> bool {
+        Self::verify_key_format(
+            pk,
+            message,
+            signature,
+            format.leaf_encoding(),
+            format.compressed_public_keys(),
+        )
+    }
+
+    fn verify_key_format(
+        pk: &HashType,
+        message: &(usize, HashType),
+        signature: &QIndexedSignature,
+        leaf_encoding: LeafEncoding,
+        compressed_public_keys: bool,
+    ) -> bool {
+        let (i_m, message) = *message;
+
+        if i_m != signature.proof.index
+            || signature.leaf_encoding != leaf_encoding
+            || signature.compressed_public_key != compressed_public_keys
+        {
             return false;
         }
 
@@ -122,14 +358,46 @@ impl SignatureScheme<HashType, (usize, HashType), QIndexedSignature> for QIndexe
             &signature.one_time_signature,
         ) {
             Err(_) => false,
-            Ok(winternitz_pk) => signature.proof.verify(pk, &winternitz_pk),
+            Ok(winternitz_pk) => signature.proof.verify_with_encoding(
+                *pk,
+                &merkle_leaf(winternitz_pk, compressed_public_keys),
+                leaf_encoding,
+            ),
         }
     }
 }
 
+impl Signer for QIndexedSignatureScheme {
+    fn public_key(&self) -> HashType {
+        *self.public_key_merkle_tree.get_root_hash()
+    }
+
+    /// Signs a message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of range.
+    /// Consider using `QIndexedSignatureScheme::try_sign()`.
+    fn sign(&mut self, message: (usize, HashType)) -> QIndexedSignature {
+        self.try_sign(message)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+}
+
+impl KeyGen for QIndexedSignatureScheme {
+    type Params = QIndexedParams;
+
+    fn key_gen(seed: HashType, params: &QIndexedParams) -> Result<Self, Error> {
+        Self::try_new(params.q, seed, params.d)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::signature::q_indexed_signature::QIndexedSignatureScheme;
+    use crate::error::Error;
+    use crate::merkle_tree::encoding::LeafEncoding;
+    use crate::signature::key_format::KeyFormat;
+    use crate::signature::q_indexed_signature::{QIndexedSignature, QIndexedSignatureScheme};
     use crate::signature::winternitz::d::D;
     use crate::signature::SignatureScheme;
 
@@ -179,4 +447,164 @@ mod tests {
         signature_scheme.sign((0, [0u8; 32]));
         signature_scheme.sign((0, [0u8; 32]));
     }
+
+    #[test]
+    fn test_try_new_not_a_power_of_two() {
+        assert_eq!(
+            QIndexedSignatureScheme::try_new(3, [0u8; 32], D::new(255)).err(),
+            Some(Error::NotAPowerOfTwo(3))
+        );
+    }
+
+    #[test]
+    fn test_try_sign_index_out_of_range() {
+        let mut signature_scheme = get_signature_scheme();
+        assert_eq!(
+            signature_scheme.try_sign((4, [0u8; 32])).err(),
+            Some(Error::IndexOutOfRange { index: 4, size: 4 })
+        );
+    }
+
+    #[test]
+    fn test_compressed_public_keys() {
+        let mut signature_scheme =
+            QIndexedSignatureScheme::try_with_compressed_public_keys(4, [0u8; 32], D::new(15))
+                .unwrap();
+        assert_ne!(
+            signature_scheme.public_key(),
+            get_signature_scheme().public_key()
+        );
+
+        let signature = signature_scheme.sign((2, [2u8; 32]));
+        assert!(signature.compressed_public_key);
+        assert!(QIndexedSignatureScheme::verify_compressed(
+            &signature_scheme.public_key(),
+            &(2, [2u8; 32]),
+            &signature
+        ));
+        assert!(!QIndexedSignatureScheme::verify_compressed(
+            &signature_scheme.public_key(),
+            &(2, [3u8; 32]),
+            &signature
+        ));
+        // The verifier needs to know that the key is compressed
+        assert!(!QIndexedSignatureScheme::verify(
+            signature_scheme.public_key(),
+            (2, [2u8; 32]),
+            &signature
+        ));
+
+        // The flag is part of the serialized signature
+        let bytes = rmp_serde::to_vec(&signature).unwrap();
+        let deserialized: QIndexedSignature = rmp_serde::from_slice(&bytes).unwrap();
+        assert!(deserialized == signature);
+
+        // The signature can't claim that the key is not compressed
+        let mut uncompressed = signature;
+        uncompressed.compressed_public_key = false;
+        assert!(!QIndexedSignatureScheme::verify_compressed(
+            &signature_scheme.public_key(),
+            &(2, [2u8; 32]),
+            &uncompressed
+        ));
+    }
+
+    #[test]
+    fn test_leaf_encoding_is_part_of_the_key() {
+        let mut legacy = QIndexedSignatureScheme::try_with_encoding(
+            4,
+            [0u8; 32],
+            D::new(15),
+            LeafEncoding::MessagePack,
+        )
+        .unwrap();
+        let signature = legacy.sign((1, [1u8; 32]));
+        assert!(QIndexedSignatureScheme::verify_with_encoding(
+            &legacy.public_key(),
+            &(1, [1u8; 32]),
+            &signature,
+            LeafEncoding::MessagePack
+        ));
+        assert!(!QIndexedSignatureScheme::verify(
+            legacy.public_key(),
+            (1, [1u8; 32]),
+            &signature
+        ));
+
+        // A signature of a current key can't be verified using the legacy encoding
+        let mut current = get_signature_scheme();
+        let mut signature = current.sign((1, [1u8; 32]));
+        signature.leaf_encoding = LeafEncoding::MessagePack;
+        assert!(!QIndexedSignatureScheme::verify_with_encoding(
+            &current.public_key(),
+            &(1, [1u8; 32]),
+            &signature,
+            LeafEncoding::MessagePack
+        ));
+    }
+
+    #[test]
+    fn test_from_checkpoints() {
+        for format in [KeyFormat::Canonical, KeyFormat::Compressed] {
+            let mut with_checkpoints =
+                QIndexedSignatureScheme::try_with_checkpoints(4, [0u8; 32], D::new(15), format, 4)
+                    .unwrap();
+            assert_eq!(with_checkpoints.key_format(), format);
+            let checkpoints = with_checkpoints.checkpoints().unwrap();
+            assert_eq!(checkpoints.len(), 4);
+
+            let mut restored = QIndexedSignatureScheme::from_checkpoints(
+                4,
+                [0u8; 32],
+                D::new(15),
+                format,
+                checkpoints.clone(),
+            )
+            .unwrap();
+            assert_eq!(restored.public_key(), with_checkpoints.public_key());
+            assert!(restored.sign((2, [2u8; 32])) == with_checkpoints.sign((2, [2u8; 32])));
+
+            // Checkpoints of other keys, or not enough of them, are rejected
+            assert_eq!(
+                QIndexedSignatureScheme::from_checkpoints(
+                    4,
+                    [1u8; 32],
+                    D::new(15),
+                    format,
+                    checkpoints.clone()
+                )
+                .err(),
+                Some(Error::CheckpointMismatch)
+            );
+            assert_eq!(
+                QIndexedSignatureScheme::from_checkpoints(
+                    4,
+                    [0u8; 32],
+                    D::new(15),
+                    format,
+                    checkpoints[1..].to_vec()
+                )
+                .err(),
+                Some(Error::CheckpointMismatch)
+            );
+        }
+
+        let mut signature_scheme = get_signature_scheme();
+        assert!(signature_scheme.checkpoints().is_none());
+        let with_checkpoints = QIndexedSignatureScheme::try_with_checkpoints(
+            4,
+            [0u8; 32],
+            D::new(255),
+            KeyFormat::CURRENT,
+            16,
+        )
+        .unwrap();
+        assert_eq!(with_checkpoints.public_key(), signature_scheme.public_key());
+        let signature = signature_scheme.sign((1, [1u8; 32]));
+        assert!(QIndexedSignatureScheme::verify(
+            with_checkpoints.public_key(),
+            (1, [1u8; 32]),
+            &signature
+        ));
+    }
 }
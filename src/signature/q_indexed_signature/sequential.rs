@@ -0,0 +1,453 @@
+use crate::error::Error;
+use crate::merkle_tree::encoding::LeafEncoding;
+use crate::merkle_tree::traversal::AuthPathTraversal;
+use crate::merkle_tree::MerkleProof;
+use crate::secret::Secret;
+use crate::signature::key_format::KeyFormat;
+use crate::signature::q_indexed_signature::{
+    merkle_leaf, QIndexedSignature, QIndexedSignatureScheme,
+};
+use crate::signature::winternitz::d::D;
+use crate::signature::winternitz::WinternitzSignatureScheme;
+use crate::signature::{HashType, Signer, Verifier};
+use crate::utils::try_string_to_hash;
+use data_encoding::HEXLOWER;
+use rand::prelude::*;
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+use zeroize::Zeroize;
+
+/// A stateful variant of `QIndexedSignatureScheme`, which uses the one-time keys in order.
+///
+/// Instead of keeping all one-time keys and the whole Merkle tree in memory, it only keeps
+/// the seed and the state of an `AuthPathTraversal`, regenerating the one-time keys as
+/// needed. Its public key and signatures are the same as those of the
+/// `QIndexedSignatureScheme` with the same parameters, so signatures verify with
+/// `QIndexedSignatureScheme::verify()`, given the index of the signature.
+/// Like there, the one-time public keys can be compressed
+/// (see `SequentialQIndexedSignatureScheme::try_with_format()`).
+///
+/// Since the state changes with every signature, it needs to be persisted (see
+/// `SequentialQIndexedSignatureScheme::private_key()`) before a signature is published.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::signature::q_indexed_signature::sequential::SequentialQIndexedSignatureScheme;
+/// use hash_based_signatures::signature::{Signer, Verifier};
+/// use hash_based_signatures::signature::winternitz::d::D;
+///
+/// let mut signature_scheme = SequentialQIndexedSignatureScheme::new(4, [0; 32], D::new(15));
+/// let signature0 = signature_scheme.sign([0u8; 32]);
+/// let signature1 = signature_scheme.sign([1u8; 32]);
+///
+/// assert!(SequentialQIndexedSignatureScheme::verify(
+///     &signature_scheme.public_key(),
+///     &[1u8; 32],
+///     &signature1
+/// ));
+/// ```
+pub struct SequentialQIndexedSignatureScheme {
+    seed: Secret<HashType>,
+    d: D,
+    leaf_encoding: LeafEncoding,
+    compressed_public_keys: bool,
+    public_key: HashType,
+    traversal: AuthPathTraversal,
+}
+
+/// Serialized form of `SequentialQIndexedSignatureScheme`, including the traversal state.
+#[derive(Serialize, Deserialize)]
+pub struct SequentialQIndexedPrivateKey {
+    pub seed_hex: String,
+    pub d: u64,
+    pub leaf_encoding: LeafEncoding,
+    /// Key files written before one-time public keys could be compressed don't contain it.
+    #[serde(default)]
+    pub compressed_public_keys: bool,
+    pub public_key: String,
+    /// The number of one-time keys and the index of the next one are part of this state
+    pub traversal: AuthPathTraversal,
+}
+
+impl Drop for SequentialQIndexedPrivateKey {
+    fn drop(&mut self) {
+        self.seed_hex.zeroize();
+    }
+}
+
+impl Debug for SequentialQIndexedPrivateKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SequentialQIndexedPrivateKey")
+            .field("seed_hex", &"[REDACTED]")
+            .field("d", &self.d)
+            .field("leaf_encoding", &self.leaf_encoding)
+            .field("compressed_public_keys", &self.compressed_public_keys)
+            .field("public_key", &self.public_key)
+            .field("traversal", &self.traversal)
+            .finish()
+    }
+}
+
+/// The number of levels below the root whose nodes are kept, see
+/// `AuthPathTraversal::with_retained_levels()`. This stores at most 30 nodes and saves
+/// 8 one-time key generations per signature.
+const RETAINED_LEVELS: usize = 4;
+
+/// Derives the seed of the `index`-th one-time key, like `QIndexedSignatureScheme` does.
+fn one_time_signature(seed: &HashType, d: D, index: usize) -> WinternitzSignatureScheme {
+    let mut rng = ChaCha20Rng::from_seed(*seed);
+    // Each seed consumes 8 words of the stream
+    rng.set_word_pos(8 * index as u128);
+    let mut seed_for_sub_scheme: [u8; 32] = [0; 32];
+    rng.fill_bytes(&mut seed_for_sub_scheme);
+    let one_time_signature = WinternitzSignatureScheme::new(seed_for_sub_scheme, d);
+    seed_for_sub_scheme.zeroize();
+    one_time_signature
+}
+
+/// The hash of the `index`-th leaf of the Merkle tree, like `QIndexedSignatureScheme` computes it.
+fn leaf_hash(seed: &HashType, d: D, format: KeyFormat, index: usize) -> HashType {
+    let public_key = one_time_signature(seed, d, index).public_key();
+    format
+        .leaf_encoding()
+        .leaf_hash(&merkle_leaf(public_key, format.compressed_public_keys()))
+}
+
+impl SequentialQIndexedSignatureScheme {
+    /// Builds the scheme from the given `seed`, computing all one-time public keys once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` is not a power of two.
+    /// Consider using `SequentialQIndexedSignatureScheme::try_new()`.
+    pub fn new(q: usize, seed: [u8; 32], d: D) -> Self {
+        Self::try_new(q, seed, d).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Like `SequentialQIndexedSignatureScheme::new()`, but fails if `q` is not a power of two.
+    pub fn try_new(q: usize, seed: [u8; 32], d: D) -> Result<Self, Error> {
+        Self::try_with_encoding(q, seed, d, LeafEncoding::CURRENT)
+    }
+
+    /// Like `SequentialQIndexedSignatureScheme::try_new()`, but encodes the one-time public keys
+    /// using `leaf_encoding`, see `QIndexedSignatureScheme::try_with_encoding()`.
+    pub fn try_with_encoding(
+        q: usize,
+        seed: [u8; 32],
+        d: D,
+        leaf_encoding: LeafEncoding,
+    ) -> Result<Self, Error> {
+        let format =
+            KeyFormat::new(leaf_encoding, false).expect("Uncompressed keys support all encodings");
+        Self::try_with_format(q, seed, d, format)
+    }
+
+    /// Like `SequentialQIndexedSignatureScheme::try_new()`, for a key in the given `format`,
+    /// see `QIndexedSignatureScheme::try_with_format()`.
+    pub fn try_with_format(
+        q: usize,
+        seed: [u8; 32],
+        d: D,
+        format: KeyFormat,
+    ) -> Result<Self, Error> {
+        if !q.is_power_of_two() {
+            return Err(Error::NotAPowerOfTwo(q));
+        }
+        let seed = Secret::new(seed);
+        let (traversal, public_key) = AuthPathTraversal::with_retained_levels(
+            q.trailing_zeros() as usize,
+            RETAINED_LEVELS,
+            format.leaf_encoding(),
+            |i| leaf_hash(&seed, d, format, i),
+        );
+        Ok(Self {
+            seed,
+            d,
+            leaf_encoding: format.leaf_encoding(),
+            compressed_public_keys: format.compressed_public_keys(),
+            public_key,
+            traversal,
+        })
+    }
+
+    /// Restores the scheme from its private key, failing if the traversal state is
+    /// inconsistent (see `AuthPathTraversal::validate()`).
+    pub fn from_private_key(key: &SequentialQIndexedPrivateKey) -> Result<Self, Error> {
+        key.traversal.validate()?;
+        if KeyFormat::new(key.leaf_encoding, key.compressed_public_keys).is_none() {
+            return Err(Error::UnsupportedKeyFormat);
+        }
+        Ok(Self {
+            seed: Secret::new(try_string_to_hash(&key.seed_hex)?),
+            d: D::try_new(key.d)?,
+            leaf_encoding: key.leaf_encoding,
+            compressed_public_keys: key.compressed_public_keys,
+            public_key: try_string_to_hash(&key.public_key)?,
+            traversal: key.traversal.clone(),
+        })
+    }
+
+    pub fn private_key(&self) -> SequentialQIndexedPrivateKey {
+        SequentialQIndexedPrivateKey {
+            seed_hex: HEXLOWER.encode(&*self.seed),
+            d: self.d.d,
+            leaf_encoding: self.leaf_encoding,
+            compressed_public_keys: self.compressed_public_keys,
+            public_key: HEXLOWER.encode(&self.public_key),
+            traversal: self.traversal.clone(),
+        }
+    }
+
+    /// The format of the public key.
+    pub fn key_format(&self) -> KeyFormat {
+        KeyFormat::new(self.leaf_encoding, self.compressed_public_keys)
+            .expect("Checked when creating the scheme")
+    }
+
+    /// The index of the one-time key used for the next signature.
+    pub fn next_index(&self) -> usize {
+        self.traversal.next_leaf()
+    }
+
+    /// Signs a message with the next one-time key, failing if all of them have been used.
+    pub fn try_sign(&mut self, message: HashType) -> Result<QIndexedSignature, Error> {
+        let (seed, d, format) = (&self.seed, self.d, self.key_format());
+        let (index, hash_chain) = self
+            .traversal
+            .next(|i| leaf_hash(seed, d, format, i))
+            .ok_or(Error::KeyExhausted(self.traversal.leaf_count()))?;
+        Ok(QIndexedSignature {
+            proof: MerkleProof::from_hash_chain(index, hash_chain),
+            one_time_signature: one_time_signature(seed, d, index).sign(message),
+            leaf_encoding: format.leaf_encoding(),
+            compressed_public_key: format.compressed_public_keys(),
+        })
+    }
+
+    /// Verifies a signature of a key created with
+    /// `SequentialQIndexedSignatureScheme::try_with_encoding()`,
+    /// see `QIndexedSignatureScheme::verify_with_encoding()`.
+    pub fn verify_with_encoding(
+        pk: &HashType,
+        message: &HashType,
+        signature: &QIndexedSignature,
+        leaf_encoding: LeafEncoding,
+    ) -> bool {
+        QIndexedSignatureScheme::verify_with_encoding(
+            pk,
+            &(signature.proof.index, *message),
+            signature,
+            leaf_encoding,
+        )
+    }
+
+    /// Verifies a signature of a key in the given `format`,
+    /// see `QIndexedSignatureScheme::verify_with_format()`.
+    pub fn verify_with_format(
+        pk: &HashType,
+        message: &HashType,
+        signature: &QIndexedSignature,
+        format: KeyFormat,
+    ) -> bool {
+        QIndexedSignatureScheme::verify_with_format(
+            pk,
+            &(signature.proof.index, *message),
+            signature,
+            format,
+        )
+    }
+}
+
+impl Verifier for SequentialQIndexedSignatureScheme {
+    type PublicKey = HashType;
+    type Message = HashType;
+    type Signature = QIndexedSignature;
+
+    fn verify(pk: &HashType, message: &HashType, signature: &QIndexedSignature) -> bool {
+        QIndexedSignatureScheme::verify(pk, &(signature.proof.index, *message), signature)
+    }
+}
+
+impl Signer for SequentialQIndexedSignatureScheme {
+    fn public_key(&self) -> HashType {
+        self.public_key
+    }
+
+    /// Signs a message with the next one-time key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if all one-time keys have been used.
+    /// Consider using `SequentialQIndexedSignatureScheme::try_sign()`.
+    fn sign(&mut self, message: HashType) -> QIndexedSignature {
+        self.try_sign(message)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::merkle_tree::encoding::LeafEncoding;
+    use crate::signature::key_format::KeyFormat;
+    use crate::signature::q_indexed_signature::sequential::{
+        SequentialQIndexedPrivateKey, SequentialQIndexedSignatureScheme,
+    };
+    use crate::signature::q_indexed_signature::QIndexedSignatureScheme;
+    use crate::signature::winternitz::d::D;
+    use crate::signature::{Signer, Verifier};
+
+    #[test]
+    fn test_matches_q_indexed_signature_scheme() {
+        for format in [
+            KeyFormat::Legacy,
+            KeyFormat::Canonical,
+            KeyFormat::Compressed,
+        ] {
+            let mut q_indexed =
+                QIndexedSignatureScheme::try_with_format(8, [1u8; 32], D::new(15), format).unwrap();
+            let mut sequential = SequentialQIndexedSignatureScheme::try_with_format(
+                8,
+                [1u8; 32],
+                D::new(15),
+                format,
+            )
+            .unwrap();
+            assert_eq!(sequential.public_key(), q_indexed.public_key());
+            assert_eq!(sequential.key_format(), format);
+
+            for i in 0..8 {
+                let message = [i as u8; 32];
+                let signature = sequential.sign(message);
+                assert_eq!(signature.proof.index, i);
+                assert!(signature == q_indexed.sign((i, message)));
+                assert!(SequentialQIndexedSignatureScheme::verify_with_format(
+                    &sequential.public_key(),
+                    &message,
+                    &signature,
+                    format
+                ));
+            }
+        }
+
+        let mut sequential = SequentialQIndexedSignatureScheme::try_with_encoding(
+            8,
+            [1u8; 32],
+            D::new(15),
+            LeafEncoding::MessagePack,
+        )
+        .unwrap();
+        let signature = sequential.sign([0u8; 32]);
+        assert!(SequentialQIndexedSignatureScheme::verify_with_encoding(
+            &sequential.public_key(),
+            &[0u8; 32],
+            &signature,
+            LeafEncoding::MessagePack
+        ));
+    }
+
+    #[test]
+    fn test_compressed_private_key() {
+        let mut signature_scheme = SequentialQIndexedSignatureScheme::try_with_format(
+            4,
+            [2u8; 32],
+            D::new(15),
+            KeyFormat::Compressed,
+        )
+        .unwrap();
+        signature_scheme.sign([0u8; 32]);
+
+        let mut private_key = signature_scheme.private_key();
+        let mut restored =
+            SequentialQIndexedSignatureScheme::from_private_key(&private_key).unwrap();
+        assert_eq!(restored.key_format(), KeyFormat::Compressed);
+        let signature = restored.sign([1u8; 32]);
+        assert!(QIndexedSignatureScheme::verify_compressed(
+            &signature_scheme.public_key(),
+            &(1, [1u8; 32]),
+            &signature
+        ));
+
+        // Key files without the field have uncompressed keys
+        let mut json = serde_json::to_value(&private_key).unwrap();
+        json.as_object_mut()
+            .unwrap()
+            .remove("compressed_public_keys");
+        let uncompressed: SequentialQIndexedPrivateKey = serde_json::from_value(json).unwrap();
+        assert!(!uncompressed.compressed_public_keys);
+
+        private_key.leaf_encoding = LeafEncoding::MessagePack;
+        assert_eq!(
+            SequentialQIndexedSignatureScheme::from_private_key(&private_key).err(),
+            Some(Error::UnsupportedKeyFormat)
+        );
+    }
+
+    #[test]
+    fn test_incorrect_signature() {
+        let mut signature_scheme = SequentialQIndexedSignatureScheme::new(4, [0u8; 32], D::new(15));
+        let signature = signature_scheme.sign([0u8; 32]);
+        assert!(!SequentialQIndexedSignatureScheme::verify(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_exhausted() {
+        let mut signature_scheme = SequentialQIndexedSignatureScheme::new(2, [0u8; 32], D::new(15));
+        signature_scheme.sign([0u8; 32]);
+        signature_scheme.sign([1u8; 32]);
+        assert_eq!(
+            signature_scheme.try_sign([2u8; 32]).err(),
+            Some(Error::KeyExhausted(2))
+        );
+    }
+
+    #[test]
+    fn test_private_key_roundtrip_keeps_state() {
+        let mut signature_scheme =
+            SequentialQIndexedSignatureScheme::new(16, [2u8; 32], D::new(15));
+        for i in 0..5 {
+            signature_scheme.sign([i; 32]);
+        }
+
+        let json = serde_json::to_string(&signature_scheme.private_key()).unwrap();
+        let private_key: SequentialQIndexedPrivateKey = serde_json::from_str(&json).unwrap();
+        let mut restored =
+            SequentialQIndexedSignatureScheme::from_private_key(&private_key).unwrap();
+        assert_eq!(restored.public_key(), signature_scheme.public_key());
+        assert_eq!(restored.next_index(), 5);
+
+        let signature = restored.sign([5u8; 32]);
+        assert_eq!(signature.proof.index, 5);
+        assert!(SequentialQIndexedSignatureScheme::verify(
+            &restored.public_key(),
+            &[5u8; 32],
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_from_private_key_invalid_traversal() {
+        let signature_scheme = SequentialQIndexedSignatureScheme::new(16, [2u8; 32], D::new(15));
+        let mut json = serde_json::to_value(signature_scheme.private_key()).unwrap();
+        json["traversal"]["next_leaf"] = 17.into();
+        let private_key: SequentialQIndexedPrivateKey = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            SequentialQIndexedSignatureScheme::from_private_key(&private_key).err(),
+            Some(Error::InvalidTraversalState("next leaf is out of range"))
+        );
+    }
+
+    #[test]
+    fn test_try_new_not_a_power_of_two() {
+        assert_eq!(
+            SequentialQIndexedSignatureScheme::try_new(3, [0u8; 32], D::new(15)).err(),
+            Some(Error::NotAPowerOfTwo(3))
+        );
+    }
+}
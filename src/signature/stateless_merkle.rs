@@ -1,14 +1,59 @@
+pub mod compact;
+pub mod derivation;
+pub mod rust_crypto;
+pub mod streaming;
+
+use crate::error::Error;
+use crate::merkle_tree::encoding::LeafEncoding;
+use crate::secret::Secret;
+use crate::signature::key_format::{KeyFormat, VersionedPublicKey};
 use crate::signature::q_indexed_signature::{QIndexedSignature, QIndexedSignatureScheme};
+use crate::signature::winternitz::checkpoints::WinternitzCheckpoints;
 use crate::signature::winternitz::d::D;
-use crate::signature::{HashType, SignatureScheme};
-use crate::utils::{hash, hmac, string_to_hash};
-use anyhow::Result;
+use crate::signature::{HashType, KeyGen, Signer, Verifier};
+use crate::utils::{hash, hmac, try_string_to_hash};
 use data_encoding::HEXLOWER;
 use rand::Rng;
 use rand_chacha::rand_core::SeedableRng;
 use rand_chacha::ChaCha20Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
+use std::sync::{Mutex, PoisonError};
+use zeroize::Zeroize;
+
+/// Maximal length of a context string, in bytes (like in FIPS 205)
+pub const MAX_CONTEXT_LENGTH: usize = 255;
+
+/// Binds the `context` string to the `message`.
+///
+/// For the empty context, the message is returned as is, so that signatures without a context
+/// remain unchanged. Otherwise, the result is `len(context) || context || message`, which is
+/// always longer than 32 bytes.
+fn message_with_context(message: &HashType, context: &[u8]) -> Result<Vec<u8>, Error> {
+    if context.len() > MAX_CONTEXT_LENGTH {
+        return Err(Error::ContextTooLong(context.len()));
+    }
+    if context.is_empty() {
+        Ok(message.to_vec())
+    } else {
+        Ok([&[context.len() as u8], context, message].concat())
+    }
+}
+
+/// The context string for signing messages of a `protocol` built on this signature scheme,
+/// e.g. `TREE_HEAD_CONTEXT` for the tree heads of a `MerkleLog`, within a user-chosen `context`.
+///
+/// The result is `protocol || 0x00 || context`, or just `protocol` for an empty `context`.
+/// Each protocol signs with a fixed, non-empty context, so that its signatures can't be
+/// passed off as signatures of a file (signed with `sign_hash()`), or vice versa.
+pub fn protocol_context(protocol: &[u8], context: &[u8]) -> Vec<u8> {
+    if context.is_empty() {
+        protocol.to_vec()
+    } else {
+        [protocol, &[0u8], context].concat()
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct StatelessMerklePrivateKey {
@@ -17,7 +62,46 @@ pub struct StatelessMerklePrivateKey {
     pub depth: usize,
     pub d: u64,
     // This can be derived from the seed, but might be useful for someone who inspects the JSON
+    /// The public key along with its format, see `VersionedPublicKey`.
     pub public_key: String,
+    /// Key files written before the canonical leaf encoding was introduced don't contain it,
+    /// and keep their public key by using the legacy encoding.
+    #[serde(default = "LeafEncoding::legacy")]
+    pub leaf_encoding: LeafEncoding,
+    /// Key files written before one-time public keys could be compressed don't contain it.
+    #[serde(default)]
+    pub compressed_public_keys: bool,
+}
+
+impl Drop for StatelessMerklePrivateKey {
+    fn drop(&mut self) {
+        self.seed_hex.zeroize();
+    }
+}
+
+impl Debug for StatelessMerklePrivateKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StatelessMerklePrivateKey")
+            .field("seed_hex", &"[REDACTED]")
+            .field("width", &self.width)
+            .field("depth", &self.depth)
+            .field("d", &self.d)
+            .field("public_key", &self.public_key)
+            .field("leaf_encoding", &self.leaf_encoding)
+            .field("compressed_public_keys", &self.compressed_public_keys)
+            .finish()
+    }
+}
+
+/// Parameters of `StatelessMerkleSignatureScheme`
+#[derive(Clone, Copy)]
+pub struct StatelessMerkleParams {
+    /// The width `q` of the tree, needs to be a power of two
+    pub width: usize,
+    /// The depth of the tree, needs to be at least 1
+    pub depth: usize,
+    /// Winternitz parameter of the one-time signatures
+    pub d: D,
 }
 
 /// Stateless Merkle signatures, as described in Section 14.6.3
@@ -25,15 +109,24 @@ pub struct StatelessMerklePrivateKey {
 ///
 /// Builds a tree of depth `depth` and width `q`. For each signature,
 /// a pseudo-random path is selected.
+/// By default, the path is derived deterministically from the message, using a secret PRF key.
+/// Alternatively, `sign_randomized()` derives the path from a published randomizer `R`
+/// (like SPHINCS+), so that the verifier can check that the path is bound to the message.
 /// Then, the signature contains a series of q-indexed signatures,
 /// each signing the public key of the next one. The leaf node signs
 /// the hash of the message.
+/// Each signature regenerates the q-indexed signature schemes along its path, which signers
+/// of many messages can speed up by caching the upper layers (see `enable_checkpoint_cache()`).
+/// By default, the q-indexed signature schemes don't compress their one-time public keys, so
+/// that existing keys and signatures remain valid. Keys created with
+/// `StatelessMerkleSignatureScheme::try_with_format()` for `KeyFormat::Compressed` do (see
+/// `QIndexedSignatureScheme::try_with_compressed_public_keys()`).
 ///
 /// # Examples
 ///
 /// ```
 /// use hash_based_signatures::signature::stateless_merkle::StatelessMerkleSignatureScheme;
-/// use hash_based_signatures::signature::SignatureScheme;
+/// use hash_based_signatures::signature::{Signer, Verifier};
 /// use hash_based_signatures::signature::winternitz::d::D;
 ///
 /// let mut signature_scheme = StatelessMerkleSignatureScheme::new([0; 32], 16, 5, D::new(255));
@@ -41,40 +134,71 @@ pub struct StatelessMerklePrivateKey {
 /// let signature1 = signature_scheme.sign([1u8; 32]);
 ///
 /// assert!(StatelessMerkleSignatureScheme::verify(
-///     signature_scheme.public_key(),
-///     [0u8; 32],
+///     &signature_scheme.public_key(),
+///     &[0u8; 32],
 ///     &signature0
 /// ));
 /// assert!(StatelessMerkleSignatureScheme::verify(
-///     signature_scheme.public_key(),
-///     [1u8; 32],
+///     &signature_scheme.public_key(),
+///     &[1u8; 32],
 ///     &signature1
 /// ));
 /// assert!(!StatelessMerkleSignatureScheme::verify(
-///     signature_scheme.public_key(),
-///     [2u8; 32],
+///     &signature_scheme.public_key(),
+///     &[2u8; 32],
 ///     &signature1
 /// ));
 /// ```
 pub struct StatelessMerkleSignatureScheme {
-    seed: HashType,
-    seed_prf_key: HashType,
-    path_prf_key: HashType,
+    seed: Secret<HashType>,
+    seed_prf_key: Secret<HashType>,
+    path_prf_key: Secret<HashType>,
+    randomizer_prf_key: Secret<HashType>,
     root_signature: QIndexedSignatureScheme,
     q: usize,
     depth: usize,
     d: D,
+    leaf_encoding: LeafEncoding,
+    compressed_public_keys: bool,
+    checkpoint_cache: Option<Box<CheckpointCache>>,
+}
+
+/// The checkpoints of the one-time keys of the upper layers of the tree,
+/// see `StatelessMerkleSignatureScheme::enable_checkpoint_cache()`.
+struct CheckpointCache {
+    interval: u8,
+    levels: usize,
+    /// The checkpoints of the q-indexed signature schemes, by their path from the root
+    checkpoints: Mutex<HashMap<Vec<usize>, Vec<WinternitzCheckpoints>>>,
 }
 
-#[derive(PartialEq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct StatelessMerkleSignature {
     public_key_signatures: Vec<(HashType, QIndexedSignature)>,
     message_signature: QIndexedSignature,
+    /// The randomizer `R` from which the path was derived, if the signature is randomized.
+    /// Omitted for deterministic signatures, so that their encoding does not change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    randomizer: Option<HashType>,
+}
+
+/// Derives the path of a randomized signature as `H(R, pk, message)`.
+fn randomized_path_seed(randomizer: &HashType, pk: &HashType, message: &[u8]) -> HashType {
+    hash(&[randomizer, pk, message].concat())
+}
+
+/// Expands a seed to a pseudo-random path of length `depth` in a tree of width `q`.
+fn path_from_seed(seed: HashType, q: usize, depth: usize) -> Vec<usize> {
+    let mut rng = ChaCha20Rng::from_seed(seed);
+    (0..depth).map(|_| rng.gen_range(0..q)).collect()
 }
 
 impl Debug for StatelessMerkleSignature {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut result = String::from("Stateless signature:\n");
+        if let Some(randomizer) = &self.randomizer {
+            result += &format!("Randomizer: {}\n", HEXLOWER.encode(randomizer));
+        }
         for (message, signature) in &self.public_key_signatures {
             result += &format!(
                 "- ({}, {})\n",
@@ -99,64 +223,261 @@ impl StatelessMerkleSignatureScheme {
     ///
     /// # Panics
     ///
-    /// Panics if `q` is not a power of two.
+    /// Panics if `q` is not a power of two or if `depth` is zero.
+    /// Consider using `StatelessMerkleSignatureScheme::try_new()`.
     pub fn new(seed: HashType, q: usize, depth: usize, d: D) -> Self {
+        Self::try_new(seed, q, depth, d).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Like `StatelessMerkleSignatureScheme::new()`, but fails if `q` is not a power of two
+    /// or if `depth` is zero.
+    pub fn try_new(seed: HashType, q: usize, depth: usize, d: D) -> Result<Self, Error> {
+        Self::try_with_encoding(seed, q, depth, d, LeafEncoding::CURRENT)
+    }
+
+    /// Like `StatelessMerkleSignatureScheme::try_new()`, but encodes the one-time public keys
+    /// in the leaves of all q-indexed signature schemes using `leaf_encoding`.
+    ///
+    /// This changes the public key, so existing keys need to keep using the encoding they
+    /// were created with (see `StatelessMerklePrivateKey`).
+    pub fn try_with_encoding(
+        seed: HashType,
+        q: usize,
+        depth: usize,
+        d: D,
+        leaf_encoding: LeafEncoding,
+    ) -> Result<Self, Error> {
+        let format =
+            KeyFormat::new(leaf_encoding, false).expect("Uncompressed keys support all encodings");
+        Self::try_with_format(seed, q, depth, d, format)
+    }
+
+    /// Like `StatelessMerkleSignatureScheme::try_new()`, for a key in the given `format`,
+    /// see `QIndexedSignatureScheme::try_with_format()`.
+    ///
+    /// With `KeyFormat::Compressed`, every q-indexed signature scheme of the hypertree
+    /// compresses its one-time public keys, which changes the public key.
+    pub fn try_with_format(
+        seed: HashType,
+        q: usize,
+        depth: usize,
+        d: D,
+        format: KeyFormat,
+    ) -> Result<Self, Error> {
+        if depth == 0 {
+            return Err(Error::InvalidDepth(depth));
+        }
+
         // Derive keys by using HMAC as a PRF
-        let root_seed = hmac(&seed, &[0]);
-        let seed_prf_key = hmac(&seed, &[1]);
-        let path_prf_key = hmac(&seed, &[2]);
-        Self {
-            seed,
-            root_signature: QIndexedSignatureScheme::new(q, root_seed, d),
+        let mut root_seed = hmac(&seed, &[0]);
+        let seed_prf_key = Secret::new(hmac(&seed, &[1]));
+        let path_prf_key = Secret::new(hmac(&seed, &[2]));
+        let randomizer_prf_key = Secret::new(hmac(&seed, &[3]));
+        let root_signature = QIndexedSignatureScheme::try_with_format(q, root_seed, d, format);
+        root_seed.zeroize();
+        Ok(Self {
+            seed: Secret::new(seed),
+            root_signature: root_signature?,
             seed_prf_key,
             path_prf_key,
+            randomizer_prf_key,
             q,
             depth,
             d,
-        }
+            leaf_encoding: format.leaf_encoding(),
+            compressed_public_keys: format.compressed_public_keys(),
+            checkpoint_cache: None,
+        })
     }
 
-    pub fn from_private_key(key: &StatelessMerklePrivateKey) -> Result<Self> {
-        Ok(Self::new(
-            string_to_hash(&key.seed_hex),
+    /// Keeps the checkpoints of the hash chains (see `WinternitzCheckpoints`) of the one-time
+    /// keys of the root and of the top `levels` layers below it, so that signing regenerates
+    /// these layers from their checkpoints instead of walking all of their hash chains again.
+    ///
+    /// Layers are cached as they are used, so the cache grows to up to `q + q^2 + ... + q^levels`
+    /// q-indexed signature schemes, each of which keeps the checkpoints of `q` one-time keys.
+    /// Signatures and the public key don't change.
+    /// Fails if `interval` is 0 or larger than `d`.
+    pub fn enable_checkpoint_cache(&mut self, interval: u8, levels: usize) -> Result<(), Error> {
+        let mut root_seed = hmac(&self.seed, &[0]);
+        let root_signature = QIndexedSignatureScheme::try_with_checkpoints(
+            self.q,
+            root_seed,
+            self.d,
+            self.key_format(),
+            interval,
+        );
+        root_seed.zeroize();
+        self.root_signature = root_signature?;
+        self.checkpoint_cache = Some(Box::new(CheckpointCache {
+            interval,
+            levels,
+            checkpoints: Mutex::new(HashMap::new()),
+        }));
+        Ok(())
+    }
+
+    pub fn from_private_key(key: &StatelessMerklePrivateKey) -> Result<Self, Error> {
+        let format = KeyFormat::new(key.leaf_encoding, key.compressed_public_keys)
+            .ok_or(Error::UnsupportedKeyFormat)?;
+        Self::try_with_format(
+            try_string_to_hash(&key.seed_hex)?,
             key.width,
             key.depth,
-            D::try_from(key.d)?,
-        ))
+            D::try_new(key.d)?,
+            format,
+        )
     }
 
     pub fn private_key(&self) -> StatelessMerklePrivateKey {
         StatelessMerklePrivateKey {
-            seed_hex: HEXLOWER.encode(&self.seed),
-            public_key: HEXLOWER.encode(&self.public_key()),
+            seed_hex: HEXLOWER.encode(&*self.seed),
+            public_key: self.versioned_public_key().to_string(),
             width: self.q,
             depth: self.depth,
             d: self.d.d,
+            leaf_encoding: self.leaf_encoding,
+            compressed_public_keys: self.compressed_public_keys,
         }
     }
 
-    fn signature_scheme(&self, path: &[usize]) -> QIndexedSignatureScheme {
-        if path.len() == 0 {
-            self.root_signature.clone()
-        } else {
-            let path_bytes: Vec<u8> = path.iter().map(|x| x.to_be_bytes()).flatten().collect();
-            let seed = hmac(&self.seed_prf_key, &path_bytes);
-            QIndexedSignatureScheme::new(self.q, seed, self.d)
+    /// The format of the public key, which verifiers need to know along with the public key.
+    pub fn key_format(&self) -> KeyFormat {
+        KeyFormat::new(self.leaf_encoding, self.compressed_public_keys)
+            .expect("Checked when creating the scheme")
+    }
+
+    /// The public key along with its format, e.g. to publish it.
+    pub fn versioned_public_key(&self) -> VersionedPublicKey {
+        VersionedPublicKey::new(self.public_key(), self.key_format())
+    }
+
+    /// The parameters this scheme was instantiated with.
+    pub fn params(&self) -> StatelessMerkleParams {
+        StatelessMerkleParams {
+            width: self.q,
+            depth: self.depth,
+            d: self.d,
         }
     }
-}
 
-impl SignatureScheme<HashType, HashType, StatelessMerkleSignature>
-    for StatelessMerkleSignatureScheme
-{
-    fn public_key(&self) -> HashType {
-        self.root_signature.public_key()
+    /// Signs a message.
+    /// Unlike `Signer::sign()`, this does not need a mutable reference, because no state is kept.
+    pub fn sign_hash(&self, message: HashType) -> StatelessMerkleSignature {
+        self.sign_bytes(&message, None)
     }
 
-    fn sign(&mut self, message: HashType) -> StatelessMerkleSignature {
-        // Generate pseudo-random path, using hmac(path_prf_key, message) as the seed
-        let mut rng = ChaCha20Rng::from_seed(hmac(&self.path_prf_key, &message));
-        let path: Vec<usize> = (0..self.depth).map(|_| rng.gen_range(0..self.q)).collect();
+    /// Signs a message within the given `context`, e.g. the name of a protocol or key policy.
+    ///
+    /// A signature only verifies with the same context, so signatures can't be reused across
+    /// contexts. Signing with an empty context is the same as `sign_hash()`.
+    /// Fails if the context is longer than `MAX_CONTEXT_LENGTH` bytes.
+    pub fn sign_with_context(
+        &self,
+        message: HashType,
+        context: &[u8],
+    ) -> Result<StatelessMerkleSignature, Error> {
+        Ok(self.sign_bytes(&message_with_context(&message, context)?, None))
+    }
+
+    /// Signs a message within the given `context`, selecting the path from a randomizer.
+    ///
+    /// The randomizer is computed as `R = PRF(opt_rand, message)` and published in the signature.
+    /// The path is then derived as `H(R, pk, message)`, which the verifier checks.
+    /// `opt_rand` should be fresh randomness; even if it is not, `R` still depends on the
+    /// secret key and the message.
+    /// Fails if the context is longer than `MAX_CONTEXT_LENGTH` bytes.
+    pub fn sign_randomized(
+        &self,
+        message: HashType,
+        context: &[u8],
+        opt_rand: HashType,
+    ) -> Result<StatelessMerkleSignature, Error> {
+        Ok(self.sign_bytes(&message_with_context(&message, context)?, Some(opt_rand)))
+    }
+
+    /// Verifies a signature created by `sign_with_context()`.
+    pub fn verify_with_context(
+        pk: &HashType,
+        message: &HashType,
+        context: &[u8],
+        signature: &StatelessMerkleSignature,
+    ) -> bool {
+        Self::verify_with_format(pk, message, context, signature, KeyFormat::CURRENT)
+    }
+
+    /// Like `verify_with_context()`, for a key in the given `format`, e.g. one created with
+    /// `try_with_encoding()`.
+    ///
+    /// The format is part of the key and needs to come from the verifier, e.g. from a
+    /// `VersionedPublicKey`. Signatures that claim a different format are rejected.
+    pub fn verify_with_format(
+        pk: &HashType,
+        message: &HashType,
+        context: &[u8],
+        signature: &StatelessMerkleSignature,
+        format: KeyFormat,
+    ) -> bool {
+        match message_with_context(message, context) {
+            Ok(message) => Self::verify_bytes(pk, &message, signature, format),
+            Err(_) => false,
+        }
+    }
+
+    /// Verifies a signature of a message signed by the key `public_key`, in its format.
+    pub fn verify_versioned(
+        public_key: &VersionedPublicKey,
+        message: &HashType,
+        context: &[u8],
+        signature: &StatelessMerkleSignature,
+    ) -> bool {
+        Self::verify_with_format(
+            &public_key.public_key,
+            message,
+            context,
+            signature,
+            public_key.format,
+        )
+    }
+
+    /// Verifies a signature created by `sign_randomized()`, rejecting signatures without
+    /// a randomizer.
+    ///
+    /// `verify_with_context()` also accepts randomized signatures, but can't tell them apart
+    /// from deterministic ones once the randomizer is stripped, in which case the path is not
+    /// checked. Verifiers that expect randomized signatures should use this function instead.
+    pub fn verify_randomized(
+        pk: &HashType,
+        message: &HashType,
+        context: &[u8],
+        signature: &StatelessMerkleSignature,
+    ) -> bool {
+        Self::verify_randomized_with_format(pk, message, context, signature, KeyFormat::CURRENT)
+    }
+
+    /// Like `verify_randomized()`, for a key in the given `format`, see `verify_with_format()`.
+    pub fn verify_randomized_with_format(
+        pk: &HashType,
+        message: &HashType,
+        context: &[u8],
+        signature: &StatelessMerkleSignature,
+        format: KeyFormat,
+    ) -> bool {
+        signature.randomizer.is_some()
+            && Self::verify_with_format(pk, message, context, signature, format)
+    }
+
+    fn sign_bytes(&self, message: &[u8], opt_rand: Option<HashType>) -> StatelessMerkleSignature {
+        let (path_seed, randomizer) = match opt_rand {
+            // Generate pseudo-random path, using hmac(path_prf_key, message) as the seed
+            None => (hmac(&self.path_prf_key, message), None),
+            Some(opt_rand) => {
+                let randomizer = hmac(&self.randomizer_prf_key, &[&opt_rand, message].concat());
+                let path_seed = randomized_path_seed(&randomizer, &self.public_key(), message);
+                (path_seed, Some(randomizer))
+            }
+        };
+        let path = path_from_seed(path_seed, self.q, self.depth);
 
         let mut public_key_signatures = Vec::with_capacity(self.depth);
         let mut current_signing_scheme = self.root_signature.clone();
@@ -173,7 +494,7 @@ impl SignatureScheme<HashType, HashType, StatelessMerkleSignature>
         // Even though the message might be a hash already, hash it again to prevent extension attacks:
         // Otherwise an adversary could create his own q-indexed public key, trick the signer to
         // sign it and then extend the signature to sign arbitrary messages.
-        let hashed_message = hash(&message);
+        let hashed_message = hash(message);
 
         // Leaf node, sign message
         let message_signature =
@@ -182,18 +503,31 @@ impl SignatureScheme<HashType, HashType, StatelessMerkleSignature>
         StatelessMerkleSignature {
             public_key_signatures,
             message_signature,
+            randomizer,
         }
     }
 
-    fn verify(pk: HashType, message: HashType, signature: &StatelessMerkleSignature) -> bool {
-        let mut current_public_key = pk;
+    fn verify_bytes(
+        pk: &HashType,
+        message: &[u8],
+        signature: &StatelessMerkleSignature,
+        format: KeyFormat,
+    ) -> bool {
+        if let Some(randomizer) = &signature.randomizer {
+            if !Self::verify_path(pk, message, randomizer, signature) {
+                return false;
+            }
+        }
+
+        let mut current_public_key = *pk;
 
         // Verify public keys along path
         for (public_key, one_time_signature) in &signature.public_key_signatures {
-            if !QIndexedSignatureScheme::verify(
-                current_public_key,
-                (one_time_signature.proof.index, *public_key),
+            if !QIndexedSignatureScheme::verify_with_format(
+                &current_public_key,
+                &(one_time_signature.proof.index, *public_key),
                 one_time_signature,
+                format,
             ) {
                 return false;
             }
@@ -201,19 +535,150 @@ impl SignatureScheme<HashType, HashType, StatelessMerkleSignature>
         }
 
         // Verify message signature
-        QIndexedSignatureScheme::verify(
-            current_public_key,
-            (signature.message_signature.proof.index, hash(&message)),
+        QIndexedSignatureScheme::verify_with_format(
+            &current_public_key,
+            &(signature.message_signature.proof.index, hash(message)),
             &signature.message_signature,
+            format,
         )
     }
+
+    /// Checks that the path of a randomized signature is the one derived from its randomizer.
+    fn verify_path(
+        pk: &HashType,
+        message: &[u8],
+        randomizer: &HashType,
+        signature: &StatelessMerkleSignature,
+    ) -> bool {
+        // The width of the tree is not known to the verifier, but determined by the
+        // length of the Merkle proofs. All of them need to be the same.
+        let proof_length = signature.message_signature.proof.hash_chain.len();
+        let proofs_have_same_length = signature
+            .public_key_signatures
+            .iter()
+            .all(|(_, s)| s.proof.hash_chain.len() == proof_length);
+        if !proofs_have_same_length || proof_length >= usize::BITS as usize {
+            return false;
+        }
+
+        let depth = signature.public_key_signatures.len();
+        let path_seed = randomized_path_seed(randomizer, pk, message);
+        let expected_path = path_from_seed(path_seed, 1 << proof_length, depth);
+        let path = signature
+            .public_key_signatures
+            .iter()
+            .map(|(_, s)| s.proof.index);
+
+        expected_path.iter().copied().eq(path)
+            && expected_path.last() == Some(&signature.message_signature.proof.index)
+    }
+
+    fn signature_scheme(&self, path: &[usize]) -> QIndexedSignatureScheme {
+        if path.is_empty() {
+            self.root_signature.clone()
+        } else {
+            let path_bytes: Vec<u8> = path.iter().flat_map(|x| x.to_be_bytes()).collect();
+            let mut seed = hmac(&self.seed_prf_key, &path_bytes);
+            let signature_scheme = match &self.checkpoint_cache {
+                Some(cache) if path.len() <= cache.levels => {
+                    self.cached_signature_scheme(cache, path, seed)
+                }
+                _ => QIndexedSignatureScheme::try_with_format(
+                    self.q,
+                    seed,
+                    self.d,
+                    self.key_format(),
+                ),
+            }
+            .unwrap_or_else(|error| panic!("{error}"));
+            seed.zeroize();
+            signature_scheme
+        }
+    }
+
+    /// Rebuilds the q-indexed signature scheme at `path` from its cached checkpoints,
+    /// computing and caching them if they are not cached yet.
+    fn cached_signature_scheme(
+        &self,
+        cache: &CheckpointCache,
+        path: &[usize],
+        seed: HashType,
+    ) -> Result<QIndexedSignatureScheme, Error> {
+        let checkpoints = cache
+            .checkpoints
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+            .cloned();
+        match checkpoints {
+            Some(checkpoints) => QIndexedSignatureScheme::from_checkpoints(
+                self.q,
+                seed,
+                self.d,
+                self.key_format(),
+                checkpoints,
+            ),
+            None => {
+                let signature_scheme = QIndexedSignatureScheme::try_with_checkpoints(
+                    self.q,
+                    seed,
+                    self.d,
+                    self.key_format(),
+                    cache.interval,
+                )?;
+                let checkpoints = signature_scheme
+                    .checkpoints()
+                    .expect("Created with checkpoints");
+                cache
+                    .checkpoints
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(path.to_vec(), checkpoints);
+                Ok(signature_scheme)
+            }
+        }
+    }
+}
+
+impl Verifier for StatelessMerkleSignatureScheme {
+    type PublicKey = HashType;
+    type Message = HashType;
+    type Signature = StatelessMerkleSignature;
+
+    fn verify(pk: &HashType, message: &HashType, signature: &StatelessMerkleSignature) -> bool {
+        Self::verify_bytes(pk, message, signature, KeyFormat::CURRENT)
+    }
+}
+
+impl Signer for StatelessMerkleSignatureScheme {
+    fn public_key(&self) -> HashType {
+        self.root_signature.public_key()
+    }
+
+    fn sign(&mut self, message: HashType) -> StatelessMerkleSignature {
+        self.sign_hash(message)
+    }
+}
+
+impl KeyGen for StatelessMerkleSignatureScheme {
+    type Params = StatelessMerkleParams;
+
+    fn key_gen(seed: HashType, params: &StatelessMerkleParams) -> Result<Self, Error> {
+        Self::try_new(seed, params.width, params.depth, params.d)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+    use crate::error::Error;
+    use crate::merkle_tree::encoding::LeafEncoding;
+    use crate::signature::key_format::KeyFormat;
+    use crate::signature::stateless_merkle::{
+        protocol_context, StatelessMerklePrivateKey, StatelessMerkleSignatureScheme,
+    };
     use crate::signature::winternitz::d::D;
     use crate::signature::SignatureScheme;
+    use data_encoding::HEXLOWER;
 
     fn get_signature_scheme() -> StatelessMerkleSignatureScheme {
         let seed = [0u8; 32];
@@ -249,4 +714,322 @@ mod tests {
             &signature
         ))
     }
+
+    #[test]
+    fn test_context() {
+        let signature_scheme = get_signature_scheme();
+        let signature = signature_scheme
+            .sign_with_context([1u8; 32], b"releases")
+            .unwrap();
+        assert!(StatelessMerkleSignatureScheme::verify_with_context(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            b"releases",
+            &signature
+        ));
+        assert!(!StatelessMerkleSignatureScheme::verify_with_context(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            b"configs",
+            &signature
+        ));
+        assert!(!StatelessMerkleSignatureScheme::verify(
+            signature_scheme.public_key(),
+            [1u8; 32],
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_empty_context() {
+        let signature_scheme = get_signature_scheme();
+        assert_eq!(
+            signature_scheme.sign_with_context([1u8; 32], b"").unwrap(),
+            signature_scheme.sign_hash([1u8; 32])
+        );
+    }
+
+    #[test]
+    fn test_context_too_long() {
+        let signature_scheme = get_signature_scheme();
+        assert_eq!(
+            signature_scheme
+                .sign_with_context([1u8; 32], &[0u8; 256])
+                .err(),
+            Some(Error::ContextTooLong(256))
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_cache() {
+        let signature_scheme = StatelessMerkleSignatureScheme::new([0u8; 32], 4, 3, D::new(15));
+        let mut cached = StatelessMerkleSignatureScheme::new([0u8; 32], 4, 3, D::new(15));
+        assert_eq!(
+            cached.enable_checkpoint_cache(0, 1).err(),
+            Some(Error::InvalidCheckpointInterval(0))
+        );
+        assert!(cached.checkpoint_cache.is_none());
+
+        cached.enable_checkpoint_cache(4, 1).unwrap();
+        assert_eq!(cached.public_key(), signature_scheme.public_key());
+        for message in [[1u8; 32], [2u8; 32], [1u8; 32]] {
+            assert!(cached.sign_hash(message) == signature_scheme.sign_hash(message));
+        }
+
+        // Only the layer below the root is cached
+        let cache = cached.checkpoint_cache.as_ref().unwrap();
+        let checkpoints = cache.checkpoints.lock().unwrap();
+        assert_eq!(checkpoints.len(), 2);
+        assert!(checkpoints.keys().all(|path| path.len() == 1));
+    }
+
+    #[test]
+    fn test_protocol_context() {
+        assert_eq!(protocol_context(b"protocol", b""), b"protocol");
+        assert_eq!(
+            protocol_context(b"protocol", b"releases"),
+            b"protocol\0releases"
+        );
+
+        let signature_scheme = get_signature_scheme();
+        let signature = signature_scheme
+            .sign_with_context([1u8; 32], &protocol_context(b"protocol", b"releases"))
+            .unwrap();
+        for context in [&b""[..], b"protocol", b"releases"] {
+            assert!(!StatelessMerkleSignatureScheme::verify_with_context(
+                &signature_scheme.public_key(),
+                &[1u8; 32],
+                context,
+                &signature
+            ));
+        }
+    }
+
+    #[test]
+    fn test_randomized_signature() {
+        let signature_scheme = get_signature_scheme();
+        let signature1 = signature_scheme
+            .sign_randomized([1u8; 32], b"", [1u8; 32])
+            .unwrap();
+        let signature2 = signature_scheme
+            .sign_randomized([1u8; 32], b"", [2u8; 32])
+            .unwrap();
+        assert_ne!(signature1.randomizer, signature2.randomizer);
+
+        for signature in [signature1, signature2] {
+            assert!(StatelessMerkleSignatureScheme::verify_randomized(
+                &signature_scheme.public_key(),
+                &[1u8; 32],
+                b"",
+                &signature
+            ));
+            assert!(StatelessMerkleSignatureScheme::verify(
+                signature_scheme.public_key(),
+                [1u8; 32],
+                &signature
+            ));
+            assert!(!StatelessMerkleSignatureScheme::verify(
+                signature_scheme.public_key(),
+                [2u8; 32],
+                &signature
+            ));
+        }
+    }
+
+    #[test]
+    fn test_randomized_signature_checks_path() {
+        let signature_scheme = get_signature_scheme();
+        let mut signature = signature_scheme
+            .sign_randomized([1u8; 32], b"", [1u8; 32])
+            .unwrap();
+        signature.randomizer = Some([0u8; 32]);
+        assert!(!StatelessMerkleSignatureScheme::verify(
+            signature_scheme.public_key(),
+            [1u8; 32],
+            &signature
+        ));
+
+        // Stripping the randomizer does not bypass the check
+        signature.randomizer = None;
+        assert!(!StatelessMerkleSignatureScheme::verify_randomized(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            b"",
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_deterministic_signature_has_no_randomizer() {
+        let signature_scheme = get_signature_scheme();
+        let signature = signature_scheme.sign_hash([1u8; 32]);
+        assert_eq!(signature.randomizer, None);
+        assert!(!StatelessMerkleSignatureScheme::verify_randomized(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            b"",
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_try_new_invalid_parameters() {
+        assert_eq!(
+            StatelessMerkleSignatureScheme::try_new([0u8; 32], 12, 5, D::new(255)).err(),
+            Some(Error::NotAPowerOfTwo(12))
+        );
+        assert_eq!(
+            StatelessMerkleSignatureScheme::try_new([0u8; 32], 16, 0, D::new(255)).err(),
+            Some(Error::InvalidDepth(0))
+        );
+    }
+
+    #[test]
+    fn test_private_key_debug_is_redacted() {
+        let private_key = get_signature_scheme().private_key();
+        let debug = format!("{:?}", private_key);
+        assert!(debug.contains("[REDACTED]"));
+        assert!(!debug.contains(&private_key.seed_hex));
+    }
+
+    #[test]
+    fn test_from_private_key_invalid_seed() {
+        let mut private_key = get_signature_scheme().private_key();
+        private_key.seed_hex = String::from("not a seed");
+        assert!(StatelessMerkleSignatureScheme::from_private_key(&private_key).is_err());
+    }
+
+    #[test]
+    fn test_legacy_private_key_keeps_public_key() {
+        let legacy_scheme = StatelessMerkleSignatureScheme::try_with_encoding(
+            [0u8; 32],
+            16,
+            5,
+            D::new(255),
+            LeafEncoding::MessagePack,
+        )
+        .unwrap();
+        assert_ne!(
+            legacy_scheme.public_key(),
+            get_signature_scheme().public_key()
+        );
+
+        // Key files written before the leaf encoding was versioned don't contain it
+        let mut json = serde_json::to_value(legacy_scheme.private_key()).unwrap();
+        json.as_object_mut().unwrap().remove("leaf_encoding");
+        let private_key: StatelessMerklePrivateKey = serde_json::from_value(json).unwrap();
+        assert_eq!(private_key.leaf_encoding, LeafEncoding::MessagePack);
+
+        let mut signature_scheme =
+            StatelessMerkleSignatureScheme::from_private_key(&private_key).unwrap();
+        assert_eq!(signature_scheme.public_key(), legacy_scheme.public_key());
+
+        // Their public keys are written without a version, like before
+        assert_eq!(signature_scheme.key_format(), KeyFormat::Legacy);
+        assert_eq!(
+            private_key.public_key,
+            HEXLOWER.encode(&signature_scheme.public_key())
+        );
+        assert_eq!(
+            get_signature_scheme().private_key().public_key,
+            format!(
+                "v1:{}",
+                HEXLOWER.encode(&get_signature_scheme().public_key())
+            )
+        );
+
+        // Signatures of legacy keys are encoded as before and still verify,
+        // if the verifier knows that the key is a legacy key
+        let signature = signature_scheme.sign([1u8; 32]);
+        assert_eq!(
+            signature.message_signature.leaf_encoding,
+            LeafEncoding::MessagePack
+        );
+        let bytes = rmp_serde::to_vec(&signature).unwrap();
+        let signature = rmp_serde::from_slice(&bytes).unwrap();
+        assert!(StatelessMerkleSignatureScheme::verify_with_format(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            b"",
+            &signature,
+            KeyFormat::Legacy
+        ));
+        assert!(!StatelessMerkleSignatureScheme::verify(
+            signature_scheme.public_key(),
+            [1u8; 32],
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_compressed_public_keys() {
+        let mut signature_scheme = StatelessMerkleSignatureScheme::try_with_format(
+            [0u8; 32],
+            16,
+            5,
+            D::new(255),
+            KeyFormat::Compressed,
+        )
+        .unwrap();
+        assert_ne!(
+            signature_scheme.public_key(),
+            get_signature_scheme().public_key()
+        );
+        assert!(signature_scheme
+            .versioned_public_key()
+            .to_string()
+            .starts_with("v2:"));
+
+        let signature = signature_scheme.sign([1u8; 32]);
+        assert!(signature.message_signature.compressed_public_key);
+        assert!(StatelessMerkleSignatureScheme::verify_versioned(
+            &signature_scheme.versioned_public_key(),
+            &[1u8; 32],
+            b"",
+            &signature
+        ));
+        assert!(!StatelessMerkleSignatureScheme::verify(
+            signature_scheme.public_key(),
+            [1u8; 32],
+            &signature
+        ));
+
+        let private_key = signature_scheme.private_key();
+        assert!(private_key.compressed_public_keys);
+        let restored = StatelessMerkleSignatureScheme::from_private_key(&private_key).unwrap();
+        assert_eq!(
+            restored.versioned_public_key(),
+            signature_scheme.versioned_public_key()
+        );
+    }
+
+    #[test]
+    fn test_compressed_legacy_private_key_is_rejected() {
+        let mut private_key = get_signature_scheme().private_key();
+        private_key.leaf_encoding = LeafEncoding::MessagePack;
+        private_key.compressed_public_keys = true;
+        assert!(matches!(
+            StatelessMerkleSignatureScheme::from_private_key(&private_key),
+            Err(Error::UnsupportedKeyFormat)
+        ));
+    }
+
+    #[test]
+    fn test_signature_with_wrong_leaf_encoding_is_rejected() {
+        let mut signature_scheme = get_signature_scheme();
+        let mut signature = signature_scheme.sign([1u8; 32]);
+        signature.message_signature.leaf_encoding = LeafEncoding::MessagePack;
+        assert!(!StatelessMerkleSignatureScheme::verify(
+            signature_scheme.public_key(),
+            [1u8; 32],
+            &signature
+        ));
+        assert!(!StatelessMerkleSignatureScheme::verify_with_format(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            b"",
+            &signature,
+            KeyFormat::Legacy
+        ));
+    }
 }
@@ -0,0 +1,328 @@
+use crate::merkle_tree::encoding::LeafEncoding;
+use crate::merkle_tree::MerkleProof;
+use crate::signature::key_format::KeyFormat;
+use crate::signature::q_indexed_signature::merkle_leaf;
+use crate::signature::stateless_merkle::{
+    message_with_context, path_from_seed, randomized_path_seed, StatelessMerkleSignature,
+    StatelessMerkleSignatureScheme,
+};
+use crate::signature::winternitz::{WinternitzKey, WinternitzSignature, WinternitzSignatureScheme};
+use crate::signature::HashType;
+use crate::utils::{ct_eq, hash};
+use serde::{Deserialize, Serialize};
+
+/// A randomized `StatelessMerkleSignature` without the parts that the verifier can derive.
+///
+/// The intermediate public keys are recomputed bottom-up from the one-time signatures
+/// (like in SPHINCS+), and the indices of the Merkle proofs are recomputed from the randomizer.
+/// This saves about 40 bytes per layer.
+/// Deterministic signatures can't be compacted, because their path is not derivable.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+/// use hash_based_signatures::signature::winternitz::d::D;
+/// use hash_based_signatures::signature::Signer;
+///
+/// let signature_scheme = StatelessMerkleSignatureScheme::new([0; 32], 16, 5, D::new(255));
+/// let signature = signature_scheme
+///     .sign_randomized([1; 32], b"", [2; 32])
+///     .unwrap()
+///     .compact()
+///     .unwrap();
+///
+/// assert!(StatelessMerkleSignatureScheme::verify_compact(
+///     &signature_scheme.public_key(),
+///     &[1; 32],
+///     &signature
+/// ));
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CompactStatelessMerkleSignature {
+    randomizer: HashType,
+    /// One layer per q-indexed signature, from the root down to the one signing the message.
+    layers: Vec<CompactLayer>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct CompactLayer {
+    one_time_signature: WinternitzSignature,
+    hash_chain: Vec<HashType>,
+    #[serde(
+        default = "LeafEncoding::legacy",
+        skip_serializing_if = "LeafEncoding::is_legacy"
+    )]
+    leaf_encoding: LeafEncoding,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    compressed_public_key: bool,
+}
+
+impl StatelessMerkleSignature {
+    /// Drops the derivable public keys and indices from the signature.
+    ///
+    /// Returns `None` if the signature is not randomized.
+    pub fn compact(&self) -> Option<CompactStatelessMerkleSignature> {
+        let layers = self
+            .public_key_signatures
+            .iter()
+            .map(|(_, signature)| signature)
+            .chain([&self.message_signature])
+            .map(|signature| CompactLayer {
+                one_time_signature: signature.one_time_signature.clone(),
+                hash_chain: signature.proof.hash_chain.clone(),
+                leaf_encoding: signature.leaf_encoding,
+                compressed_public_key: signature.compressed_public_key,
+            })
+            .collect();
+
+        Some(CompactStatelessMerkleSignature {
+            randomizer: self.randomizer?,
+            layers,
+        })
+    }
+}
+
+impl StatelessMerkleSignatureScheme {
+    /// Verifies a compact signature, see `CompactStatelessMerkleSignature`.
+    pub fn verify_compact(
+        pk: &HashType,
+        message: &HashType,
+        signature: &CompactStatelessMerkleSignature,
+    ) -> bool {
+        Self::verify_compact_with_context(pk, message, b"", signature)
+    }
+
+    /// Verifies a compact signature of a message signed with `sign_randomized()` in the given `context`.
+    pub fn verify_compact_with_context(
+        pk: &HashType,
+        message: &HashType,
+        context: &[u8],
+        signature: &CompactStatelessMerkleSignature,
+    ) -> bool {
+        Self::verify_compact_with_format(pk, message, context, signature, KeyFormat::CURRENT)
+    }
+
+    /// Like `verify_compact_with_context()`, for a key in the given `format`,
+    /// see `StatelessMerkleSignatureScheme::verify_with_format()`.
+    pub fn verify_compact_with_format(
+        pk: &HashType,
+        message: &HashType,
+        context: &[u8],
+        signature: &CompactStatelessMerkleSignature,
+        format: KeyFormat,
+    ) -> bool {
+        match message_with_context(message, context) {
+            Ok(message) => verify_compact_bytes(pk, &message, signature, format),
+            Err(_) => false,
+        }
+    }
+}
+
+fn verify_compact_bytes(
+    pk: &HashType,
+    message: &[u8],
+    signature: &CompactStatelessMerkleSignature,
+    format: KeyFormat,
+) -> bool {
+    let leaf_encoding = format.leaf_encoding();
+    let compressed_public_keys = format.compressed_public_keys();
+
+    let depth = match signature.layers.len().checked_sub(1) {
+        Some(depth) if depth > 0 => depth,
+        _ => return false,
+    };
+
+    // As for full signatures, the width of the tree is determined by the length of the Merkle proofs
+    let proof_length = signature.layers[0].hash_chain.len();
+    let proofs_have_same_length = signature
+        .layers
+        .iter()
+        .all(|layer| layer.hash_chain.len() == proof_length);
+    if !proofs_have_same_length || proof_length >= usize::BITS as usize {
+        return false;
+    }
+    // The encoding and compression are part of the key, so the signature can't choose them
+    if signature.layers.iter().any(|layer| {
+        layer.leaf_encoding != leaf_encoding
+            || layer.compressed_public_key != compressed_public_keys
+    }) {
+        return false;
+    }
+
+    let path_seed = randomized_path_seed(&signature.randomizer, pk, message);
+    let path = path_from_seed(path_seed, 1 << proof_length, depth);
+    // The leaf scheme signs the message at the same index as the last public key
+    let indices: Vec<usize> = path.iter().chain(path.last()).copied().collect();
+
+    // Walk up the tree: Each layer signs the root of the layer below
+    let mut current_message = hash(message);
+    for (layer, index) in signature.layers.iter().zip(indices).rev() {
+        let winternitz_pk = match WinternitzSignatureScheme::public_key_from_message_and_signature(
+            current_message,
+            &layer.one_time_signature,
+        ) {
+            Ok(winternitz_pk) => winternitz_pk,
+            Err(_) => return false,
+        };
+        let proof = MerkleProof::<WinternitzKey>::from_hash_chain(index, layer.hash_chain.clone());
+        let leaf = merkle_leaf(winternitz_pk, compressed_public_keys);
+        current_message = match proof.root_hash_with_encoding(&leaf, leaf_encoding) {
+            Some(root_hash) => root_hash,
+            None => return false,
+        };
+    }
+
+    ct_eq(&current_message, pk)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::encoding::LeafEncoding;
+    use crate::signature::key_format::KeyFormat;
+    use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+    use crate::signature::winternitz::d::D;
+    use crate::signature::Signer;
+
+    fn get_signature_scheme() -> StatelessMerkleSignatureScheme {
+        StatelessMerkleSignatureScheme::new([0u8; 32], 16, 5, D::new(255))
+    }
+
+    #[test]
+    fn test_compact_signature() {
+        let signature_scheme = get_signature_scheme();
+        let signature = signature_scheme
+            .sign_randomized([1u8; 32], b"", [2u8; 32])
+            .unwrap()
+            .compact()
+            .unwrap();
+
+        assert!(StatelessMerkleSignatureScheme::verify_compact(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            &signature
+        ));
+        assert!(!StatelessMerkleSignatureScheme::verify_compact(
+            &signature_scheme.public_key(),
+            &[2u8; 32],
+            &signature
+        ));
+        assert!(!StatelessMerkleSignatureScheme::verify_compact(
+            &[0u8; 32], &[1u8; 32], &signature
+        ));
+    }
+
+    #[test]
+    fn test_compact_signature_with_context() {
+        let signature_scheme = get_signature_scheme();
+        let signature = signature_scheme
+            .sign_randomized([1u8; 32], b"context", [2u8; 32])
+            .unwrap()
+            .compact()
+            .unwrap();
+
+        assert!(StatelessMerkleSignatureScheme::verify_compact_with_context(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            b"context",
+            &signature
+        ));
+        assert!(!StatelessMerkleSignatureScheme::verify_compact(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_tampered_compact_signature() {
+        let signature_scheme = get_signature_scheme();
+        let signature = signature_scheme
+            .sign_randomized([1u8; 32], b"", [2u8; 32])
+            .unwrap()
+            .compact()
+            .unwrap();
+
+        let mut wrong_randomizer = signature.clone();
+        wrong_randomizer.randomizer = [0u8; 32];
+        let mut missing_layer = signature.clone();
+        missing_layer.layers.remove(0);
+        let mut wrong_hash_chain = signature.clone();
+        wrong_hash_chain.layers[2].hash_chain[0] = [0u8; 32];
+        let mut wrong_leaf_encoding = signature;
+        wrong_leaf_encoding.layers[2].leaf_encoding = LeafEncoding::MessagePack;
+
+        for signature in [
+            wrong_randomizer,
+            missing_layer,
+            wrong_hash_chain,
+            wrong_leaf_encoding,
+        ] {
+            assert!(!StatelessMerkleSignatureScheme::verify_compact(
+                &signature_scheme.public_key(),
+                &[1u8; 32],
+                &signature
+            ));
+        }
+    }
+
+    #[test]
+    fn test_compact_signature_with_compressed_public_keys() {
+        let signature_scheme = StatelessMerkleSignatureScheme::try_with_format(
+            [0u8; 32],
+            16,
+            5,
+            D::new(255),
+            KeyFormat::Compressed,
+        )
+        .unwrap();
+        let signature = signature_scheme
+            .sign_randomized([1u8; 32], b"", [2u8; 32])
+            .unwrap()
+            .compact()
+            .unwrap();
+
+        assert!(StatelessMerkleSignatureScheme::verify_compact_with_format(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            b"",
+            &signature,
+            KeyFormat::Compressed
+        ));
+        assert!(!StatelessMerkleSignatureScheme::verify_compact(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            &signature
+        ));
+
+        let mut uncompressed = signature;
+        uncompressed.layers[2].compressed_public_key = false;
+        assert!(!StatelessMerkleSignatureScheme::verify_compact_with_format(
+            &signature_scheme.public_key(),
+            &[1u8; 32],
+            b"",
+            &uncompressed,
+            KeyFormat::Compressed
+        ));
+    }
+
+    #[test]
+    fn test_deterministic_signature_is_not_compactable() {
+        let signature = get_signature_scheme().sign_hash([1u8; 32]);
+        assert!(signature.compact().is_none());
+    }
+
+    #[test]
+    fn test_compact_signature_is_smaller() {
+        let signature = get_signature_scheme()
+            .sign_randomized([1u8; 32], b"", [2u8; 32])
+            .unwrap();
+        let full_size = rmp_serde::to_vec(&signature).unwrap().len();
+        let compact_size = rmp_serde::to_vec(&signature.compact().unwrap())
+            .unwrap()
+            .len();
+
+        // Saves at least the 32-byte public key for each of the 5 layers
+        assert!(compact_size + 5 * 32 <= full_size);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::error::Error;
+use crate::secret::Secret;
+use crate::signature::stateless_merkle::{StatelessMerkleParams, StatelessMerkleSignatureScheme};
+use crate::signature::HashType;
+use crate::utils::{hmac, try_string_to_hash};
+use data_encoding::HEXLOWER;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+/// Prefix of the HMAC input when deriving a child seed.
+/// It separates child derivation from the keys derived in `StatelessMerkleSignatureScheme::new()`,
+/// which use single-byte inputs.
+const CHILD_DERIVATION_TAG: u8 = 0xff;
+
+/// A master seed from which independent child keys can be derived.
+///
+/// Children are identified by labels like `"releases/2026"`. Each segment of the label is
+/// applied in turn as `seed = HMAC(seed, 0xff || segment)`, so that the key of `"releases"`
+/// can derive all keys below it, but not the other way around.
+///
+/// Unlike BIP 32, there is no public derivation: Hash-based public keys can't be computed
+/// without the seed, so deriving a child public key requires the master seed.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::signature::stateless_merkle::derivation::MasterKey;
+/// use hash_based_signatures::signature::stateless_merkle::StatelessMerkleParams;
+/// use hash_based_signatures::signature::winternitz::d::D;
+/// use hash_based_signatures::signature::Signer;
+///
+/// let params = StatelessMerkleParams { width: 16, depth: 5, d: D::new(255) };
+/// let master_key = MasterKey::new([0; 32]);
+/// let releases = master_key.derive("releases/2026", params).unwrap();
+/// let nightlies = master_key.derive("nightlies/2026", params).unwrap();
+/// assert_ne!(releases.public_key(), nightlies.public_key());
+///
+/// let same_releases = master_key.child("releases").unwrap().derive("2026", params).unwrap();
+/// assert_eq!(releases.public_key(), same_releases.public_key());
+/// ```
+#[derive(Clone, Debug)]
+pub struct MasterKey {
+    seed: Secret<HashType>,
+}
+
+/// Serialized form of a `MasterKey`, as written by `cli::master_keygen()`.
+#[derive(Serialize, Deserialize)]
+pub struct MasterPrivateKey {
+    pub master_seed_hex: String,
+}
+
+impl Drop for MasterPrivateKey {
+    fn drop(&mut self) {
+        self.master_seed_hex.zeroize();
+    }
+}
+
+impl MasterKey {
+    pub fn new(seed: HashType) -> Self {
+        Self {
+            seed: Secret::new(seed),
+        }
+    }
+
+    pub fn from_private_key(key: &MasterPrivateKey) -> Result<Self, Error> {
+        Ok(Self::new(try_string_to_hash(&key.master_seed_hex)?))
+    }
+
+    pub fn private_key(&self) -> MasterPrivateKey {
+        MasterPrivateKey {
+            master_seed_hex: HEXLOWER.encode(&*self.seed),
+        }
+    }
+
+    /// Derives the master key of the child with the given `label`.
+    ///
+    /// Fails if the label or any of its `/`-separated segments is empty.
+    pub fn child(&self, label: &str) -> Result<MasterKey, Error> {
+        let mut seed = self.seed.clone();
+        for segment in label.split('/') {
+            if segment.is_empty() {
+                return Err(Error::InvalidLabel(String::from(label)));
+            }
+            *seed = hmac(
+                &seed,
+                &[&[CHILD_DERIVATION_TAG], segment.as_bytes()].concat(),
+            );
+        }
+        Ok(MasterKey { seed })
+    }
+
+    /// Derives the seed of the child with the given `label`, see `MasterKey::child()`.
+    pub fn derive_seed(&self, label: &str) -> Result<HashType, Error> {
+        Ok(*self.child(label)?.seed)
+    }
+
+    /// Derives the signature scheme of the child with the given `label`.
+    pub fn derive(
+        &self,
+        label: &str,
+        params: StatelessMerkleParams,
+    ) -> Result<StatelessMerkleSignatureScheme, Error> {
+        StatelessMerkleSignatureScheme::try_new(
+            self.derive_seed(label)?,
+            params.width,
+            params.depth,
+            params.d,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::signature::stateless_merkle::derivation::MasterKey;
+    use crate::signature::stateless_merkle::{
+        StatelessMerkleParams, StatelessMerkleSignatureScheme,
+    };
+    use crate::signature::winternitz::d::D;
+    use crate::signature::{Signer, Verifier};
+    use crate::utils::hmac;
+
+    fn params() -> StatelessMerkleParams {
+        StatelessMerkleParams {
+            width: 16,
+            depth: 5,
+            d: D::new(255),
+        }
+    }
+
+    #[test]
+    fn test_derivation_is_deterministic() {
+        let master_key = MasterKey::new([1u8; 32]);
+        assert_eq!(
+            master_key.derive_seed("releases/2026").unwrap(),
+            master_key.derive_seed("releases/2026").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_children_are_independent() {
+        let master_key = MasterKey::new([1u8; 32]);
+        let seeds = [
+            master_key.derive_seed("releases").unwrap(),
+            master_key.derive_seed("releases/2026").unwrap(),
+            master_key.derive_seed("releases/2027").unwrap(),
+            master_key.derive_seed("releases2026").unwrap(),
+            MasterKey::new([2u8; 32])
+                .derive_seed("releases/2026")
+                .unwrap(),
+        ];
+        for (i, seed1) in seeds.iter().enumerate() {
+            for seed2 in &seeds[i + 1..] {
+                assert_ne!(seed1, seed2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_child_seeds_differ_from_internal_keys() {
+        let seed = [1u8; 32];
+        let child_seed = MasterKey::new(seed).derive_seed("0").unwrap();
+        for i in 0..4 {
+            assert_ne!(child_seed, hmac(&seed, &[i]));
+        }
+    }
+
+    #[test]
+    fn test_derive_in_steps() {
+        let master_key = MasterKey::new([1u8; 32]);
+        assert_eq!(
+            master_key.child("a").unwrap().derive_seed("b/c").unwrap(),
+            master_key.derive_seed("a/b/c").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_derived_key_signs() {
+        let signature_scheme = MasterKey::new([1u8; 32])
+            .derive("releases/2026", params())
+            .unwrap();
+        let signature = signature_scheme.sign_hash([3u8; 32]);
+        assert!(StatelessMerkleSignatureScheme::verify(
+            &signature_scheme.public_key(),
+            &[3u8; 32],
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_invalid_labels() {
+        let master_key = MasterKey::new([1u8; 32]);
+        for label in ["", "/", "releases/", "/releases", "releases//2026"] {
+            assert_eq!(
+                master_key.derive_seed(label),
+                Err(Error::InvalidLabel(String::from(label)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_private_key_roundtrip() {
+        let master_key = MasterKey::new([1u8; 32]);
+        assert_eq!(
+            *MasterKey::from_private_key(&master_key.private_key())
+                .unwrap()
+                .seed,
+            *master_key.seed
+        );
+    }
+}
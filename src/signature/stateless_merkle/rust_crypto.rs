@@ -0,0 +1,128 @@
+use crate::signature::stateless_merkle::{
+    StatelessMerkleSignature, StatelessMerkleSignatureScheme,
+};
+use crate::signature::{HashType, Signer, Verifier};
+use crate::utils::hash;
+use signature::{Error, Keypair, SignatureEncoding};
+
+/// Public key of `StatelessMerkleSignatureScheme`, as used by the
+/// [RustCrypto `signature`](https://docs.rs/signature) traits.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+/// use hash_based_signatures::signature::winternitz::d::D;
+/// use signature::{Keypair, Signer, Verifier};
+///
+/// let signature_scheme = StatelessMerkleSignatureScheme::new([0; 32], 16, 5, D::new(255));
+/// let signature = signature_scheme.sign(b"Hello, world!");
+/// assert!(signature_scheme
+///     .verifying_key()
+///     .verify(b"Hello, world!", &signature)
+///     .is_ok());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StatelessMerkleVerifyingKey(pub HashType);
+
+impl AsRef<[u8]> for StatelessMerkleVerifyingKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<HashType> for StatelessMerkleVerifyingKey {
+    fn from(public_key: HashType) -> Self {
+        StatelessMerkleVerifyingKey(public_key)
+    }
+}
+
+/// Messages of arbitrary length are hashed using SHA256 before signing them.
+/// This means that signing the bytes of a file results in the same signature as
+/// `cli::sign()`, which signs the SHA256 hash of the file.
+impl signature::Signer<StatelessMerkleSignature> for StatelessMerkleSignatureScheme {
+    fn try_sign(&self, msg: &[u8]) -> Result<StatelessMerkleSignature, Error> {
+        Ok(self.sign_hash(hash(msg)))
+    }
+}
+
+impl signature::Verifier<StatelessMerkleSignature> for StatelessMerkleVerifyingKey {
+    fn verify(&self, msg: &[u8], signature: &StatelessMerkleSignature) -> Result<(), Error> {
+        if <StatelessMerkleSignatureScheme as Verifier>::verify(&self.0, &hash(msg), signature) {
+            Ok(())
+        } else {
+            Err(Error::new())
+        }
+    }
+}
+
+impl Keypair for StatelessMerkleSignatureScheme {
+    type VerifyingKey = StatelessMerkleVerifyingKey;
+
+    fn verifying_key(&self) -> StatelessMerkleVerifyingKey {
+        StatelessMerkleVerifyingKey(Signer::public_key(self))
+    }
+}
+
+/// Signatures are encoded using MessagePack, like the signature files written by `cli::sign()`.
+impl SignatureEncoding for StatelessMerkleSignature {
+    type Repr = Vec<u8>;
+}
+
+impl TryFrom<&[u8]> for StatelessMerkleSignature {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        rmp_serde::from_slice(bytes).map_err(Error::from_source)
+    }
+}
+
+impl TryFrom<StatelessMerkleSignature> for Vec<u8> {
+    type Error = Error;
+
+    fn try_from(signature: StatelessMerkleSignature) -> Result<Self, Error> {
+        rmp_serde::to_vec(&signature).map_err(Error::from_source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::signature::stateless_merkle::StatelessMerkleSignature;
+    use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+    use crate::signature::winternitz::d::D;
+    use crate::utils::hash;
+    use signature::{Keypair, SignatureEncoding, Signer, Verifier};
+
+    fn get_signature_scheme() -> StatelessMerkleSignatureScheme {
+        StatelessMerkleSignatureScheme::new([0u8; 32], 16, 5, D::new(255))
+    }
+
+    #[test]
+    fn test_correct_signature() {
+        let signature_scheme = get_signature_scheme();
+        let signature = signature_scheme.sign(b"message");
+        let verifying_key = signature_scheme.verifying_key();
+        assert!(verifying_key.verify(b"message", &signature).is_ok());
+        assert!(verifying_key.verify(b"other message", &signature).is_err());
+    }
+
+    #[test]
+    fn test_signs_sha256_of_message() {
+        let signature_scheme = get_signature_scheme();
+        assert_eq!(
+            signature_scheme.sign(b"message"),
+            signature_scheme.sign_hash(hash(b"message"))
+        );
+    }
+
+    #[test]
+    fn test_encoding_roundtrip() {
+        let signature = get_signature_scheme().sign(b"message");
+        let bytes = signature.to_bytes();
+        assert_eq!(
+            StatelessMerkleSignature::try_from(bytes.as_slice()).unwrap(),
+            signature
+        );
+        assert!(StatelessMerkleSignature::try_from(&bytes[1..]).is_err());
+    }
+}
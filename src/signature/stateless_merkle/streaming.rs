@@ -0,0 +1,169 @@
+use crate::signature::stateless_merkle::{
+    StatelessMerkleSignature, StatelessMerkleSignatureScheme,
+};
+use crate::signature::{HashType, Verifier};
+use crate::utils::slice_to_hash;
+use ring::digest::{Context, SHA256};
+use std::io::{Read, Write};
+
+/// Incrementally signs a message of arbitrary length.
+///
+/// The message is hashed using SHA256 and the digest is signed, so the signature
+/// is the same as signing the SHA256 hash of the whole message with `Signer::sign()`.
+///
+/// # Examples
+///
+/// ```
+/// use hash_based_signatures::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+/// use hash_based_signatures::signature::winternitz::d::D;
+/// use hash_based_signatures::signature::Signer;
+///
+/// let signature_scheme = StatelessMerkleSignatureScheme::new([0; 32], 16, 5, D::new(255));
+/// let mut context = signature_scheme.signing_context();
+/// context.update(b"Hello, ");
+/// context.update(b"world!");
+/// let signature = context.finalize();
+///
+/// let mut context = StatelessMerkleSignatureScheme::verifying_context(signature_scheme.public_key());
+/// context.update(b"Hello, world!");
+/// assert!(context.finalize(&signature));
+/// ```
+pub struct SigningContext<'a> {
+    signature_scheme: &'a StatelessMerkleSignatureScheme,
+    digest: Context,
+}
+
+/// Incrementally verifies a signature of a message of arbitrary length.
+/// See `SigningContext`.
+pub struct VerifyingContext {
+    public_key: HashType,
+    digest: Context,
+}
+
+impl<'a> SigningContext<'a> {
+    /// Appends `data` to the message.
+    pub fn update(&mut self, data: &[u8]) {
+        self.digest.update(data);
+    }
+
+    /// Signs the message.
+    pub fn finalize(self) -> StatelessMerkleSignature {
+        let message_hash = slice_to_hash(self.digest.finish().as_ref());
+        self.signature_scheme.sign_hash(message_hash)
+    }
+}
+
+impl Write for SigningContext<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl VerifyingContext {
+    /// Appends `data` to the message.
+    pub fn update(&mut self, data: &[u8]) {
+        self.digest.update(data);
+    }
+
+    /// Verifies the signature of the message.
+    pub fn finalize(self, signature: &StatelessMerkleSignature) -> bool {
+        let message_hash = slice_to_hash(self.digest.finish().as_ref());
+        StatelessMerkleSignatureScheme::verify(&self.public_key, &message_hash, signature)
+    }
+}
+
+impl Write for VerifyingContext {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl StatelessMerkleSignatureScheme {
+    /// Starts signing a message of arbitrary length.
+    pub fn signing_context(&self) -> SigningContext<'_> {
+        SigningContext {
+            signature_scheme: self,
+            digest: Context::new(&SHA256),
+        }
+    }
+
+    /// Starts verifying a signature of a message of arbitrary length.
+    pub fn verifying_context(public_key: HashType) -> VerifyingContext {
+        VerifyingContext {
+            public_key,
+            digest: Context::new(&SHA256),
+        }
+    }
+
+    /// Signs everything that can be read from `reader`.
+    pub fn sign_reader<R: Read>(&self, mut reader: R) -> std::io::Result<StatelessMerkleSignature> {
+        let mut context = self.signing_context();
+        std::io::copy(&mut reader, &mut context)?;
+        Ok(context.finalize())
+    }
+
+    /// Verifies a signature of everything that can be read from `reader`.
+    pub fn verify_reader<R: Read>(
+        public_key: HashType,
+        mut reader: R,
+        signature: &StatelessMerkleSignature,
+    ) -> std::io::Result<bool> {
+        let mut context = Self::verifying_context(public_key);
+        std::io::copy(&mut reader, &mut context)?;
+        Ok(context.finalize(signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
+    use crate::signature::winternitz::d::D;
+    use crate::signature::Signer;
+    use crate::utils::hash;
+
+    fn get_signature_scheme() -> StatelessMerkleSignatureScheme {
+        StatelessMerkleSignatureScheme::new([0u8; 32], 16, 5, D::new(255))
+    }
+
+    #[test]
+    fn test_sign_and_verify_reader() {
+        let signature_scheme = get_signature_scheme();
+        let message = vec![42u8; 10000];
+        let signature = signature_scheme.sign_reader(message.as_slice()).unwrap();
+
+        assert!(StatelessMerkleSignatureScheme::verify_reader(
+            signature_scheme.public_key(),
+            message.as_slice(),
+            &signature
+        )
+        .unwrap());
+        assert!(!StatelessMerkleSignatureScheme::verify_reader(
+            signature_scheme.public_key(),
+            &message[1..],
+            &signature
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_context_signs_sha256_of_message() {
+        let signature_scheme = get_signature_scheme();
+        let mut context = signature_scheme.signing_context();
+        context.update(b"Hello, ");
+        context.update(b"world!");
+        assert_eq!(
+            context.finalize(),
+            signature_scheme.sign_hash(hash(b"Hello, world!"))
+        );
+    }
+}
@@ -1,9 +1,21 @@
+pub mod checkpoints;
 pub mod d;
 pub mod domination_free_function;
+pub mod l_tree;
+pub mod multi_buffer;
 
+use crate::error::Error;
+use crate::secret::Secret;
+use crate::signature::winternitz::checkpoints::WinternitzCheckpoints;
 use crate::signature::winternitz::d::D;
 use crate::signature::winternitz::domination_free_function::domination_free_function;
-use crate::signature::{HashType, SignatureScheme};
+use crate::signature::winternitz::l_tree::l_tree;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::signature::winternitz::multi_buffer::LANES;
+use crate::signature::winternitz::multi_buffer::{hash_chains, Backend};
+use crate::signature::{HashType, KeyGen, Signer, Verifier};
+use crate::utils::ct_eq;
+#[cfg(test)]
 use crate::utils::{bits_to_unsigned_ints, get_least_significant_bits, hash};
 use anyhow::{bail, Result};
 use rand::prelude::*;
@@ -13,7 +25,7 @@ use std::iter;
 
 /// Private or Public key.
 /// The length depends on Winternitz parameter `d` and is roughly
-/// `256 / log2(d)`.
+/// `message_bits / log2(d + 1)`.
 pub type WinternitzKey = Vec<[u8; 32]>;
 
 /// Winternitz signature.
@@ -28,31 +40,39 @@ pub type WinternitzSignature = (u64, Vec<[u8; 32]>);
 /// The parameter `d` trades off signature size (the higher, the smaller the signature)
 /// and computation time (the higher, the longer the time).
 ///
+/// Messages are 256-bit hashes, unless the scheme is created with a different message
+/// length (see `D::with_message_bits()`). Such messages are signed using
+/// `WinternitzSignatureScheme::try_sign_bytes()`.
+///
 /// # Examples
 ///
 /// ```
-/// use hash_based_signatures::signature::SignatureScheme;
+/// use hash_based_signatures::signature::{Signer, Verifier};
 /// use hash_based_signatures::signature::winternitz::d::D;
 /// use hash_based_signatures::signature::winternitz::WinternitzSignatureScheme;
 ///
 /// let mut signature_scheme = WinternitzSignatureScheme::new([0u8; 32], D::new(15));
 /// let signature0 = signature_scheme.sign([0u8; 32]);
 /// assert!(WinternitzSignatureScheme::verify(
-///     signature_scheme.public_key(),
-///     [0u8; 32],
+///     &signature_scheme.public_key(),
+///     &[0u8; 32],
 ///     &signature0
 /// ));
 /// ```
 #[derive(Clone)]
 pub struct WinternitzSignatureScheme {
-    sk: WinternitzKey,
+    sk: Secret<WinternitzKey>,
     pk: WinternitzKey,
     d: D,
+    checkpoints: Option<WinternitzCheckpoints>,
 }
 
 /// Computes the hash chain of a given (intermediate) input.
 /// To do so, hash `i` is computed as `Sha256(i, <input>)`, with `i` going from
 /// `start` (inclusive) to `end` (exclusive).
+///
+/// This is the reference for `multi_buffer::hash_chains()`, which computes all chains.
+#[cfg(test)]
 fn hash_chain(input: HashType, start: u8, end: u8) -> HashType {
     let mut current_hash_value = input;
     let mut counter_buffer = [0u8; 32];
@@ -62,9 +82,7 @@ fn hash_chain(input: HashType, start: u8, end: u8) -> HashType {
         let index_bitstring = get_least_significant_bits(i as usize, 32);
         let index_bytes = bits_to_unsigned_ints(&index_bitstring);
         assert_eq!(index_bytes.len(), 4);
-        for i in 0..4 {
-            counter_buffer[i] = index_bytes[i];
-        }
+        counter_buffer[..4].copy_from_slice(&index_bytes);
 
         current_hash_value = hash(&[counter_buffer, current_hash_value].concat());
     }
@@ -73,7 +91,7 @@ fn hash_chain(input: HashType, start: u8, end: u8) -> HashType {
 
 #[cfg(not(target_arch = "wasm32"))]
 fn hash_chain_parallel(
-    inputs: &Vec<HashType>,
+    inputs: &[HashType],
     starts: impl Iterator<Item = u8>,
     ends: impl Iterator<Item = u8>,
 ) -> Vec<HashType> {
@@ -81,48 +99,133 @@ fn hash_chain_parallel(
     let starts: Vec<u8> = starts.take(inputs.len()).collect();
     let ends: Vec<u8> = ends.take(inputs.len()).collect();
 
+    // Each thread advances its share of the chains `LANES` at a time, see
+    // `multi_buffer::hash_chains()`. Giving it more than `LANES` chains lets a lane pick up
+    // the next chain as soon as its current one ends, instead of idling until the longest
+    // chain of the task is done.
+    let backend = Backend::detect();
+    let chunk_size = inputs
+        .len()
+        .div_ceil(rayon::current_num_threads())
+        .next_multiple_of(LANES)
+        .max(LANES);
     inputs
-        .par_iter()
-        .zip(starts.par_iter())
-        .zip(ends.par_iter())
-        .map(|((input, start), end)| hash_chain(*input, *start, *end))
-        .collect()
+        .par_chunks(chunk_size)
+        .zip(starts.par_chunks(chunk_size))
+        .zip(ends.par_chunks(chunk_size))
+        .map(|((inputs, starts), ends)| hash_chains(backend, inputs, starts, ends))
+        .collect::<Vec<_>>()
+        .concat()
 }
 
 #[cfg(target_arch = "wasm32")]
 fn hash_chain_parallel(
-    inputs: &Vec<HashType>,
+    inputs: &[HashType],
     starts: impl Iterator<Item = u8>,
     ends: impl Iterator<Item = u8>,
 ) -> Vec<HashType> {
-    // Same as above, but using `iter()` instead of `par_iter()` to avoid spawning threads.
+    // Same as above, but without `par_chunks()` to avoid spawning threads.
     let starts: Vec<u8> = starts.take(inputs.len()).collect();
     let ends: Vec<u8> = ends.take(inputs.len()).collect();
 
-    inputs
-        .iter()
-        .zip(starts.iter())
-        .zip(ends.iter())
-        .map(|((input, start), end)| hash_chain(*input, *start, *end))
-        .collect()
+    hash_chains(Backend::detect(), inputs, &starts, &ends)
 }
 
 impl WinternitzSignatureScheme {
     /// Builds a Winternitz signature scheme from the given `seed`.
     pub fn new(seed: [u8; 32], d: D) -> Self {
-        let mut rng = ChaCha20Rng::from_seed(seed);
+        let sk = Self::secret_key(seed, d);
+        let pk = hash_chain_parallel(&sk, iter::repeat(0), iter::repeat(d.d as u8));
+
+        Self {
+            sk,
+            pk,
+            d,
+            checkpoints: None,
+        }
+    }
+
+    /// Like `WinternitzSignatureScheme::new()`, but keeps the values of the hash chains
+    /// at every multiple of `interval`, so that signing starts from the nearest checkpoint.
+    ///
+    /// Fails if `interval` is 0 or larger than `d`.
+    pub fn with_checkpoints(seed: [u8; 32], d: D, interval: u8) -> Result<Self, Error> {
+        let sk = Self::secret_key(seed, d);
+        let checkpoints = WinternitzCheckpoints::compute(&seed, &sk, d, interval)?;
+
+        Ok(Self {
+            sk,
+            pk: checkpoints.public_key(),
+            d,
+            checkpoints: Some(checkpoints),
+        })
+    }
 
-        let mut buffer = [0u8; 32];
-        let mut sk = Vec::with_capacity(d.signature_and_key_size());
+    /// Rebuilds the scheme created by `WinternitzSignatureScheme::with_checkpoints()` from
+    /// its checkpoints, e.g. from a cache, without computing the hash chains again.
+    ///
+    /// Fails if the checkpoints were created for a different seed or different parameters,
+    /// or if they have been modified.
+    pub fn from_checkpoints(
+        seed: [u8; 32],
+        d: D,
+        checkpoints: WinternitzCheckpoints,
+    ) -> Result<Self, Error> {
+        checkpoints.validate(&seed, d)?;
 
-        // create secrets
-        for _ in 0..d.signature_and_key_size() {
-            rng.fill_bytes(&mut buffer);
-            sk.push(buffer);
+        Ok(Self {
+            sk: Self::secret_key(seed, d),
+            pk: checkpoints.public_key(),
+            d,
+            checkpoints: Some(checkpoints),
+        })
+    }
+
+    fn secret_key(seed: [u8; 32], d: D) -> Secret<WinternitzKey> {
+        let mut rng = ChaCha20Rng::from_seed(seed);
+
+        let mut sk = Secret::new(vec![[0u8; 32]; d.signature_and_key_size()]);
+        for secret in sk.iter_mut() {
+            rng.fill_bytes(secret);
         }
-        let pk = hash_chain_parallel(&sk, iter::repeat(0), iter::repeat(d.d as u8));
+        sk
+    }
+
+    /// The checkpoints of the hash chains, if the scheme keeps them.
+    pub fn checkpoints(&self) -> Option<&WinternitzCheckpoints> {
+        self.checkpoints.as_ref()
+    }
+
+    /// The Winternitz parameter `d` of this scheme.
+    pub fn d(&self) -> D {
+        self.d
+    }
+
+    /// The public key, compressed into a single hash using `l_tree()`.
+    pub fn compressed_public_key(&self) -> HashType {
+        l_tree(&self.pk)
+    }
+
+    /// Like `WinternitzSignatureScheme::public_key_from_message_and_signature()`,
+    /// but computes the compressed public key.
+    pub fn compressed_public_key_from_message_and_signature(
+        message: HashType,
+        signature: &WinternitzSignature,
+    ) -> Result<HashType> {
+        let public_key = Self::public_key_from_message_and_signature(message, signature)?;
+        Ok(l_tree(&public_key))
+    }
 
-        Self { sk, pk, d }
+    /// Verifies a signature against a compressed public key.
+    pub fn verify_compressed(
+        pk: &HashType,
+        message: &HashType,
+        signature: &WinternitzSignature,
+    ) -> bool {
+        match Self::compressed_public_key_from_message_and_signature(*message, signature) {
+            Ok(expected_public_key) => ct_eq(&expected_public_key, pk),
+            Err(_) => false,
+        }
     }
 
     /// Given a message and signature, computes the public key belonging to the private
@@ -130,9 +233,18 @@ impl WinternitzSignatureScheme {
     pub fn public_key_from_message_and_signature(
         message: HashType,
         signature: &WinternitzSignature,
+    ) -> Result<WinternitzKey> {
+        Self::public_key_from_message_bytes_and_signature(&message, signature)
+    }
+
+    /// Like `WinternitzSignatureScheme::public_key_from_message_and_signature()`,
+    /// but for messages of any length supported by `D`.
+    pub fn public_key_from_message_bytes_and_signature(
+        message: &[u8],
+        signature: &WinternitzSignature,
     ) -> Result<WinternitzKey> {
         let (d, signature) = signature;
-        let d = D::try_from(*d)?;
+        let d = D::try_with_message_bits(*d, message.len() * 8)?;
 
         let times_to_hash = domination_free_function(message, &d);
 
@@ -141,39 +253,99 @@ impl WinternitzSignatureScheme {
         }
 
         let expected_pk = hash_chain_parallel(
-            &signature,
+            signature,
             times_to_hash.into_iter(),
             iter::repeat(d.d as u8),
         );
 
         Ok(expected_pk)
     }
-}
 
-impl SignatureScheme<WinternitzKey, HashType, WinternitzSignature> for WinternitzSignatureScheme {
-    fn public_key(&self) -> WinternitzKey {
-        self.pk.clone()
-    }
-
-    fn sign(&mut self, message: HashType) -> WinternitzSignature {
+    /// Signs a message whose length matches the message length of `d`,
+    /// failing if it does not.
+    pub fn try_sign_bytes(&mut self, message: &[u8]) -> Result<WinternitzSignature, Error> {
+        if message.len() * 8 != self.d.message_bits() {
+            return Err(Error::InvalidMessageLength {
+                length: message.len() * 8,
+                expected: self.d.message_bits(),
+            });
+        }
         let times_to_hash = domination_free_function(message, &self.d);
         assert_eq!(times_to_hash.len(), self.sk.len());
 
-        let signature = hash_chain_parallel(&self.sk, iter::repeat(0), times_to_hash.into_iter());
+        let signature = match &self.checkpoints {
+            None => hash_chain_parallel(&self.sk, iter::repeat(0), times_to_hash.into_iter()),
+            Some(checkpoints) => {
+                let (inputs, starts): (Vec<HashType>, Vec<u8>) = times_to_hash
+                    .iter()
+                    .enumerate()
+                    .map(|(chain, times)| {
+                        checkpoints
+                            .nearest(chain, *times)
+                            .unwrap_or((self.sk[chain], 0))
+                    })
+                    .unzip();
+                let inputs = Secret::new(inputs);
+                hash_chain_parallel(&inputs, starts.into_iter(), times_to_hash.into_iter())
+            }
+        };
 
-        (self.d.d, signature)
+        Ok((self.d.d, signature))
     }
 
-    fn verify(pk: WinternitzKey, message: HashType, signature: &WinternitzSignature) -> bool {
-        match WinternitzSignatureScheme::public_key_from_message_and_signature(message, signature) {
-            Ok(expected_public_key) => expected_public_key == pk,
+    /// Verifies a signature of a message of any length supported by `D`.
+    pub fn verify_bytes(
+        pk: &WinternitzKey,
+        message: &[u8],
+        signature: &WinternitzSignature,
+    ) -> bool {
+        match WinternitzSignatureScheme::public_key_from_message_bytes_and_signature(
+            message, signature,
+        ) {
+            Ok(expected_public_key) => ct_eq(&expected_public_key.concat(), &pk.concat()),
             Err(_) => false,
         }
     }
 }
 
+impl Verifier for WinternitzSignatureScheme {
+    type PublicKey = WinternitzKey;
+    type Message = HashType;
+    type Signature = WinternitzSignature;
+
+    fn verify(pk: &WinternitzKey, message: &HashType, signature: &WinternitzSignature) -> bool {
+        WinternitzSignatureScheme::verify_bytes(pk, message, signature)
+    }
+}
+
+impl Signer for WinternitzSignatureScheme {
+    fn public_key(&self) -> WinternitzKey {
+        self.pk.clone()
+    }
+
+    /// Signs a message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scheme was created for messages that are not 256 bits long.
+    /// Consider using `WinternitzSignatureScheme::try_sign_bytes()`.
+    fn sign(&mut self, message: HashType) -> WinternitzSignature {
+        self.try_sign_bytes(&message)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+}
+
+impl KeyGen for WinternitzSignatureScheme {
+    type Params = D;
+
+    fn key_gen(seed: HashType, d: &D) -> Result<Self, Error> {
+        Ok(Self::new(seed, *d))
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::signature::winternitz::d::D;
     use crate::signature::winternitz::WinternitzSignatureScheme;
     use crate::signature::SignatureScheme;
@@ -205,6 +377,17 @@ mod tests {
         ))
     }
 
+    #[test]
+    fn test_correct_signature_d7() {
+        let mut signature_scheme = get_signature_scheme(D::new(7));
+        let signature = signature_scheme.sign([1u8; 32]);
+        assert!(WinternitzSignatureScheme::verify(
+            signature_scheme.public_key(),
+            [1u8; 32],
+            &signature
+        ))
+    }
+
     #[test]
     fn test_correct_signature_d15() {
         let mut signature_scheme = get_signature_scheme(D::new(15));
@@ -216,6 +399,17 @@ mod tests {
         ))
     }
 
+    #[test]
+    fn test_correct_signature_d31() {
+        let mut signature_scheme = get_signature_scheme(D::new(31));
+        let signature = signature_scheme.sign([1u8; 32]);
+        assert!(WinternitzSignatureScheme::verify(
+            signature_scheme.public_key(),
+            [1u8; 32],
+            &signature
+        ))
+    }
+
     #[test]
     fn test_correct_signature_d255() {
         let mut signature_scheme = get_signature_scheme(D::new(255));
@@ -226,4 +420,129 @@ mod tests {
             &signature
         ))
     }
+
+    #[test]
+    fn test_correct_signature_message_bits() {
+        for message_bits in [128, 192, 256, 512] {
+            let d = D::with_message_bits(15, message_bits);
+            let mut signature_scheme = get_signature_scheme(d);
+            assert_eq!(signature_scheme.pk.len(), d.signature_and_key_size());
+
+            let message = vec![1u8; message_bits / 8];
+            let signature = signature_scheme.try_sign_bytes(&message).unwrap();
+            assert!(WinternitzSignatureScheme::verify_bytes(
+                &signature_scheme.pk,
+                &message,
+                &signature
+            ));
+
+            let mut other_message = message.clone();
+            other_message[0] = 2;
+            assert!(!WinternitzSignatureScheme::verify_bytes(
+                &signature_scheme.pk,
+                &other_message,
+                &signature
+            ));
+            // A prefix of the message has a different length, and hence a different key size
+            assert!(!WinternitzSignatureScheme::verify_bytes(
+                &signature_scheme.pk,
+                &message[..8],
+                &signature
+            ));
+        }
+    }
+
+    #[test]
+    fn test_try_sign_bytes_wrong_length() {
+        let mut signature_scheme = get_signature_scheme(D::with_message_bits(15, 512));
+        assert_eq!(
+            signature_scheme.try_sign_bytes(&[0u8; 32]).err(),
+            Some(Error::InvalidMessageLength {
+                length: 256,
+                expected: 512
+            })
+        );
+    }
+
+    #[test]
+    fn test_compressed_public_key() {
+        let mut signature_scheme = get_signature_scheme(D::new(15));
+        let compressed_public_key = signature_scheme.compressed_public_key();
+        let signature = signature_scheme.sign([1u8; 32]);
+        assert!(WinternitzSignatureScheme::verify_compressed(
+            &compressed_public_key,
+            &[1u8; 32],
+            &signature
+        ));
+        assert!(!WinternitzSignatureScheme::verify_compressed(
+            &compressed_public_key,
+            &[2u8; 32],
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_checkpoints_give_same_keys_and_signatures() {
+        for (d, interval) in [(1, 1), (3, 2), (15, 4), (15, 15), (255, 16), (255, 7)] {
+            let d = D::new(d);
+            let mut signature_scheme = get_signature_scheme(d);
+            let mut with_checkpoints =
+                WinternitzSignatureScheme::with_checkpoints([0u8; 32], d, interval).unwrap();
+            assert_eq!(with_checkpoints.pk, signature_scheme.pk);
+
+            for message in [[0u8; 32], [1u8; 32], [0xa5; 32], [255u8; 32]] {
+                assert_eq!(
+                    with_checkpoints.sign(message),
+                    signature_scheme.sign(message)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_checkpoints() {
+        let d = D::new(15);
+        let signature_scheme =
+            WinternitzSignatureScheme::with_checkpoints([1u8; 32], d, 4).unwrap();
+        let bytes = rmp_serde::to_vec(signature_scheme.checkpoints().unwrap()).unwrap();
+
+        let checkpoints = rmp_serde::from_slice(&bytes).unwrap();
+        let mut restored =
+            WinternitzSignatureScheme::from_checkpoints([1u8; 32], d, checkpoints).unwrap();
+        assert_eq!(restored.pk, signature_scheme.pk);
+        let signature = restored.sign([3u8; 32]);
+        assert!(WinternitzSignatureScheme::verify(
+            restored.public_key(),
+            [3u8; 32],
+            &signature
+        ));
+
+        // Checkpoints of other keys or parameters are rejected
+        for (seed, d) in [([2u8; 32], D::new(15)), ([1u8; 32], D::new(3))] {
+            let checkpoints = rmp_serde::from_slice(&bytes).unwrap();
+            assert_eq!(
+                WinternitzSignatureScheme::from_checkpoints(seed, d, checkpoints).err(),
+                Some(Error::CheckpointMismatch)
+            );
+        }
+        // Modified checkpoints are rejected, e.g. a different public key
+        let mut modified = bytes.clone();
+        let last = modified.len() - 1;
+        modified[last] ^= 1;
+        let checkpoints = rmp_serde::from_slice(&modified).unwrap();
+        assert_eq!(
+            WinternitzSignatureScheme::from_checkpoints([1u8; 32], d, checkpoints).err(),
+            Some(Error::CheckpointMismatch)
+        );
+    }
+
+    #[test]
+    fn test_invalid_checkpoint_interval() {
+        for interval in [0, 16] {
+            assert_eq!(
+                WinternitzSignatureScheme::with_checkpoints([0u8; 32], D::new(15), interval).err(),
+                Some(Error::InvalidCheckpointInterval(interval))
+            );
+        }
+    }
 }
@@ -0,0 +1,246 @@
+use crate::error::Error;
+use crate::secret::Secret;
+use crate::signature::winternitz::d::D;
+use crate::signature::winternitz::{hash_chain_parallel, WinternitzKey};
+use crate::signature::HashType;
+use crate::utils::{ct_eq, hmac};
+use serde::{Deserialize, Serialize};
+use std::iter;
+use zeroize::Zeroize;
+
+/// Prefix of the data authenticated by the MAC stored along with the checkpoints
+pub const CHECKPOINT_MAC_DOMAIN: &[u8] = b"hash-based-signatures winternitz checkpoints";
+
+/// Intermediate values of the hash chains of a Winternitz key, stored at a fixed interval.
+///
+/// Signing then only needs to hash from the nearest checkpoint below the signature value,
+/// i.e. less than `interval` times per chain, and a key can be rebuilt from its checkpoints
+/// without walking the chains (see `WinternitzSignatureScheme::from_checkpoints()`).
+/// Computing the checkpoints walks every chain in full once, like computing the public key.
+/// This trades memory for speed, which pays off when the same one-time key is used or
+/// rebuilt repeatedly, e.g. from a cache.
+/// `QIndexedSignatureScheme` can be rebuilt from the checkpoints of all of its one-time keys
+/// (see `QIndexedSignatureScheme::from_checkpoints()`), which
+/// `StatelessMerkleSignatureScheme::enable_checkpoint_cache()` uses to regenerate the upper
+/// layers of its tree without walking their chains again.
+///
+/// The checkpoints can be serialized to keep them in an on-disk cache. Their shape is checked
+/// when deserializing them, and they are authenticated by a MAC keyed with the seed when a key
+/// is rebuilt from them, so a modified cache (e.g. a wrong public key) is rejected.
+/// Note that they are secret key material: A value at position `i` of a chain can be used
+/// to sign any message for which the chain needs to be hashed at least `i` times.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawWinternitzCheckpoints")]
+pub struct WinternitzCheckpoints {
+    /// MAC of the parameters and values, keyed with the seed the key was derived from.
+    /// Detects checkpoints of other keys as well as modified values.
+    mac: HashType,
+    d: u64,
+    interval: u8,
+    /// For each chain, the values at positions `interval`, `2 * interval`, ..., and `d`
+    values: Vec<Vec<HashType>>,
+}
+
+impl Drop for WinternitzCheckpoints {
+    fn drop(&mut self) {
+        self.values.zeroize();
+    }
+}
+
+/// The serialized form of `WinternitzCheckpoints`, whose shape is checked before use.
+#[derive(Deserialize)]
+struct RawWinternitzCheckpoints {
+    mac: HashType,
+    d: u64,
+    interval: u8,
+    values: Vec<Vec<HashType>>,
+}
+
+impl Drop for RawWinternitzCheckpoints {
+    fn drop(&mut self) {
+        self.values.zeroize();
+    }
+}
+
+impl TryFrom<RawWinternitzCheckpoints> for WinternitzCheckpoints {
+    type Error = Error;
+
+    /// Checks that `d` and the interval are valid, and that every chain has a value for each
+    /// checkpoint. Whether they belong to a key is checked by `WinternitzCheckpoints::validate()`.
+    fn try_from(mut raw: RawWinternitzCheckpoints) -> Result<Self, Error> {
+        let d = D::try_new(raw.d)?;
+        if raw.interval == 0 || raw.interval as u64 > d.d {
+            return Err(Error::InvalidCheckpointInterval(raw.interval));
+        }
+        let expected_positions = positions(d.d as u8, raw.interval).len();
+        if raw
+            .values
+            .iter()
+            .any(|chain| chain.len() != expected_positions)
+        {
+            return Err(Error::CheckpointMismatch);
+        }
+        Ok(Self {
+            mac: raw.mac,
+            d: d.d,
+            interval: raw.interval,
+            values: std::mem::take(&mut raw.values),
+        })
+    }
+}
+
+/// Authenticates the parameters and values of the checkpoints using the `seed` as the key.
+fn mac(seed: &HashType, d: u64, interval: u8, values: &[Vec<HashType>]) -> HashType {
+    let mut data = [CHECKPOINT_MAC_DOMAIN, &d.to_be_bytes(), &[interval]].concat();
+    data.extend(values.iter().flatten().flatten());
+    let mac = hmac(seed, &data);
+    data.zeroize();
+    mac
+}
+
+/// The positions of the checkpoints: All multiples of `interval` below `d`, and `d`
+fn positions(d: u8, interval: u8) -> Vec<u8> {
+    (interval..d)
+        .step_by(interval as usize)
+        .chain([d])
+        .collect()
+}
+
+impl WinternitzCheckpoints {
+    /// Computes the checkpoints of the chains starting at `secrets`, which were derived
+    /// from `seed`.
+    ///
+    /// Fails if `interval` is 0 or larger than `d`.
+    pub(crate) fn compute(
+        seed: &HashType,
+        secrets: &[HashType],
+        d: D,
+        interval: u8,
+    ) -> Result<Self, Error> {
+        if interval == 0 || interval as u64 > d.d {
+            return Err(Error::InvalidCheckpointInterval(interval));
+        }
+        let positions = positions(d.d as u8, interval);
+
+        // Advance all chains from one checkpoint to the next at once, see `hash_chain_parallel()`
+        let mut values: Vec<Vec<HashType>> =
+            vec![Vec::with_capacity(positions.len()); secrets.len()];
+        let mut current = Secret::new(secrets.to_vec());
+        let mut position = 0;
+        for next_position in &positions {
+            current = Secret::new(hash_chain_parallel(
+                &current,
+                iter::repeat(position),
+                iter::repeat(*next_position),
+            ));
+            position = *next_position;
+            for (chain, value) in values.iter_mut().zip(current.iter()) {
+                chain.push(*value);
+            }
+        }
+
+        Ok(Self {
+            mac: mac(seed, d.d, interval, &values),
+            d: d.d,
+            interval,
+            values,
+        })
+    }
+
+    /// Checks that the checkpoints belong to a key with the given seed and parameters,
+    /// and that they have not been modified.
+    pub(crate) fn validate(&self, seed: &HashType, d: D) -> Result<(), Error> {
+        let expected_positions = match self.interval {
+            0 => 0,
+            interval => positions(d.d as u8, interval).len(),
+        };
+        let matches = ct_eq(&self.mac, &mac(seed, self.d, self.interval, &self.values))
+            && self.d == d.d
+            && expected_positions > 0
+            && self.values.len() == d.signature_and_key_size()
+            && self
+                .values
+                .iter()
+                .all(|chain| chain.len() == expected_positions);
+        if !matches {
+            return Err(Error::CheckpointMismatch);
+        }
+        Ok(())
+    }
+
+    /// The interval between two checkpoints of a chain.
+    pub fn interval(&self) -> u8 {
+        self.interval
+    }
+
+    /// The public key, i.e. the last values of the chains.
+    pub fn public_key(&self) -> WinternitzKey {
+        self.values
+            .iter()
+            .map(|chain| *chain.last().expect("Chains have at least one checkpoint"))
+            .collect()
+    }
+
+    /// The value of the `chain`-th chain at the nearest checkpoint at or below `position`,
+    /// along with the position of that checkpoint.
+    /// Returns `None` if the nearest checkpoint is the start of the chain.
+    pub(crate) fn nearest(&self, chain: usize, position: u8) -> Option<(HashType, u8)> {
+        let index = (position / self.interval) as usize;
+        if index == 0 {
+            return None;
+        }
+        // The last checkpoint is at `d`, which is not necessarily a multiple of the interval
+        let index = index.min(self.values[chain].len());
+        let checkpoint_position = if index == self.values[chain].len() {
+            self.d as u8
+        } else {
+            index as u8 * self.interval
+        };
+        Some((self.values[chain][index - 1], checkpoint_position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::signature::winternitz::checkpoints::{
+        positions, RawWinternitzCheckpoints, WinternitzCheckpoints,
+    };
+
+    #[test]
+    fn test_positions() {
+        assert_eq!(positions(15, 4), vec![4, 8, 12, 15]);
+        assert_eq!(positions(15, 5), vec![5, 10, 15]);
+        assert_eq!(positions(15, 15), vec![15]);
+        assert_eq!(positions(255, 16).len(), 16);
+    }
+
+    #[test]
+    fn test_invalid_shape_is_rejected_when_deserializing() {
+        let deserialize = |d: u64, interval: u8, values: Vec<Vec<[u8; 32]>>| {
+            let bytes = rmp_serde::to_vec(&([0u8; 32], d, interval, values)).unwrap();
+            rmp_serde::from_slice::<WinternitzCheckpoints>(&bytes).map(|_| ())
+        };
+        let chain = vec![[1u8; 32]; 4];
+
+        assert!(deserialize(15, 4, vec![chain.clone(); 3]).is_ok());
+        for (d, interval, values) in [
+            (15, 0, vec![chain.clone()]),
+            (15, 16, vec![chain.clone()]),
+            (14, 4, vec![chain.clone()]),
+            (15, 4, vec![chain.clone(), vec![]]),
+            (15, 5, vec![chain.clone()]),
+        ] {
+            assert!(deserialize(d, interval, values).is_err());
+        }
+
+        // The errors are the ones of `Error`
+        let checkpoints = WinternitzCheckpoints::try_from(RawWinternitzCheckpoints {
+            mac: [0u8; 32],
+            d: 15,
+            interval: 0,
+            values: vec![],
+        });
+        assert_eq!(checkpoints.err(), Some(Error::InvalidCheckpointInterval(0)));
+    }
+}
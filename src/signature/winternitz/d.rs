@@ -1,63 +1,155 @@
-use anyhow::{anyhow, Error, Result};
+use crate::error::Error;
 
 /// Wrapper around the parameter "d" used for `domination_free_function`
+///
+/// `d + 1` is the Winternitz parameter `w = 2^k`, i.e. each hash chain encodes `k` bits.
+/// It also determines the length of the messages (in bits) that can be signed, which is
+/// 256 bits unless specified otherwise.
 #[derive(Clone, Copy)]
 pub struct D {
     pub d: u64,
-    log_log_d_plus_1: usize,
+    bits: usize,
+    message_bits: usize,
 }
 
+/// The default message length, i.e. that of a SHA-256 digest
+pub const DEFAULT_MESSAGE_BITS: usize = 256;
+
+/// The maximal message length, i.e. that of a SHA-512 digest
+pub const MAX_MESSAGE_BITS: usize = 512;
+
 impl D {
     /// Wraps a value for `d`.
     ///
     /// # Panics
-    /// Panics if `d` is not of the form 2^(2^x) - 1.
-    /// Consider using `D::try_from()`.
+    /// Panics if `d` is not of the form 2^k - 1 for 1 <= k <= 8.
+    /// Consider using `D::try_new()`.
     pub fn new(d: u64) -> Self {
-        D::try_from(d).unwrap()
+        D::try_new(d).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Wraps a value for `d`, failing if `d` is not of the form 2^k - 1 for 1 <= k <= 8.
+    pub fn try_new(d: u64) -> Result<Self, Error> {
+        D::try_with_message_bits(d, DEFAULT_MESSAGE_BITS)
+    }
+
+    /// Wraps a value for `d`, for signing messages of `message_bits` bits (e.g. 128, 192,
+    /// 256 or 512).
+    ///
+    /// # Panics
+    /// Panics if `d` is not of the form 2^k - 1 for 1 <= k <= 8, or if `message_bits`
+    /// is not a multiple of 8 between 8 and 512.
+    /// Consider using `D::try_with_message_bits()`.
+    pub fn with_message_bits(d: u64, message_bits: usize) -> Self {
+        D::try_with_message_bits(d, message_bits).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Like `D::with_message_bits()`, but fails on invalid parameters.
+    pub fn try_with_message_bits(d: u64, message_bits: usize) -> Result<Self, Error> {
+        if message_bits == 0 || message_bits % 8 != 0 || message_bits > MAX_MESSAGE_BITS {
+            return Err(Error::InvalidMessageBits(message_bits));
+        }
+        let bits = match d.checked_add(1) {
+            Some(d_plus_1) if d_plus_1.is_power_of_two() => d_plus_1.trailing_zeros(),
+            _ => return Err(Error::InvalidD(d)),
+        };
+        // Integers are represented as `u8`s, so at most 8 bits can be combined
+        if bits == 0 || bits > 8 {
+            return Err(Error::InvalidD(d));
+        }
+        Ok(D {
+            d,
+            bits: bits as usize,
+            message_bits,
+        })
     }
 
     /// The number of bits that are combined into one integer value
     pub fn bits_to_combine(&self) -> usize {
-        1 << self.log_log_d_plus_1
+        self.bits
+    }
+
+    /// The length of the messages that can be signed, in bits
+    pub fn message_bits(&self) -> usize {
+        self.message_bits
+    }
+
+    /// The number of integers the message is split into.
+    /// If `bits_to_combine` does not divide the message length, the last one is zero-padded.
+    pub fn n0(&self) -> usize {
+        self.message_bits.div_ceil(self.bits)
     }
 
     /// The number of bits of the "checksum" c
     pub fn bits_c(&self) -> usize {
-        // The maximal value of c is d * n0,
-        // and this is the number of bits of (d + 1) * n0:
-        // log2((d + 1) * n0)
-        // = log2(d + 1) + log2(n0)
-        // = bits_to_combine + log2(256 / bits_to_combine)
-        // = bits_to_combine + 8 + log2_bits_to_combine
-        let bits_c = self.bits_to_combine() + 8 - self.log_log_d_plus_1;
+        // The maximal value of c is d * n0
+        let max_c = self.d * self.n0() as u64;
+        let bits_c = (u64::BITS - max_c.leading_zeros()) as usize;
 
         // Round up to the next factor of bits_to_combine
-        let bits_c = (((bits_c as f32) / (self.bits_to_combine() as f32)).ceil() as usize)
-            * self.bits_to_combine();
-        bits_c
+        bits_c.div_ceil(self.bits) * self.bits
     }
 
     /// Size of the resulting Winternitz signature / key
     pub fn signature_and_key_size(&self) -> usize {
-        (256 + self.bits_c()) / self.bits_to_combine()
+        self.n0() + self.bits_c() / self.bits
     }
 }
 
 impl TryFrom<u64> for D {
     type Error = Error;
 
-    fn try_from(d: u64) -> Result<D> {
-        let log_log_d_plus_1 = ((d + 1) as f64).log2().log2() as usize;
-        if d + 1 != (1 << (1 << log_log_d_plus_1)) {
-            Err(anyhow!(
-                "d is not of the form 2^(2^x) - 1! Try one of 1, 3, 15, or 255."
-            ))
-        } else {
-            Ok(D {
-                d,
-                log_log_d_plus_1,
-            })
+    fn try_from(d: u64) -> Result<D, Error> {
+        D::try_new(d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::signature::winternitz::d::D;
+
+    #[test]
+    fn test_valid_d() {
+        for d in [1, 3, 7, 15, 31, 63, 127, 255] {
+            assert_eq!(D::try_new(d).unwrap().d, d);
+        }
+    }
+
+    #[test]
+    fn test_signature_and_key_size() {
+        // Sizes for w = 4 and w = 16 match RFC 8391.
+        // For w = 8, the message is split into 86 integers (the last one padded with a zero
+        // bit), and the maximal checksum 7 * 86 = 602 needs 10 bits, i.e. 4 integers.
+        for (d, size) in [(1, 265), (3, 133), (7, 90), (15, 67), (31, 55), (255, 34)] {
+            assert_eq!(D::new(d).signature_and_key_size(), size);
+        }
+    }
+
+    #[test]
+    fn test_signature_and_key_size_for_message_bits() {
+        // Values from RFC 8391 (n = 16, 24 and 64)
+        for (message_bits, size) in [(128, 35), (192, 51), (256, 67), (512, 131)] {
+            let d = D::with_message_bits(15, message_bits);
+            assert_eq!(d.message_bits(), message_bits);
+            assert_eq!(d.signature_and_key_size(), size);
+        }
+    }
+
+    #[test]
+    fn test_invalid_message_bits() {
+        for message_bits in [0, 4, 100, 520, 1024] {
+            assert_eq!(
+                D::try_with_message_bits(15, message_bits).err(),
+                Some(Error::InvalidMessageBits(message_bits))
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_d() {
+        for d in [0, 2, 16, 511, 65535, u64::MAX] {
+            assert_eq!(D::try_new(d).err(), Some(Error::InvalidD(d)));
         }
     }
 }
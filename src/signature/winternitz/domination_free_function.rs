@@ -1,36 +1,36 @@
 use crate::signature::winternitz::d::D;
-use crate::signature::HashType;
 use crate::utils::{bits_to_unsigned_int, get_least_significant_bits};
 
-fn bitstring_to_integers(bit_string: &Vec<bool>, d: &D) -> Vec<u8> {
-    let n_elements = bit_string.len() / d.bits_to_combine();
-    (0..n_elements)
-        .map(|i| {
-            let start_bit = i * d.bits_to_combine();
-            let end_bit = start_bit + d.bits_to_combine();
-            bits_to_unsigned_int(&bit_string[start_bit..end_bit])
+fn bitstring_to_integers(bit_string: &[bool], d: &D) -> Vec<u8> {
+    bit_string
+        .chunks(d.bits_to_combine())
+        .map(|bits| {
+            // If bits_to_combine does not divide the length, the last integer is zero-padded
+            bits_to_unsigned_int(bits) << (d.bits_to_combine() - bits.len())
         })
         .collect()
 }
 
 /// A "domination-free function", as described in section 14.3.1 of the
 /// [textbook](http://toc.cryptobook.us/) by Boneh & Shoup (version 0.5).
-pub fn domination_free_function(input: HashType, d: &D) -> Vec<u8> {
+///
+/// # Panics
+/// Panics if the length of `input` does not match `d.message_bits()`.
+pub fn domination_free_function(input: &[u8], d: &D) -> Vec<u8> {
+    assert_eq!(input.len() * 8, d.message_bits());
     let bit_string: Vec<bool> = input
-        .map(|x| get_least_significant_bits(x as usize, 8))
-        .into_iter()
-        .flatten()
+        .iter()
+        .flat_map(|x| get_least_significant_bits(*x as usize, 8))
         .collect();
 
     let mut result = Vec::new();
-    let n0 = 256 / d.bits_to_combine();
-    let mut c = d.d * (n0 as u64);
+    let mut c = d.d * (d.n0() as u64);
     for x in bitstring_to_integers(&bit_string, d) {
         result.push(x);
         c -= x as u64;
     }
 
-    let c_bitstring = get_least_significant_bits(c as usize, d.bits_c() as usize);
+    let c_bitstring = get_least_significant_bits(c as usize, d.bits_c());
 
     result.append(&mut bitstring_to_integers(&c_bitstring, d));
 
@@ -45,7 +45,7 @@ mod tests {
 
     #[test]
     fn test_domination_free_function_0s_d1() {
-        let result = domination_free_function([0; 32], &D::new(1));
+        let result = domination_free_function(&[0; 32], &D::new(1));
         let mut expected = vec![0u8; 256];
 
         // Maximal value of c is 2^8
@@ -56,7 +56,7 @@ mod tests {
 
     #[test]
     fn test_domination_free_function_0s_d3() {
-        let result = domination_free_function([0; 32], &D::new(3));
+        let result = domination_free_function(&[0; 32], &D::new(3));
         let mut expected = vec![0u8; 128];
 
         // bits_to_combine is 2
@@ -67,9 +67,47 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_domination_free_function_0s_d7() {
+        let result = domination_free_function(&[0; 32], &D::new(7));
+        let mut expected = vec![0u8; 86];
+
+        // bits_to_combine is 3, so the last of the 86 integers is padded
+        // Maximal value of c is 7 * 86 = 0o1132
+        // Which should be encoded in 4 3-bit integers
+        expected.extend([1, 1, 3, 2]);
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_domination_free_function_1s_d7() {
+        let result = domination_free_function(&[255; 32], &D::new(7));
+        let mut expected = vec![7u8; 85];
+
+        // The last integer consists of the last bit and two padding bits
+        expected.push(0b100);
+        // c is 7 * 86 - (7 * 85 + 4) = 3
+        expected.extend([0, 0, 0, 3]);
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_domination_free_function_0s_d31() {
+        let result = domination_free_function(&[0; 32], &D::new(31));
+        let mut expected = vec![0u8; 52];
+
+        // bits_to_combine is 5, so the last of the 52 integers is padded
+        // Maximal value of c is 31 * 52 = 1612 = 0b00001_10010_01100
+        expected.extend([1, 18, 12]);
+
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn test_domination_free_function_0s_d15() {
-        let result = domination_free_function([0; 32], &D::new(15));
+        let result = domination_free_function(&[0; 32], &D::new(15));
         let mut expected = vec![0u8; 64];
 
         // bits_to_combine is 4
@@ -82,7 +120,7 @@ mod tests {
 
     #[test]
     fn test_domination_free_function_0s_d255() {
-        let result = domination_free_function([0; 32], &D::new(255));
+        let result = domination_free_function(&[0; 32], &D::new(255));
         let mut expected = vec![0u8; 32];
 
         // bits_to_combine is 8
@@ -95,7 +133,7 @@ mod tests {
 
     #[test]
     fn test_domination_free_function_1s_d255() {
-        let result = domination_free_function([255; 32], &D::new(255));
+        let result = domination_free_function(&[255; 32], &D::new(255));
         let mut expected = vec![255u8; 32];
 
         // bits_to_combine is 16
@@ -107,16 +145,16 @@ mod tests {
 
     fn get_domination_free_vectors_on_random_data(d: &D, count: usize) -> Vec<Vec<u8>> {
         let mut rng = ChaCha20Rng::from_seed([0; 32]);
-        let mut hash = [0u8; 32];
+        let mut hash = vec![0u8; d.message_bits() / 8];
         (0..count)
             .map(|_| {
                 rng.fill_bytes(&mut hash);
-                domination_free_function(hash, d)
+                domination_free_function(&hash, d)
             })
             .collect()
     }
 
-    fn assert_no_domination(domination_free_vectors: &Vec<Vec<u8>>) {
+    fn assert_no_domination(domination_free_vectors: &[Vec<u8>]) {
         for i in 0..domination_free_vectors.len() {
             for j in 0..domination_free_vectors.len() {
                 if i != j {
@@ -146,6 +184,78 @@ mod tests {
         assert_no_domination(&domination_free_vectors);
     }
 
+    #[test]
+    fn domination_free_on_random_data_d7() {
+        let domination_free_vectors = get_domination_free_vectors_on_random_data(&D::new(7), 1000);
+        assert_no_domination(&domination_free_vectors);
+    }
+
+    #[test]
+    fn domination_free_on_random_data_d31() {
+        let domination_free_vectors = get_domination_free_vectors_on_random_data(&D::new(31), 1000);
+        assert_no_domination(&domination_free_vectors);
+    }
+
+    #[test]
+    fn domination_free_on_random_data_d63_and_d127() {
+        for d in [63, 127] {
+            let domination_free_vectors =
+                get_domination_free_vectors_on_random_data(&D::new(d), 1000);
+            assert_no_domination(&domination_free_vectors);
+        }
+    }
+
+    #[test]
+    fn test_domination_free_function_length() {
+        for message_bits in [128, 192, 256, 512] {
+            for d in [1, 3, 7, 15, 31, 63, 127, 255] {
+                let d = D::with_message_bits(d, message_bits);
+                let result = domination_free_function(&vec![0xa5; message_bits / 8], &d);
+                assert_eq!(result.len(), d.signature_and_key_size());
+                assert!(result.iter().all(|x| (*x as u64) <= d.d));
+            }
+        }
+    }
+
+    #[test]
+    fn domination_free_on_random_data_message_bits() {
+        for message_bits in [128, 192, 512] {
+            for d in [3, 15, 255] {
+                let d = D::with_message_bits(d, message_bits);
+                let domination_free_vectors = get_domination_free_vectors_on_random_data(&d, 500);
+                assert_no_domination(&domination_free_vectors);
+            }
+        }
+    }
+
+    #[test]
+    fn test_domination_free_function_0s_128_bits() {
+        let result = domination_free_function(&[0; 16], &D::with_message_bits(15, 128));
+        let mut expected = vec![0u8; 32];
+
+        // Maximal value of c is 15 * 32 = 0x1e0
+        expected.extend([0x1, 0xe, 0x0]);
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_domination_free_function_1s_512_bits() {
+        let result = domination_free_function(&[255; 64], &D::with_message_bits(255, 512));
+        let mut expected = vec![255u8; 64];
+
+        // c is 0, encoded in 2 8-bit integers (the maximum is 255 * 64 = 0x3fc0)
+        expected.extend([0, 0]);
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_domination_free_function_wrong_length() {
+        domination_free_function(&[0; 16], &D::new(15));
+    }
+
     #[test]
     fn domination_free_on_random_data_d15() {
         let domination_free_vectors = get_domination_free_vectors_on_random_data(&D::new(15), 1000);
@@ -0,0 +1,76 @@
+use crate::signature::HashType;
+use crate::utils::hash;
+
+/// Prefix of the hashed nodes of an L-tree, so that they never collide with the nodes
+/// of a `MerkleTree`
+pub const L_TREE_NODE_DOMAIN: &[u8] = b"hash-based-signatures winternitz l-tree node";
+
+/// Hash function applied to the internal nodes of an L-tree
+pub fn l_tree_node_hash(left: &HashType, right: &HashType) -> HashType {
+    hash(&[L_TREE_NODE_DOMAIN, left, right].concat())
+}
+
+/// Compresses a Winternitz public key (i.e. the ends of its hash chains) into a single hash,
+/// using a tree of the same shape as the L-tree of
+/// [XMSS](https://www.rfc-editor.org/rfc/rfc8391#section-4.1.5).
+///
+/// An L-tree is a binary tree over the chain ends, which are not necessarily a power of two:
+/// On each level, neighbouring nodes are hashed, and a node without a neighbour is lifted
+/// to the next level as is.
+///
+/// Unlike in XMSS, the nodes are not hashed with addresses and bitmasks, but using the plain
+/// hash function with a fixed domain separator (see `l_tree_node_hash()`). So the result is not
+/// compatible with XMSS, and its security relies on the collision resistance of the hash
+/// function, like the rest of this crate.
+///
+/// # Panics
+/// Panics if `chain_ends` is empty.
+pub fn l_tree(chain_ends: &[HashType]) -> HashType {
+    assert!(!chain_ends.is_empty(), "L-tree needs at least one node");
+    let mut nodes = chain_ends.to_vec();
+    while nodes.len() > 1 {
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => l_tree_node_hash(left, right),
+                [node] => *node,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    nodes[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::signature::winternitz::l_tree::{l_tree, l_tree_node_hash};
+
+    #[test]
+    fn test_l_tree_of_three_nodes() {
+        let nodes = [[0u8; 32], [1u8; 32], [2u8; 32]];
+        let expected = l_tree_node_hash(&l_tree_node_hash(&nodes[0], &nodes[1]), &nodes[2]);
+        assert_eq!(l_tree(&nodes), expected);
+        assert_eq!(l_tree(&nodes[..1]), nodes[0]);
+    }
+
+    #[test]
+    fn test_l_tree_of_five_nodes() {
+        let nodes: Vec<[u8; 32]> = (0u8..5).map(|i| [i; 32]).collect();
+        // Level 1: h(0, 1), h(2, 3), 4
+        // Level 2: h(h(0, 1), h(2, 3)), 4
+        let expected = l_tree_node_hash(
+            &l_tree_node_hash(
+                &l_tree_node_hash(&nodes[0], &nodes[1]),
+                &l_tree_node_hash(&nodes[2], &nodes[3]),
+            ),
+            &nodes[4],
+        );
+        assert_eq!(l_tree(&nodes), expected);
+    }
+
+    #[test]
+    fn test_l_tree_depends_on_order() {
+        let nodes = [[0u8; 32], [1u8; 32]];
+        assert_ne!(l_tree(&nodes), l_tree(&[nodes[1], nodes[0]]));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::signature::HashType;
+use crate::utils::hash;
+
+/// The number of hash chains that are advanced at once.
+pub const LANES: usize = 8;
+
+/// Implementation used to advance `LANES` hash chains by one step.
+///
+/// Backends can only be obtained from `Backend::detect()` and `Backend::supported()`, so
+/// that a backend needing CPU features that are not available can never be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backend(Kind);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    /// Hashes the lanes one after another using `ring`.
+    Portable,
+    /// Hashes all lanes at once, one lane per 32-bit element of the AVX2 registers.
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+}
+
+impl Backend {
+    /// The fastest backend supported by the CPU, detected at runtime.
+    ///
+    /// Uses AVX2 whenever it is supported (compare the `winternitz/hash_chains_*`
+    /// benchmarks). Without optimizations, the intrinsics are much slower than `ring`'s
+    /// assembly though, so debug builds always use the portable backend.
+    pub fn detect() -> Self {
+        #[cfg(target_arch = "x86_64")]
+        if cfg!(not(debug_assertions)) && is_x86_feature_detected!("avx2") {
+            return Backend(Kind::Avx2);
+        }
+        Backend(Kind::Portable)
+    }
+
+    /// All backends that are supported by the CPU.
+    pub fn supported() -> Vec<Self> {
+        #[allow(unused_mut)]
+        let mut backends = vec![Backend(Kind::Portable)];
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            backends.push(Backend(Kind::Avx2));
+        }
+        backends
+    }
+
+    /// A short name of the backend, e.g. for benchmarks.
+    pub fn name(self) -> &'static str {
+        match self.0 {
+            Kind::Portable => "portable",
+            #[cfg(target_arch = "x86_64")]
+            Kind::Avx2 => "avx2",
+        }
+    }
+
+    /// Replaces each `values[i]` by `Sha256(counters[i] || values[i])`, where the counter
+    /// is encoded as a 4-byte big-endian integer, padded with zeros to 32 bytes.
+    /// This is one step of a hash chain, see `hash_chain()`.
+    fn step(self, values: &mut [HashType; LANES], counters: &[u32; LANES]) {
+        match self.0 {
+            Kind::Portable => {
+                let mut buffer = [0u8; 64];
+                for (value, counter) in values.iter_mut().zip(counters) {
+                    buffer[..4].copy_from_slice(&counter.to_be_bytes());
+                    buffer[32..].copy_from_slice(value);
+                    *value = hash(&buffer);
+                }
+            }
+            #[cfg(target_arch = "x86_64")]
+            // SAFETY: `Kind::Avx2` is private and only constructed after detecting AVX2 in
+            // `Backend::detect()` and `Backend::supported()`.
+            Kind::Avx2 => unsafe { avx2::step(values, counters) },
+        }
+    }
+}
+
+/// Computes the hash chains of all `inputs`, from `starts[i]` (inclusive) to `ends[i]`
+/// (exclusive), like `hash_chain()` does for a single input.
+///
+/// The chains are scheduled onto `LANES` lanes, and a lane picks up the next chain
+/// as soon as its chain is finished, so that all lanes are busy as long as possible.
+pub fn hash_chains(
+    backend: Backend,
+    inputs: &[HashType],
+    starts: &[u8],
+    ends: &[u8],
+) -> Vec<HashType> {
+    assert_eq!(inputs.len(), starts.len());
+    assert_eq!(inputs.len(), ends.len());
+
+    let mut results = inputs.to_vec();
+    let mut pending = (0..inputs.len()).filter(|i| starts[*i] < ends[*i]);
+    // The index of the chain in each lane, and its current position
+    let mut lanes: [Option<(usize, u8)>; LANES] =
+        [(); LANES].map(|_| pending.next().map(|i| (i, starts[i])));
+
+    let mut values = [[0u8; 32]; LANES];
+    let mut counters = [0u32; LANES];
+    while lanes.iter().any(Option::is_some) {
+        for (lane, chain) in lanes.iter().enumerate() {
+            if let Some((i, position)) = chain {
+                values[lane] = results[*i];
+                counters[lane] = *position as u32;
+            }
+        }
+
+        backend.step(&mut values, &counters);
+
+        for (lane, chain) in lanes.iter_mut().enumerate() {
+            if let Some((i, position)) = *chain {
+                results[i] = values[lane];
+                *chain = if position + 1 < ends[i] {
+                    Some((i, position + 1))
+                } else {
+                    pending.next().map(|i| (i, starts[i]))
+                };
+            }
+        }
+    }
+
+    results
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use crate::signature::winternitz::multi_buffer::LANES;
+    use crate::signature::HashType;
+    use std::arch::x86_64::*;
+
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+
+    const IV: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    macro_rules! rotr {
+        ($x:expr, $n:literal) => {
+            _mm256_or_si256(
+                _mm256_srli_epi32::<$n>($x),
+                _mm256_slli_epi32::<{ 32 - $n }>($x),
+            )
+        };
+    }
+
+    #[inline(always)]
+    unsafe fn add(a: __m256i, b: __m256i) -> __m256i {
+        _mm256_add_epi32(a, b)
+    }
+
+    #[inline(always)]
+    unsafe fn splat(x: u32) -> __m256i {
+        _mm256_set1_epi32(x as i32)
+    }
+
+    /// The SHA-256 compression function, applied to all lanes.
+    #[inline(always)]
+    unsafe fn compress(state: &mut [__m256i; 8], block: &[__m256i; 16]) {
+        let mut w = [_mm256_setzero_si256(); 64];
+        w[..16].copy_from_slice(block);
+        for t in 16..64 {
+            let s0 = _mm256_xor_si256(
+                _mm256_xor_si256(rotr!(w[t - 15], 7), rotr!(w[t - 15], 18)),
+                _mm256_srli_epi32::<3>(w[t - 15]),
+            );
+            let s1 = _mm256_xor_si256(
+                _mm256_xor_si256(rotr!(w[t - 2], 17), rotr!(w[t - 2], 19)),
+                _mm256_srli_epi32::<10>(w[t - 2]),
+            );
+            w[t] = add(add(w[t - 16], s0), add(w[t - 7], s1));
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for t in 0..64 {
+            let s1 = _mm256_xor_si256(_mm256_xor_si256(rotr!(e, 6), rotr!(e, 11)), rotr!(e, 25));
+            let ch = _mm256_xor_si256(_mm256_and_si256(e, f), _mm256_andnot_si256(e, g));
+            let temp1 = add(add(add(h, s1), add(ch, splat(K[t]))), w[t]);
+            let s0 = _mm256_xor_si256(_mm256_xor_si256(rotr!(a, 2), rotr!(a, 13)), rotr!(a, 22));
+            let maj = _mm256_xor_si256(
+                _mm256_xor_si256(_mm256_and_si256(a, b), _mm256_and_si256(a, c)),
+                _mm256_and_si256(b, c),
+            );
+            let temp2 = add(s0, maj);
+            h = g;
+            g = f;
+            f = e;
+            e = add(d, temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = add(temp1, temp2);
+        }
+
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = add(*word, value);
+        }
+    }
+
+    /// See `Backend::step()`.
+    ///
+    /// # Safety
+    /// The CPU needs to support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn step(values: &mut [HashType; LANES], counters: &[u32; LANES]) {
+        // Transpose the inputs, such that each register holds the same word of all lanes
+        let word = |lane: usize, index: usize| {
+            let bytes = &values[lane][4 * index..4 * index + 4];
+            u32::from_be_bytes(bytes.try_into().unwrap()) as i32
+        };
+        let mut block = [_mm256_setzero_si256(); 16];
+        block[0] = _mm256_loadu_si256(counters.as_ptr() as *const __m256i);
+        for index in 0..8 {
+            block[8 + index] = _mm256_setr_epi32(
+                word(0, index),
+                word(1, index),
+                word(2, index),
+                word(3, index),
+                word(4, index),
+                word(5, index),
+                word(6, index),
+                word(7, index),
+            );
+        }
+
+        let mut state = IV.map(|x| splat(x));
+        compress(&mut state, &block);
+
+        // The message is always 64 bytes long, so the padding block is always the same
+        let mut padding = [_mm256_setzero_si256(); 16];
+        padding[0] = splat(0x80000000);
+        padding[15] = splat(512);
+        compress(&mut state, &padding);
+
+        let mut words = [[0u32; LANES]; 8];
+        for (words, register) in words.iter_mut().zip(state) {
+            _mm256_storeu_si256(words.as_mut_ptr() as *mut __m256i, register);
+        }
+        for (lane, value) in values.iter_mut().enumerate() {
+            for (index, words) in words.iter().enumerate() {
+                value[4 * index..4 * index + 4].copy_from_slice(&words[lane].to_be_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::signature::winternitz::hash_chain;
+    use crate::signature::winternitz::multi_buffer::{hash_chains, Backend, LANES};
+    use rand::prelude::*;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_step_matches_hash_chain() {
+        let mut rng = ChaCha20Rng::from_seed([0; 32]);
+        let mut values = [[0u8; 32]; LANES];
+        for value in values.iter_mut() {
+            rng.fill_bytes(value);
+        }
+        let counters = [0, 1, 2, 3, 254, 255, 256, u32::MAX];
+
+        // The counter is encoded as a 32-bit value, but `hash_chain()` only supports `u8`s
+        let expected: Vec<_> = values
+            .iter()
+            .zip(counters)
+            .take(5)
+            .map(|(value, counter)| hash_chain(*value, counter as u8, counter as u8 + 1))
+            .collect();
+
+        let mut results = Vec::new();
+        for backend in Backend::supported() {
+            let mut stepped = values;
+            backend.step(&mut stepped, &counters);
+            assert_eq!(stepped[..5], expected[..]);
+            results.push(stepped);
+        }
+        // All backends agree on the larger counters as well
+        assert!(results.windows(2).all(|pair| pair[0] == pair[1]));
+    }
+
+    #[test]
+    fn test_hash_chains_match_hash_chain() {
+        let mut rng = ChaCha20Rng::from_seed([1; 32]);
+        // Not a multiple of the number of lanes, with empty and full chains
+        let count = 3 * LANES + 5;
+        let mut inputs = vec![[0u8; 32]; count];
+        for input in inputs.iter_mut() {
+            rng.fill_bytes(input);
+        }
+        let mut starts: Vec<u8> = (0..count).map(|_| rng.gen_range(0..=15)).collect();
+        let mut ends: Vec<u8> = starts
+            .iter()
+            .map(|start| rng.gen_range(*start..=15))
+            .collect();
+        starts[0] = 0;
+        ends[0] = 255;
+        starts[1] = 7;
+        ends[1] = 7;
+
+        let expected: Vec<_> = (0..count)
+            .map(|i| hash_chain(inputs[i], starts[i], ends[i]))
+            .collect();
+        for backend in Backend::supported() {
+            assert_eq!(hash_chains(backend, &inputs, &starts, &ends), expected);
+        }
+        assert!(hash_chains(Backend::detect(), &[], &[], &[]).is_empty());
+    }
+}
@@ -1,6 +1,8 @@
+use crate::error::Error;
 use crate::signature::HashType;
-use anyhow::{bail, Result};
+use anyhow::Result;
 use data_encoding::HEXLOWER;
+use ring::constant_time::verify_slices_are_equal;
 use ring::digest::{digest, SHA256};
 use ring::hmac::Key;
 use std::cmp::min;
@@ -9,11 +11,19 @@ use std::cmp::min;
 ///
 /// # Panics
 /// Panics if the input does not have length 32.
+/// Consider using `try_slice_to_hash()`.
 pub fn slice_to_hash(input_slice: &[u8]) -> HashType {
-    assert_eq!(input_slice.len(), 32);
+    try_slice_to_hash(input_slice).unwrap_or_else(|error| panic!("{error}"))
+}
+
+/// Convert a `&[u8]` to a [u8; 32], failing if the input does not have length 32.
+pub fn try_slice_to_hash(input_slice: &[u8]) -> Result<HashType, Error> {
+    if input_slice.len() != 32 {
+        return Err(Error::InvalidHashLength(input_slice.len()));
+    }
     let mut result = [0u8; 32];
     result.copy_from_slice(input_slice);
-    result
+    Ok(result)
 }
 
 /// Hashes arbitrary bytes using SHA256
@@ -27,16 +37,32 @@ pub fn hmac(key: &HashType, data: &[u8]) -> HashType {
     slice_to_hash(ring::hmac::sign(&hmac_key, data).as_ref())
 }
 
-pub fn string_to_hash_maybe(hash_string: &str) -> Result<HashType> {
+/// Compares two byte slices in constant time.
+///
+/// The running time only depends on the lengths of the slices, not on where they differ.
+/// Use this for all comparisons involving secret or attacker-controlled data in verification,
+/// so that the verifier can't be used as a timing oracle.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    verify_slices_are_equal(a, b).is_ok()
+}
+
+/// Decodes a hex-encoded 256-bit hash.
+pub fn try_string_to_hash(hash_string: &str) -> Result<HashType, Error> {
     let decoded = HEXLOWER.decode(hash_string.as_bytes())?;
-    if decoded.len() != 32 {
-        bail!("Invalid number of bytes!");
-    }
-    Ok(slice_to_hash(&decoded))
+    try_slice_to_hash(&decoded)
+}
+
+pub fn string_to_hash_maybe(hash_string: &str) -> Result<HashType> {
+    Ok(try_string_to_hash(hash_string)?)
 }
 
+/// Decodes a hex-encoded 256-bit hash.
+///
+/// # Panics
+/// Panics if the input is not a valid hex-encoded 256-bit hash.
+/// Consider using `try_string_to_hash()`.
 pub fn string_to_hash(hash_string: &str) -> HashType {
-    string_to_hash_maybe(hash_string).expect("Could not decode")
+    try_string_to_hash(hash_string).expect("Could not decode")
 }
 
 /// Gets the `bits` least significant bits of `index`,
@@ -57,9 +83,9 @@ pub fn get_least_significant_bits(index: usize, bits: usize) -> Vec<bool> {
 pub fn bits_to_unsigned_int(bits: &[bool]) -> u8 {
     assert!(bits.len() <= 8);
     let mut result = 0;
-    for i in 0..bits.len() {
-        if bits[i] {
-            result = result | (1 << (bits.len() - 1 - i));
+    for (i, bit) in bits.iter().enumerate() {
+        if *bit {
+            result |= 1 << (bits.len() - 1 - i);
         }
     }
     result
@@ -78,11 +104,21 @@ pub fn bits_to_unsigned_ints(bits: &[bool]) -> Vec<u8> {
 
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::signature::HashType;
     use crate::utils::{
-        bits_to_unsigned_int, bits_to_unsigned_ints, get_least_significant_bits, string_to_hash,
+        bits_to_unsigned_int, bits_to_unsigned_ints, ct_eq, get_least_significant_bits,
+        string_to_hash, try_slice_to_hash, try_string_to_hash,
     };
 
+    #[test]
+    fn test_ct_eq() {
+        assert!(ct_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(ct_eq(&[], &[]));
+        assert!(!ct_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!ct_eq(&[1, 2, 3], &[1, 2]));
+    }
+
     #[test]
     fn test_get_least_significant_bits() {
         assert_eq!(
@@ -107,10 +143,7 @@ mod tests {
     }
 
     fn get_test_hash() -> (HashType, String) {
-        let mut hash = [0u8; 32];
-        for i in 0..32 {
-            hash[i] = i as u8;
-        }
+        let hash: HashType = core::array::from_fn(|i| i as u8);
         (
             hash,
             String::from("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"),
@@ -135,4 +168,23 @@ mod tests {
         let hash_string = "Right length but no valid hash!                                 ";
         string_to_hash(&String::from(hash_string));
     }
+
+    #[test]
+    fn test_try_string_to_hash_invalid() {
+        assert_eq!(try_string_to_hash("abcd"), Err(Error::InvalidHashLength(2)));
+        assert!(matches!(
+            try_string_to_hash("no valid hex"),
+            Err(Error::InvalidHex(_))
+        ));
+    }
+
+    #[test]
+    fn test_try_slice_to_hash() {
+        let (test_hash, _) = get_test_hash();
+        assert_eq!(try_slice_to_hash(&test_hash), Ok(test_hash));
+        assert_eq!(
+            try_slice_to_hash(&[0u8; 31]),
+            Err(Error::InvalidHashLength(31))
+        );
+    }
 }
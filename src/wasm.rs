@@ -1,6 +1,6 @@
+use crate::signature::key_format::VersionedPublicKey;
 use crate::signature::stateless_merkle::StatelessMerkleSignatureScheme;
-use crate::signature::SignatureScheme;
-use crate::utils::{hash, slice_to_hash, string_to_hash_maybe};
+use crate::utils::hash;
 use js_sys::Uint8Array;
 
 use wasm_bindgen::prelude::*;
@@ -13,16 +13,31 @@ extern "C" {
     fn log(s: &str);
 }
 
+/// Verifies the signature of a file.
+///
+/// The public key is a `VersionedPublicKey`, so keys of all formats are verified in their
+/// format, including keys without a version (which use the legacy format).
 #[wasm_bindgen]
 pub fn verify(file_bytes: Uint8Array, signature_bytes: Uint8Array, public_key_str: &str) -> String {
+    verify_with_context(file_bytes, signature_bytes, public_key_str, "")
+}
+
+#[wasm_bindgen]
+pub fn verify_with_context(
+    file_bytes: Uint8Array,
+    signature_bytes: Uint8Array,
+    public_key_str: &str,
+    context: &str,
+) -> String {
     let file_hash = hash(&file_bytes.to_vec());
 
-    match string_to_hash_maybe(public_key_str) {
+    match public_key_str.parse::<VersionedPublicKey>() {
         Ok(public_key) => match rmp_serde::from_slice(&signature_bytes.to_vec()) {
             Ok(signature) => {
-                if StatelessMerkleSignatureScheme::verify(
-                    public_key,
-                    slice_to_hash(file_hash.as_ref()),
+                if StatelessMerkleSignatureScheme::verify_versioned(
+                    &public_key,
+                    &file_hash,
+                    context.as_bytes(),
                     &signature,
                 ) {
                     "valid".into()
@@ -0,0 +1,113 @@
+//! Dudect-style timing tests, see "Dude, is my code constant time?" by Reparaz, Balasch & Verbauwhede.
+//!
+//! Each test times an operation on inputs of two classes, interleaved in random order,
+//! and uses Welch's t-test to check whether the timing distributions differ.
+//! Here, the classes are forgeries that differ from the expected value at the first
+//! and at the last byte, respectively.
+//!
+//! Timing measurements depend on the machine and its load, so the tests are ignored by default
+//! to keep CI deterministic. Run them on an otherwise idle machine using
+//! `cargo test --release --test constant_time -- --ignored`.
+
+use hash_based_signatures::signature::winternitz::d::D;
+use hash_based_signatures::signature::winternitz::WinternitzSignatureScheme;
+use hash_based_signatures::signature::{Signer, Verifier};
+use hash_based_signatures::utils::ct_eq;
+use rand::prelude::*;
+use rand_chacha::ChaCha20Rng;
+use std::hint::black_box;
+use std::time::Instant;
+
+/// Dudect considers |t| > 10 as a definite timing leak.
+const T_THRESHOLD: f64 = 10.0;
+
+/// Only the fastest measurements are used, because outliers (e.g. due to interrupts
+/// or context switches) dominate the variance.
+const CROP_PERCENTILE: f64 = 0.9;
+
+/// Times `f` on inputs of both classes, returning the measurements per class.
+fn measure<T, F: Fn(&T) -> bool>(inputs: [T; 2], f: F, samples: usize) -> [Vec<f64>; 2] {
+    let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+    let mut measurements = [Vec::with_capacity(samples), Vec::with_capacity(samples)];
+    for _ in 0..samples {
+        let class = rng.gen_range(0..2);
+        let start = Instant::now();
+        black_box(f(black_box(&inputs[class])));
+        measurements[class].push(start.elapsed().as_nanos() as f64);
+    }
+    measurements
+}
+
+/// Drops all measurements above the given percentile of all measurements.
+fn crop(measurements: [Vec<f64>; 2], percentile: f64) -> [Vec<f64>; 2] {
+    let mut all: Vec<f64> = measurements.concat();
+    all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let threshold = all[((all.len() - 1) as f64 * percentile) as usize];
+    measurements.map(|class| class.into_iter().filter(|t| *t <= threshold).collect())
+}
+
+/// Welch's t-statistic of two samples.
+fn t_statistic(measurements: &[Vec<f64>; 2]) -> f64 {
+    let [(mean0, var0, n0), (mean1, var1, n1)] = [&measurements[0], &measurements[1]].map(|x| {
+        let n = x.len() as f64;
+        let mean = x.iter().sum::<f64>() / n;
+        let var = x.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        (mean, var, n)
+    });
+    (mean0 - mean1) / (var0 / n0 + var1 / n1).sqrt()
+}
+
+fn assert_constant_time<T, F: Fn(&T) -> bool>(inputs: [T; 2], f: F, samples: usize) {
+    // Warm up caches and branch predictors
+    for _ in 0..samples / 10 {
+        black_box(f(&inputs[0]));
+        black_box(f(&inputs[1]));
+    }
+
+    let t = t_statistic(&crop(measure(inputs, f, samples), CROP_PERCENTILE));
+    assert!(
+        t.abs() < T_THRESHOLD,
+        "Timing depends on the input class (t = {t:.2})"
+    );
+}
+
+#[test]
+#[ignore = "timing-sensitive, run with --ignored on an idle machine"]
+fn ct_eq_does_not_leak_first_difference() {
+    let expected = vec![42u8; 4096];
+    let mut differs_at_start = expected.clone();
+    differs_at_start[0] ^= 1;
+    let mut differs_at_end = expected.clone();
+    differs_at_end[4095] ^= 1;
+
+    assert_constant_time(
+        [differs_at_start, differs_at_end],
+        |forgery| ct_eq(&expected, forgery),
+        100_000,
+    );
+}
+
+#[test]
+#[ignore = "timing-sensitive, run with --ignored on an idle machine"]
+fn winternitz_verify_does_not_leak_first_difference() {
+    let mut signature_scheme = WinternitzSignatureScheme::new([0u8; 32], D::new(15));
+    let message = [1u8; 32];
+    let signature = signature_scheme.sign(message);
+
+    // Instead of forging signatures, compare against forged public keys, so that both classes
+    // need the same amount of hashing.
+    let public_key = signature_scheme.public_key();
+    let mut differs_at_start = public_key.clone();
+    differs_at_start[0][0] ^= 1;
+    let mut differs_at_end = public_key.clone();
+    let last = differs_at_end.len() - 1;
+    differs_at_end[last][31] ^= 1;
+
+    assert_constant_time(
+        [differs_at_start, differs_at_end],
+        |forged_public_key| {
+            WinternitzSignatureScheme::verify(forged_public_key, &message, &signature)
+        },
+        2_000,
+    );
+}
@@ -1,16 +1,36 @@
 use hash_based_signatures::cli::verify;
-use hash_based_signatures::utils::string_to_hash;
 use std::path::PathBuf;
 
-#[test]
-fn example_verifies() {
-    let verifies = verify(
+const EXAMPLE_PUBLIC_KEY: &str = "9e2543961faafa9a021752ad7598170472e688988ad1fa66a33dc65945385194";
+
+fn verify_example(public_key: &str, context: &[u8], randomized: bool) -> bool {
+    verify(
         PathBuf::from("example/readme.md"),
         PathBuf::from("example/readme.md.signature"),
-        string_to_hash(&String::from(
-            "9e2543961faafa9a021752ad7598170472e688988ad1fa66a33dc65945385194",
-        )),
+        public_key.parse().unwrap(),
+        context,
+        randomized,
     )
-    .unwrap();
-    assert!(verifies)
+    .unwrap()
+}
+
+#[test]
+fn example_verifies() {
+    assert!(verify_example(EXAMPLE_PUBLIC_KEY, b"", false))
+}
+
+#[test]
+fn example_does_not_verify_with_context() {
+    assert!(!verify_example(EXAMPLE_PUBLIC_KEY, b"releases", false))
+}
+
+#[test]
+fn example_does_not_verify_as_randomized() {
+    assert!(!verify_example(EXAMPLE_PUBLIC_KEY, b"", true))
+}
+
+#[test]
+fn example_does_not_verify_in_another_format() {
+    let public_key = format!("v1:{EXAMPLE_PUBLIC_KEY}");
+    assert!(!verify_example(&public_key, b"", false))
 }